@@ -0,0 +1,3 @@
+(load "2.scm")
+(define (repeat k) (if (= k 1) (knapsack 50 3 w p) (begin (knapsack 50 3 w p) (repeat (- k 1)))))
+(repeat 1000)
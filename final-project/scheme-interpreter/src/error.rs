@@ -0,0 +1,169 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+#[derive( Clone, PartialEq, Debug )]
+pub enum SchemeError {
+    ArityMismatch       { procedure: String, expected: String, given: String },
+    NotAProcedure       { given: String },
+    ContractViolation   { procedure: String, expected: String, given: String },
+    DivisionByZero      { procedure: String },
+    /// The exact result would be too large to hold
+    OutOfMemory         { procedure: String },
+    UndefinedFor        { procedure: String, given: String },
+    IndexOutOfRange     { procedure: String, index: String, range: Option< ( usize, usize ) >, kind: String, given: String },
+    /// Walking `index` pairs down `list` ran out of pairs
+    BadListIndex        { procedure: String, reason: String, index: String, list: String },
+    Undefined           { identifier: String },
+    CannotSet           { identifier: String },
+    MissingProcedure,
+    BadSyntax           { form: String, expected: String, given: String },
+    BadContext          { form: String, reason: String },
+    ReadSyntax          { message: String },
+    UnknownToken,
+    CannotOpenFile      { file: String, reason: String },
+    LoadCycle           { file: String },
+    InFile              { file: String, error: Box< SchemeError > },
+    /// Raised by `error`, the message already includes the irritants
+    Failure             { message: String },
+    /// A raised value that is not an error object and that no handler caught
+    Uncaught            { value: String },
+}
+
+
+impl SchemeError {
+
+    pub fn arity_mismatch( procedure: &str, expected: impl ToString, given: impl ToString ) -> SchemeError {
+        SchemeError::ArityMismatch { procedure: procedure.to_string(), expected: expected.to_string(), given: given.to_string() }
+    }
+
+    pub fn not_a_procedure( given: impl ToString ) -> SchemeError {
+        SchemeError::NotAProcedure { given: given.to_string() }
+    }
+
+    pub fn contract_violation( procedure: &str, expected: &str, given: impl ToString ) -> SchemeError {
+        SchemeError::ContractViolation { procedure: procedure.to_string(), expected: expected.to_string(), given: given.to_string() }
+    }
+
+    /// `range` is the inclusive range of valid indices, or `None` if the target is empty
+    pub fn index_out_of_range( procedure: &str, index: impl ToString, range: Option< ( usize, usize ) >, kind: &str, given: impl ToString ) -> SchemeError {
+        SchemeError::IndexOutOfRange { procedure: procedure.to_string(), index: index.to_string(), range, kind: kind.to_string(), given: given.to_string() }
+    }
+
+    pub fn bad_list_index( procedure: &str, reason: &str, index: impl ToString, list: impl ToString ) -> SchemeError {
+        SchemeError::BadListIndex { procedure: procedure.to_string(), reason: reason.to_string(), index: index.to_string(), list: list.to_string() }
+    }
+
+    pub fn undefined( identifier: &str ) -> SchemeError {
+        SchemeError::Undefined { identifier: identifier.to_string() }
+    }
+
+    pub fn bad_syntax( form: &str, expected: &str, given: impl ToString ) -> SchemeError {
+        SchemeError::BadSyntax { form: form.to_string(), expected: expected.to_string(), given: given.to_string() }
+    }
+
+    pub fn bad_context( form: &str, reason: &str ) -> SchemeError {
+        SchemeError::BadContext { form: form.to_string(), reason: reason.to_string() }
+    }
+
+    pub fn read_syntax( message: &str ) -> SchemeError {
+        SchemeError::ReadSyntax { message: message.to_string() }
+    }
+
+    pub fn cannot_open_file( file: &Path, err: io::Error ) -> SchemeError {
+        SchemeError::CannotOpenFile { file: file.display().to_string(), reason: err.to_string() }
+    }
+
+}
+
+
+/// Formats the error the way Racket reports it
+impl fmt::Display for SchemeError {
+
+    fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+        match self {
+            SchemeError::ArityMismatch { procedure, expected, given } => {
+                write!( f, "{}: arity mismatch;\n the expected number of arguments does not match the given number", procedure )?;
+                write_expected_given( f, expected, given )
+            },
+            SchemeError::NotAProcedure { given } => {
+                write!( f, "application: not a procedure;\n expected a procedure that can be applied to arguments" )?;
+                write_expected_given( f, "", given )
+            },
+            SchemeError::ContractViolation { procedure, expected, given } => {
+                write!( f, "{}: contract violation;", procedure )?;
+                write_expected_given( f, expected, given )
+            },
+            SchemeError::DivisionByZero { procedure } => {
+                write!( f, "{}: division by zero", procedure )
+            },
+            SchemeError::OutOfMemory { procedure } => {
+                write!( f, "{}: out of memory", procedure )
+            },
+            SchemeError::UndefinedFor { procedure, given } => {
+                write!( f, "{}: undefined for {}", procedure, given )
+            },
+            SchemeError::IndexOutOfRange { procedure, index, range, kind, given } => {
+                match range {
+                    Some( ( start, end ) )  => write!( f, "{}: index is out of range\n  index: {}\n  valid range: [{}, {}]", procedure, index, start, end )?,
+                    None                    => write!( f, "{}: index is out of range for empty {}\n  index: {}", procedure, kind, index )?,
+                }
+                write!( f, "\n  {}: {}", kind, given )
+            },
+            SchemeError::BadListIndex { procedure, reason, index, list } => {
+                write!( f, "{}: {}\n  index: {}\n  in: {}", procedure, reason, index, list )
+            },
+            SchemeError::Undefined { identifier } => {
+                write!( f, "{}: undefined;\n cannot reference an identifier before its definition", identifier )
+            },
+            SchemeError::CannotSet { identifier } => {
+                write!( f, "set!: assignment disallowed;\n cannot set variable before its definition\n  variable: {}", identifier )
+            },
+            SchemeError::MissingProcedure => {
+                write!( f, "#%app: missing procedure expression;\n probably originally (), which is an illegal empty application in: (#%app)" )
+            },
+            SchemeError::BadSyntax { form, expected, given } => {
+                write!( f, "{}: bad syntax;", form )?;
+                write_expected_given( f, expected, given )
+            },
+            SchemeError::BadContext { form, reason } => {
+                write!( f, "{}: {}", form, reason )
+            },
+            SchemeError::ReadSyntax { message } => {
+                write!( f, "read-syntax: {}", message )
+            },
+            SchemeError::UnknownToken => {
+                write!( f, "an unknown error occured while parsing..." )
+            },
+            SchemeError::CannotOpenFile { file, reason } => {
+                write!( f, "open-input-file: cannot open input file\n  file: `{}`\n  system error: {}", file, reason )
+            },
+            SchemeError::LoadCycle { file } => {
+                write!( f, "load: cycle detected;\n the file is already being loaded\n  file: `{}`", file )
+            },
+            SchemeError::InFile { file, error } => {
+                write!( f, "{}\n  in file: `{}`", error, file )
+            },
+            SchemeError::Failure { message } => {
+                write!( f, "{}", message )
+            },
+            SchemeError::Uncaught { value } => {
+                write!( f, "uncaught exception: {}", value )
+            },
+        }
+    }
+
+}
+
+
+fn write_expected_given( f: &mut fmt::Formatter<'_>, expected: &str, given: &str ) -> fmt::Result {
+    if !expected.is_empty() {
+        write!( f, "\n  expected: {}", expected )?;
+    }
+
+    if !given.is_empty() {
+        write!( f, "\n  given: {}", given )?;
+    }
+
+    Ok( () )
+}
@@ -1,47 +1,120 @@
 use std::fmt;
-use std::collections::HashMap;
+use std::collections::{ HashMap, HashSet };
 use std::cmp::Ordering;
+use std::cell::{ Cell, RefCell };
+use std::rc::{ Rc, Weak };
+use std::mem;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::PathBuf;
+use crate::error::SchemeError;
+use crate::parser::Parser;
+use crate::parser::is_plain_identifier;
+use crate::parser::bar_symbol;
+use crate::number::Number;
+use crate::number::Rounding;
+use crate::symbol::Symbol;
+use crate::symbol::BuildSymbolHasher;
+use crate::macros::Macro;
+
+
+pub struct Environment {
+    env_data    : RefCell< HashMap< Symbol, Data, BuildSymbolHasher > >,
+    parent_env  : Option< Rc< Environment > >,
+    /// Whether the cycle collector knows about this environment
+    is_tracked  : Cell< bool >,
+}
+
 
-#[derive( Clone, PartialEq, Debug )]
-pub enum DataType {
-    Invalid,
-    Integer,
-    Real,
-    Variable,
-    List,
-    Procedure,
-    Symbol,
-    Lambda,
+thread_local! {
+    /// The environments that closures were created in. A closure bound in its own
+    /// environment, or in one of its ancestors, keeps it alive through an `Rc`
+    /// cycle, so these are checked for cycles once enough of them pile up
+    static TRACKED_ENVS: RefCell< TrackedEnvs > = const { RefCell::new( TrackedEnvs { envs: vec![], threshold: MIN_COLLECT_THRESHOLD } ) };
 }
 
 
-pub struct Environment<'a> {
-    env_data    : HashMap< String, Data >,
-    parent_env  : Option< &'a Environment<'a> >
+/// The fewest tracked environments that start a collection
+const MIN_COLLECT_THRESHOLD: usize = 10_000;
+
+
+struct TrackedEnvs {
+    envs        : Vec< Weak< Environment > >,
+    /// The number of tracked environments that starts the next collection
+    threshold   : usize,
 }
 
 
 type ListValuesArr      = Vec< Data >;
 type ProcedureArgsArr   = ListValuesArr;
-type Procedure          = fn( &ProcedureArgsArr ) -> Data;
+type Procedure          = fn( &ProcedureArgsArr ) -> Result< Data, SchemeError >;
 
+
+/// A Scheme value. Code is made of the same values it is read as, so `(f x)`
+/// is a list of two symbols and `'x` is the list `(quote x)`
 #[derive( Clone )]
-pub struct Data {
-    pub list        : ListValuesArr,
-    pub string      : String,
-    pub procedure   : Procedure,
-    pub data_type   : DataType,
-    pub quote_level : u16,
+pub enum Data {
+    Void,
+    Bool( bool ),
+    Number( Number ),
+    Char( char ),
+    String( Rc< str > ),
+    Symbol( Symbol ),
+    Null,
+    Pair( Rc< Pair > ),
+    Procedure( &'static str, Procedure ),
+    Lambda( Rc< Lambda > ),
+    Macro( Rc< Macro > ),
+    Control( &'static str, ControlProcedure ),
+    Continuation( Continuation ),
+    ErrorObject( Rc< ErrorObject > ),
+    /// The results of `values` when there aren't exactly one of them
+    Values( Rc< [ Data ] > ),
+}
+
+
+/// A cons cell. A list is a chain of pairs whose last cdr is '(), and pairs are
+/// shared rather than copied, so `set-car!` and `set-cdr!` are seen by every holder
+pub struct Pair {
+    car : RefCell< Data >,
+    cdr : RefCell< Data >,
+}
+
+
+/// A procedure written in Scheme, together with the environment it was created in.
+/// A `case-lambda` has a clause for each number of arguments it accepts
+pub struct Lambda {
+    name        : Option< Symbol >,
+    clauses     : Vec< LambdaClause >,
+    env         : Rc< Environment >,
+    /// The loop of a named let binds its name in each call frame. Binding it in the
+    /// environment it closes over would make a reference cycle every time the let is entered
+    binds_name  : bool,
+}
+
+
+/// The parameters and the body of a procedure. The arguments after the
+/// parameters are passed as a list in `rest`, if there is one
+struct LambdaClause {
+    params  : Vec< Symbol >,
+    rest    : Option< Symbol >,
+    body    : Data,
+}
+
+
+/// The condition raised by `error`, and by built-in procedures when they fail
+pub struct ErrorObject {
+    message     : Data,
+    irritants   : Data,
+    /// What is reported if no handler catches the condition
+    error       : SchemeError,
 }
 
 
-impl<'a> Environment<'a> {
+impl Environment {
 
-    pub fn new() -> Environment<'a> {
-        let mut res = Environment { env_data: HashMap::new(), parent_env: None };
-        res.env_data.insert(    "#f".to_string()    , new_false_sym()       );
-        res.env_data.insert(    "#t".to_string()    , new_true_sym()        );
-        res.env_data.insert(    "'()".to_string()   , NULL_SYM              );
+    pub fn new() -> Rc< Environment > {
+        let res = Environment { env_data: RefCell::new( HashMap::default() ), parent_env: None, is_tracked: Cell::new( false ) };
         res.add_procedure(      "+"                 , proc_add              );
         res.add_procedure(      "-"                 , proc_subtract         );
         res.add_procedure(      "*"                 , proc_multiply         );
@@ -54,6 +127,34 @@ impl<'a> Environment<'a> {
         res.add_procedure(      "cons"              , proc_cons             );
         res.add_procedure(      "car"               , proc_car              );
         res.add_procedure(      "cdr"               , proc_cdr              );
+        res.add_procedure(      "caar"              , proc_caar             );
+        res.add_procedure(      "cadr"              , proc_cadr             );
+        res.add_procedure(      "cdar"              , proc_cdar             );
+        res.add_procedure(      "cddr"              , proc_cddr             );
+        res.add_procedure(      "caaar"             , proc_caaar            );
+        res.add_procedure(      "caadr"             , proc_caadr            );
+        res.add_procedure(      "cadar"             , proc_cadar            );
+        res.add_procedure(      "caddr"             , proc_caddr            );
+        res.add_procedure(      "cdaar"             , proc_cdaar            );
+        res.add_procedure(      "cdadr"             , proc_cdadr            );
+        res.add_procedure(      "cddar"             , proc_cddar            );
+        res.add_procedure(      "cdddr"             , proc_cdddr            );
+        res.add_procedure(      "length"            , proc_length           );
+        res.add_procedure(      "append"            , proc_append           );
+        res.add_procedure(      "reverse"           , proc_reverse          );
+        res.add_procedure(      "list-ref"          , proc_list_ref         );
+        res.add_procedure(      "list-tail"         , proc_list_tail        );
+        res.add_procedure(      "last-pair"         , proc_last_pair        );
+        res.add_procedure(      "list-copy"         , proc_list_copy        );
+        res.add_procedure(      "memq"              , proc_memq             );
+        res.add_procedure(      "memv"              , proc_memv             );
+        res.add_procedure(      "assq"              , proc_assq             );
+        res.add_procedure(      "assv"              , proc_assv             );
+        res.add_procedure(      "iota"              , proc_iota             );
+        res.add_procedure(      "delete"            , proc_delete           );
+        res.add_procedure(      "remove"            , proc_remove           );
+        res.add_procedure(      "set-car!"          , proc_set_car          );
+        res.add_procedure(      "set-cdr!"          , proc_set_cdr          );
         res.add_procedure(      "number?"           , proc_is_number        );
         res.add_procedure(      "integer?"          , proc_is_integer       );
         res.add_procedure(      "real?"             , proc_is_real          );
@@ -62,1252 +163,4055 @@ impl<'a> Environment<'a> {
         res.add_procedure(      "<="                , proc_less_or_equal    );
         res.add_procedure(      ">"                 , proc_greater          );
         res.add_procedure(      ">="                , proc_greater_or_equal );
-        res.add_procedure(      "and"               , proc_and              );
-        res.add_procedure(      "or"                , proc_or               );
         res.add_procedure(      "remainder"         , proc_remainder        );
         res.add_procedure(      "quotient"          , proc_quotient         );
         res.add_procedure(      "expt"              , proc_expt             );
+        res.add_procedure(      "modulo"            , proc_modulo           );
         res.add_procedure(      "max"               , proc_max              );
+        res.add_procedure(      "min"               , proc_min              );
+        res.add_procedure(      "abs"               , proc_abs              );
+        res.add_procedure(      "gcd"               , proc_gcd              );
+        res.add_procedure(      "lcm"               , proc_lcm              );
+        res.add_procedure(      "floor"             , proc_floor            );
+        res.add_procedure(      "ceiling"           , proc_ceiling          );
+        res.add_procedure(      "round"             , proc_round            );
+        res.add_procedure(      "truncate"          , proc_truncate         );
+        res.add_procedure(      "sqrt"              , proc_sqrt             );
+        res.add_procedure(      "exact-integer-sqrt", proc_exact_integer_sqrt );
+        res.add_procedure(      "exp"               , proc_exp              );
+        res.add_procedure(      "log"               , proc_log              );
+        res.add_procedure(      "sin"               , proc_sin              );
+        res.add_procedure(      "cos"               , proc_cos              );
+        res.add_procedure(      "tan"               , proc_tan              );
+        res.add_procedure(      "atan"              , proc_atan             );
+        res.add_procedure(      "zero?"             , proc_is_zero          );
+        res.add_procedure(      "positive?"         , proc_is_positive      );
+        res.add_procedure(      "negative?"         , proc_is_negative      );
+        res.add_procedure(      "odd?"              , proc_is_odd           );
+        res.add_procedure(      "even?"             , proc_is_even          );
+        res.add_procedure(      "numerator"         , proc_numerator        );
+        res.add_procedure(      "denominator"       , proc_denominator      );
+        res.add_procedure(      "exact->inexact"    , proc_exact_to_inexact );
+        res.add_procedure(      "inexact->exact"    , proc_inexact_to_exact );
+        res.add_procedure(      "string-length"     , proc_string_length    );
+        res.add_procedure(      "string-ref"        , proc_string_ref       );
+        res.add_procedure(      "substring"         , proc_substring        );
+        res.add_procedure(      "string-append"     , proc_string_append    );
+        res.add_procedure(      "string=?"          , proc_string_equals    );
+        res.add_procedure(      "string<?"          , proc_string_less      );
+        res.add_procedure(      "string<=?"         , proc_string_less_eq   );
+        res.add_procedure(      "string>?"          , proc_string_greater   );
+        res.add_procedure(      "string>=?"         , proc_string_greater_eq );
+        res.add_procedure(      "string-upcase"     , proc_string_upcase    );
+        res.add_procedure(      "string-downcase"   , proc_string_downcase  );
+        res.add_procedure(      "string->list"      , proc_string_to_list   );
+        res.add_procedure(      "list->string"      , proc_list_to_string   );
+        res.add_procedure(      "string->number"    , proc_string_to_number );
+        res.add_procedure(      "number->string"    , proc_number_to_string );
+        res.add_procedure(      "string->symbol"    , proc_string_to_symbol );
+        res.add_procedure(      "symbol->string"    , proc_symbol_to_string );
+        res.add_procedure(      "string-split"      , proc_string_split     );
+        res.add_procedure(      "string-join"       , proc_string_join      );
+        res.add_procedure(      "string-index"      , proc_string_index     );
+        res.add_procedure(      "char?"             , proc_is_char          );
+        res.add_procedure(      "char->integer"     , proc_char_to_integer  );
+        res.add_procedure(      "integer->char"     , proc_integer_to_char  );
+        res.add_procedure(      "char-upcase"       , proc_char_upcase      );
+        res.add_procedure(      "char-downcase"     , proc_char_downcase    );
+        res.add_procedure(      "char-alphabetic?"  , proc_is_char_alphabetic );
+        res.add_procedure(      "char-numeric?"     , proc_is_char_numeric  );
+        res.add_procedure(      "char-whitespace?"  , proc_is_char_whitespace );
+        res.add_procedure(      "char-upper-case?"  , proc_is_char_upper_case );
+        res.add_procedure(      "char-lower-case?"  , proc_is_char_lower_case );
+        res.add_procedure(      "char=?"            , proc_char_equals      );
+        res.add_procedure(      "char<?"            , proc_char_less        );
+        res.add_procedure(      "char<=?"           , proc_char_less_eq     );
+        res.add_procedure(      "char>?"            , proc_char_greater     );
+        res.add_procedure(      "char>=?"           , proc_char_greater_eq  );
         res.add_procedure(      "display"           , proc_display          );
-
-        res
+        res.add_procedure(      "write"             , proc_write            );
+        res.add_control(        "apply"             , proc_apply            );
+        res.add_control(        "map"               , proc_map              );
+        res.add_control(        "for-each"          , proc_for_each         );
+        res.add_control(        "filter"            , proc_filter           );
+        res.add_control(        "reduce"            , proc_reduce           );
+        res.add_control(        "fold-left"         , proc_fold_left        );
+        res.add_control(        "fold-right"        , proc_fold_right       );
+        res.add_control(        "member"            , proc_member           );
+        res.add_control(        "assoc"             , proc_assoc            );
+        res.add_control(        "call/cc"           , proc_call_cc          );
+        res.add_control(        "call-with-current-continuation", proc_call_cc );
+        res.add_control(        "dynamic-wind"      , proc_dynamic_wind     );
+        res.add_procedure(      "values"            , proc_values           );
+        res.add_control(        "call-with-values"  , proc_call_with_values );
+        res.add_control(        "error"             , proc_error            );
+        res.add_control(        "raise"             , proc_raise            );
+        res.add_control(        "raise-continuable" , proc_raise_continuable );
+        res.add_control(        "with-exception-handler", proc_with_exception_handler );
+        res.add_procedure(      "error-object?"     , proc_is_error_object  );
+        res.add_procedure(      "error-object-message", proc_error_object_message );
+        res.add_procedure(      "error-object-irritants", proc_error_object_irritants );
+        res.add_procedure(      "collect-garbage"   , proc_collect_garbage  );
+
+        Rc::new( res )
     }
 
-    pub fn with_args( params: &ProcedureArgsArr, args: &ProcedureArgsArr, parent: &'a Environment ) -> Environment<'a> {
-        let mut res = Environment { env_data: HashMap::new(), parent_env: Some( parent ) };
+    pub fn with_args( params: &[ Symbol ], args: ProcedureArgsArr, parent: &Rc< Environment > ) -> Rc< Environment > {
+        let env_data    = HashMap::with_capacity_and_hasher( params.len(), BuildSymbolHasher::default() );
+        let res         = Environment { env_data: RefCell::new( env_data ), parent_env: Some( Rc::clone( parent ) ), is_tracked: Cell::new( false ) };
 
-        for i in 0..params.len() {
-            res.env_data.insert( params[ i ].string.clone(), args[ i ].clone() );
+        for ( param, arg ) in params.iter().zip( args ) {
+            res.insert( param, arg );
         }
 
-        res
+        Rc::new( res )
     }
 
-    pub fn eval( &mut self, data: &Data ) -> Data {
-        match data.data_type {
-            DataType::Variable => {
-                let res = self.find( data.string.as_str() );
-                if let Some( x ) = res {
-                    x.clone()
-                }
-                else {
-                    print_error( Error::Undefined, data.string.as_str(), "", "");
-                    INVALID_DATA
-                }
-            },
-            DataType::Symbol => {
-                if is_null_sym( data ) {
-                    NULL_SYM
-                }
-                else {
-                    data.clone()
-                }
+    pub fn eval( self: &Rc< Self >, data: &Data ) -> Result< Data, SchemeError > {
+        run( Step::Eval( data.clone(), Rc::clone( self ) ), Cont::default() )
+    }
+
+
+    fn eval_step( self: &Rc< Self >, data: &Data, k: &mut Cont ) -> Result< Step, SchemeError > {
+        match data {
+            Data::Symbol( sym ) => {
+                Ok( Step::Return( self.lookup( sym )? ) )
             },
-            DataType::Integer | DataType::Real => {
-                data.clone()
+            Data::Null => {
+                Err( SchemeError::MissingProcedure )
             },
-            DataType::List => {
-                if data.list.len() == 1 {
-                    assert!( !is_null_sym( &data.list[ 0 ] ) );
+            Data::Pair( pair ) => {
+                if !is_proper_form( data ) {
+                    return Err( SchemeError::bad_syntax( "#%app", "", data.printed( PrintMode::Write ) ) );
                 }
 
-                if data.list.is_empty() {
-                    print_error( Error::MissingProcedure, "#%app", "", "");
-                    return INVALID_DATA;
-                }
-
-                if is_of_type( &DataType::Variable, &data.list[ 0 ] ) {
-                    let var : &str = data.list[ 0 ].string.as_str();
+                let head = pair.car();
+                let sym = match &head {
+                    Data::Symbol( sym ) => sym,
+                    _                   => return eval_args( vec![], data.clone(), Rc::clone( self ), ArgsThen::Apply, k ),
+                };
 
-                    // TODO: quote, begin
-
-                    match var {
-                        "define"    => self.eval_define( data ),
-                        "lambda"    => self.eval_lambda( data ),
-                        "if"        => self.eval_if( data ),
-                        "cond"      => self.eval_cond( data ),
-                        "apply"     => self.eval_apply( data ),
-                        "map"       => self.eval_map( data ),
-                        _           => self.eval_proc_lambda( data )
-                    }
+                if let Some( eval_form ) = special_form( sym.as_str() ) {
+                    return eval_form( self, &list_elements( data ), k );
                 }
-                else {
-                    self.eval_proc_lambda( data )
+
+                // A macro use is expanded and the expansion is evaluated in its place
+                match self.find( sym ) {
+                    Some( Data::Macro( mac ) )              => Ok( Step::Eval( mac.expand( data )?, Rc::clone( self ) ) ),
+                    Some( proc ) if is_procedure( &proc )   => eval_args( vec![ proc ], pair.cdr(), Rc::clone( self ), ArgsThen::Apply, k ),
+                    Some( _ )                               => Err( SchemeError::not_a_procedure( sym ) ),
+                    None                                    => Err( SchemeError::undefined( sym.as_str() ) ),
                 }
             },
-            DataType::Procedure | DataType::Lambda => {
-                self.eval_proc_lambda( data )
+            _ => {
+                Ok( Step::Return( data.clone() ) )
             },
-            DataType::Invalid => {
-                INVALID_DATA
-            }
         }
     }
 
-    fn find( &self, variable: &str ) -> Option< &Data > {
-        if !self.env_data.contains_key( variable ) {
-            if self.parent_env.is_some() {
-                self.parent_env.unwrap().find( variable )
-            }
-            else {
-                None
-            }
-        }
-        else {
-            self.env_data.get( variable )
+
+    /// Evaluates `expr` and hands its value to `frame`. Constants and variables are
+    /// passed on right away instead of going through the evaluation loop
+    fn eval_into( self: &Rc< Self >, expr: &Data, frame: FrameKind, k: &mut Cont ) -> Result< Step, SchemeError > {
+        match self.atom_value( expr )? {
+            Some( value ) => {
+                frame.resume( value, k )
+            },
+            None => {
+                k.push( frame );
+                Ok( Step::Eval( expr.clone(), Rc::clone( self ) ) )
+            },
         }
     }
 
 
-    fn eval_define( &mut self, data: &Data ) -> Data {
-        let list_len = data.list.len();
-
-        if list_len < 3 {
-            print_error( Error::BadSyntax, "define", "at least 2 arguments needed", (list_len - 1).to_string().as_str() );
-            return INVALID_DATA;
+    /// The value of an expression that needs no evaluation step, or `None` if it is a form
+    fn atom_value( &self, expr: &Data ) -> Result< Option< Data >, SchemeError > {
+        match expr {
+            Data::Symbol( sym ) => self.lookup( sym ).map( Some ),
+            Data::Pair( pair )  => self.primitive_call_value( pair ),
+            Data::Null          => Ok( None ),
+            _                   => Ok( Some( expr.clone() ) ),
         }
+    }
+
 
-        if list_len > 3 && !is_of_type( &DataType::List, &data.list[ 1 ] ) {
-            if list_len > 3 {
-                print_error( Error::BadSyntax, "define", "exactly one expression after identifier", "");
-                return INVALID_DATA;
+    /// Calls a built-in procedure right away if its arguments are all constants or
+    /// variables, like `(- n 1)`. Such a call can't use the continuation, so it
+    /// doesn't need the frames and steps of the evaluation loop
+    fn primitive_call_value( &self, form: &Pair ) -> Result< Option< Data >, SchemeError > {
+        let func = match form.car() {
+            Data::Symbol( sym ) if special_form( sym.as_str() ).is_none() => match self.find( &sym ) {
+                Some( Data::Procedure( _, func ) )  => func,
+                _                                   => return Ok( None ),
+            },
+            _ => return Ok( None ),
+        };
+
+        let mut args = ProcedureArgsArr::new();
+        let mut rest = form.cdr();
+        loop {
+            match rest {
+                Data::Pair( pair ) => {
+                    match pair.car() {
+                        Data::Pair( _ ) | Data::Null    => return Ok( None ),
+                        Data::Symbol( sym )             => args.push( self.lookup( &sym )? ),
+                        arg                             => args.push( arg ),
+                    }
+                    rest = pair.cdr();
+                },
+                Data::Null  => return func( &args ).map( Some ),
+                _           => return Ok( None ),
             }
         }
+    }
 
-        let mut res = Data::new();
-        let identifier: &str;
 
-        // Procedure definition
-        let first_arg   = &data.list[ 1 ];
-        if is_of_type( &DataType::List, &first_arg ) {
-            // Named function definition
-            identifier      = first_arg.list[ 0 ].string.as_str();
-            res.data_type   = DataType::Lambda;
-            res.string      = identifier.to_string();
-            res.list.push( Data::from_string( DataType::Variable, "lambda".to_string() ) );
-            res.list.push( Data::new_list() );
+    /// The value of a variable reference. Macros can only be used as the head of a form
+    fn lookup( &self, sym: &Symbol ) -> Result< Data, SchemeError > {
+        match self.find( sym ) {
+            Some( Data::Macro( _ ) )    => Err( SchemeError::bad_syntax( sym.as_str(), "", "" ) ),
+            Some( x )                   => Ok( x ),
+            None                        => Err( SchemeError::undefined( sym.as_str() ) ),
+        }
+    }
 
-            for i in 1..first_arg.list.len() {
-                res.list[ 1 ].list.push( first_arg.list[ i ].clone() );
-            }
+    /// Looks up a variable. An identifier renamed by a macro refers to the variable it
+    /// was named after where the macro was defined, unless the expansion itself bound it
+    fn find( &self, variable: &Symbol ) -> Option< Data > {
+        self.find_exact( variable ).or_else( || {
+            let ( original, env ) = variable.alias()?;
+            env.find( &original )
+        } )
+    }
 
-            for i in 2..data.list.len() {
-                res.list.push( data.list[ i ].clone() );
-            }
+
+    fn find_exact( &self, variable: &Symbol ) -> Option< Data > {
+        if let Some( x ) = self.env_data.borrow().get( variable ) {
+            return Some( x.clone() );
         }
-        else {
-            identifier  = data.list[ 1 ].string.as_str();
-            res         = self.eval( &data.list[ 2 ] );
+
+        match &self.parent_env {
+            Some( parent )  => parent.find_exact( variable ),
+            None            => None,
         }
+    }
 
-        let res_clone = res.clone();
-        self.env_data.insert( identifier.to_string(), res );
 
-        res_clone
+    fn insert( &self, variable: &Symbol, data: Data ) {
+        self.env_data.borrow_mut().insert( variable.clone(), data );
     }
 
 
-    fn eval_lambda( &self, data: &Data ) -> Data {
-        let mut res         = Data::new();
-        res.data_type   = DataType::Lambda;
-        res.list        = data.list.clone();
-        res
-    }
+    /// Updates the nearest existing binding of `variable`, which is looked up like in `find`
+    fn set( &self, variable: &Symbol, data: Data ) -> Result< (), SchemeError > {
+        let data = match self.set_exact( variable, data ) {
+            Ok( () )    => return Ok( () ),
+            Err( data ) => data,
+        };
 
-    
-    fn eval_if( &mut self, data: &Data ) -> Data {
-        let list_len = data.list.len();
-        if list_len < 3 || list_len > 4 {
-            print_error( Error::ArityMismatch, "if", "2 or 3", (list_len - 1).to_string().as_str() );
-            return INVALID_DATA;
+        match variable.alias() {
+            Some( ( original, env ) )   => env.set( &original, data ),
+            None                        => Err( SchemeError::CannotSet { identifier: variable.to_string() } ),
         }
+    }
 
-        let is_cond_satisfied = !is_false_sym( &self.eval( &data.list[ 1 ] ) ); // Only #f is false, everything else is true
-        if is_cond_satisfied {
-            self.eval( &data.list[ 2 ] )
-        }
-        else if list_len == 3 {
-            new_void_data()
+
+    /// Gives the value back if there is no binding to update
+    fn set_exact( &self, variable: &Symbol, data: Data ) -> Result< (), Data > {
+        if let Some( x ) = self.env_data.borrow_mut().get_mut( variable ) {
+            *x = data;
+            return Ok( () );
         }
-        else {
-            self.eval( &data.list[ 3 ] )
+
+        match &self.parent_env {
+            Some( parent )  => parent.set_exact( variable, data ),
+            None            => Err( data ),
         }
     }
 
 
-    fn eval_cond( &mut self, data: &Data ) -> Data {
-        let list_len = data.list.len();
+    fn eval_quote( &self, form: &[ Data ] ) -> Result< Data, SchemeError > {
+        let list_len = form.len();
 
-        for i in 1..list_len {
-            let curr_cond   = &data.list[ i ];
+        if list_len != 2 {
+            return Err( SchemeError::bad_syntax( "quote", "exactly one datum", list_len - 1 ) );
+        }
 
-            if is_of_type( &DataType::List, &curr_cond ) || curr_cond.list.is_empty() {
-                print_error( Error::BadSyntax, "cond", "pair?", curr_cond.to_string().as_str() );
-                return INVALID_DATA;
-            }
+        Ok( form[ 1 ].clone() )
+    }
 
-            let is_else = curr_cond.list[ 0 ].string == "else";
-            if is_else && curr_cond.list.len() == 1 {
-                print_error( Error::BadSyntax, "cond", "expression in `else` clause", "" );
-                return INVALID_DATA;
-            }
 
-            let mut res = if is_else { INVALID_DATA } else { self.eval( &curr_cond.list[ 0 ] ) };
-            if !is_false_sym( &res ) {
-                for i in 1..curr_cond.list.len() {
-                    res = self.eval( &curr_cond.list[ i ] );
-                    if is_invalid_data( &res ) {
-                        return res;
-                    }
-                }
+    fn eval_quasiquote( self: &Rc< Self >, form: &[ Data ] ) -> Result< Step, SchemeError > {
+        let list_len = form.len();
 
-                return res;
-            }
+        if list_len != 2 {
+            return Err( SchemeError::bad_syntax( "quasiquote", "exactly one datum", list_len - 1 ) );
         }
 
-        new_void_data()
+        Ok( Step::Eval( quasiquote_expr( &form[ 1 ], 1 )?, Rc::clone( self ) ) )
     }
 
 
-    fn eval_apply( &mut self, data: &Data ) -> Data {
-        let list_len = data.list.len();
+    fn eval_define( self: &Rc< Self >, form: &[ Data ], k: &mut Cont ) -> Result< Step, SchemeError > {
+        let list_len = form.len();
 
-        if list_len != 3 {
-            print_error( Error::ArityMismatch, "apply", "2", (list_len - 1).to_string().as_str() );
-            return INVALID_DATA;
+        if list_len < 3 {
+            return Err( SchemeError::bad_syntax( "define", "at least 2 arguments needed", list_len - 1 ) );
         }
 
-        let proc = self.eval( &data.list[ 1 ] );
-        if !is_of_type( &DataType::Procedure, &proc ) && !is_of_type( &DataType::Lambda, &proc ) {
-            print_error( Error::ContractViolation, "apply", "a procedure that can be applied to arguments", proc.to_string().as_str() );
-            return INVALID_DATA;
+        if list_len > 3 && !is_pair( &form[ 1 ] ) {
+            return Err( SchemeError::bad_syntax( "define", "exactly one expression after identifier", "" ) );
         }
 
-        let list = self.eval( &data.list[ 2 ] );
-        if is_invalid_data( &list ) {
-            return list;
+        let ( identifier, res ) = match &form[ 1 ] {
+            // Procedure definition
+            Data::Pair( header ) => {
+                let identifier  = identifier_arg( "define", &header.car() )?.clone();
+                let clause      = parse_lambda_clause( "define", &header.cdr(), &form[ 2.. ] )?;
+                ( identifier.clone(), self.make_lambda( Some( identifier ), vec![ clause ] ) )
+            },
+            _ => {
+                // A lambda is named after the variable it is defined as
+                let identifier  = identifier_arg( "define", &form[ 1 ] )?.clone();
+                let value       = list_elements( &form[ 2 ] );
+                let res         = match value.first() {
+                    Some( Data::Symbol( sym ) ) if sym.as_str() == "lambda"         => self.eval_lambda( &value, Some( identifier.clone() ) )?,
+                    Some( Data::Symbol( sym ) ) if sym.as_str() == "case-lambda"    => self.eval_case_lambda( &value, Some( identifier.clone() ) )?,
+                    _ => return self.eval_into( &form[ 2 ], FrameKind::Define { name: identifier, env: Rc::clone( self ) }, k ),
+                };
+
+                ( identifier, res )
+            },
+        };
+
+        self.insert( &identifier, res.clone() );
+
+        Ok( Step::Return( res ) )
+    }
+
+
+    fn eval_lambda( self: &Rc< Self >, form: &[ Data ], name: Option< Symbol > ) -> Result< Data, SchemeError > {
+        if form.len() < 2 {
+            return Err( SchemeError::bad_syntax( "lambda", "a list of parameters and a body", "" ) );
         }
 
-        if is_false_sym( &proc_is_list( &vec![ list.clone() ] ) ) {
-            print_error( Error::ContractViolation, "apply", "list?", list.to_string().as_str() );
-            return INVALID_DATA;
+        Ok( self.make_lambda( name, vec![ parse_lambda_clause( "lambda", &form[ 1 ], &form[ 2.. ] )? ] ) )
+    }
+
+
+    /// `(case-lambda (params body ...) ...)` is a procedure that runs the first
+    /// clause that accepts the number of arguments it is called with
+    fn eval_case_lambda( self: &Rc< Self >, form: &[ Data ], name: Option< Symbol > ) -> Result< Data, SchemeError > {
+        let mut clauses = Vec::with_capacity( form.len() - 1 );
+        for clause in &form[ 1.. ] {
+            match clause {
+                Data::Pair( pair ) if is_list( clause ) => clauses.push( parse_lambda_clause( "case-lambda", &pair.car(), &list_elements( &pair.cdr() ) )? ),
+                _                                       => return Err( SchemeError::bad_syntax( "case-lambda", "a list of parameters and a body for a clause", clause ) ),
+            }
         }
 
-        let mut to_eval = Data::new_list();
-        to_eval.list.reserve( list.list.len() + 2 );
-        to_eval.list.push( data.list[ 1 ].clone() );
-        for elem in &list.list {
-            to_eval.list.push( elem.clone() );
+        Ok( self.make_lambda( name, clauses ) )
+    }
+
+
+    /// Creates the procedure of a `lambda` form or a procedure definition
+    fn make_lambda( self: &Rc< Self >, name: Option< Symbol >, clauses: Vec< LambdaClause > ) -> Data {
+        self.track();
+        Data::Lambda( Rc::new( Lambda { name, clauses, env: Rc::clone( self ), binds_name: false } ) )
+    }
+
+
+    fn eval_if( self: &Rc< Self >, form: &[ Data ], k: &mut Cont ) -> Result< Step, SchemeError > {
+        let list_len = form.len();
+        if !(3..=4).contains(&list_len) {
+            return Err( SchemeError::arity_mismatch( "if", "2 or 3", list_len - 1 ) );
         }
 
-        to_eval.list.pop(); // pops the '() at the end of the list
+        let frame = FrameKind::If { then: form[ 2 ].clone(), otherwise: form.get( 3 ).cloned(), env: Rc::clone( self ) };
+        self.eval_into( &form[ 1 ], frame, k )
+    }
+
 
-        self.eval( &to_eval )
+    fn eval_cond( self: &Rc< Self >, form: &[ Data ], k: &mut Cont ) -> Result< Step, SchemeError > {
+        self.eval_cond_clauses( new_list_data( form[ 1.. ].to_vec() ), k )
     }
 
 
-    fn eval_map( &mut self, data: &Data ) -> Data {
-        let list_len = data.list.len();
+    /// Tests the clauses in order until one of them is true
+    fn eval_cond_clauses( self: &Rc< Self >, clauses: Data, k: &mut Cont ) -> Result< Step, SchemeError > {
+        let ( curr_cond, rest ) = match &clauses {
+            Data::Pair( pair )  => ( pair.car(), pair.cdr() ),
+            _                   => return Ok( Step::Return( Data::Void ) ),
+        };
 
-        if list_len < 3 {
-            print_error( Error::ArityMismatch, "map", "at least 2", (list_len - 1).to_string().as_str() );
-            return INVALID_DATA;
-        }
+        let ( test, body ) = match &curr_cond {
+            Data::Pair( pair )  => ( pair.car(), pair.cdr() ),
+            _                   => return Err( SchemeError::bad_syntax( "cond", "pair?", curr_cond ) ),
+        };
+
+        if matches!( &test, Data::Symbol( sym ) if sym.as_str() == "else" ) {
+            if matches!( body, Data::Null ) {
+                return Err( SchemeError::bad_syntax( "cond", "expression in `else` clause", "" ) );
+            }
 
-        let proc = self.eval( &data.list[ 1 ] );
-        if !is_of_type( &DataType::Procedure, &proc ) && !is_of_type( &DataType::Lambda, &proc ) {
-            print_error( Error::ContractViolation, "map", "a procedure that can be applied to arguments", proc.to_string().as_str() );
-            return INVALID_DATA;
+            return Ok( eval_body( body, Rc::clone( self ), k ) );
         }
 
-        let list = &data.list[ 2 ];
-        if is_false_sym( &proc_is_list( &vec![ list.clone() ] ) ) {
-            print_error( Error::ContractViolation, "map", "list?", list.to_string().as_str() );
-            return INVALID_DATA;
+        self.eval_into( &test, FrameKind::Cond { body, clauses: rest, env: Rc::clone( self ) }, k )
+    }
+
+
+    /// Evaluates the body of the `cond` or `case` clause that applies. `(=> receiver)`
+    /// calls the receiver with the value of the test, or with the key
+    fn eval_clause_body( self: &Rc< Self >, value: Data, body: Data, k: &mut Cont ) -> Result< Step, SchemeError > {
+        match list_elements( &body ).as_slice() {
+            [ Data::Symbol( sym ), receiver ] if sym.as_str() == "=>" => {
+                self.eval_into( receiver, FrameKind::Control { resume: resume_apply_to, saved: vec![ value ] }, k )
+            },
+            _ => {
+                Ok( eval_body( body, Rc::clone( self ), k ) )
+            },
         }
+    }
 
-        let len = list.list.len();
-        if len == 0 {
-            return NULL_SYM;
+
+    fn eval_case( self: &Rc< Self >, form: &[ Data ], k: &mut Cont ) -> Result< Step, SchemeError > {
+        if form.len() < 2 {
+            return Err( SchemeError::bad_syntax( "case", "an expression and clauses", "" ) );
         }
 
-        let mut res_list = Data::new_list();
-        for i in 0..(len - 1) {
-            let mut to_eval = Data::new_list();
-            to_eval.list.reserve( len );
-            to_eval.list.push( data.list[ 1 ].clone() );
-            for j in 2..data.list.len() {
-                let list = &data.list[ j ];
-                if is_false_sym( &proc_is_list( &vec![ list.clone() ] ) ) {
-                    print_error( Error::ContractViolation, "map", "list?", list.to_string().as_str() );
-                    return INVALID_DATA;
-                }
+        self.eval_into( &form[ 1 ], FrameKind::Case { clauses: new_list_data( form[ 2.. ].to_vec() ), env: Rc::clone( self ) }, k )
+    }
 
-                let other_len = data.list[ j ].list.len();
-                if other_len != len {
-                    print_error( Error::ContractViolation, "map", "all lists must have same size", ( (len - 1).to_string() + " and " + ( if other_len == 0 { 0 } else { other_len - 1 } ).to_string().as_str() ).as_str() );
-                    return INVALID_DATA;
-                }
 
-                to_eval.list.push( list.list[ i ].clone() );
+    /// Evaluates the first `case` clause that lists the key, compared with `eqv?`
+    fn eval_case_clauses( self: &Rc< Self >, key: Data, clauses: Data, k: &mut Cont ) -> Result< Step, SchemeError > {
+        for clause in list_elements( &clauses ) {
+            let ( data, body ) = match &clause {
+                Data::Pair( pair ) if is_list( &clause ) && is_pair( &pair.cdr() )  => ( pair.car(), pair.cdr() ),
+                _                                                                   => return Err( SchemeError::bad_syntax( "case", "a list of data or `else` and a body for a clause", &clause ) ),
+            };
+
+            let is_else = matches!( &data, Data::Symbol( sym ) if sym.as_str() == "else" );
+            if !is_else && !is_list( &data ) {
+                return Err( SchemeError::bad_syntax( "case", "a list of data or `else` and a body for a clause", &clause ) );
             }
 
-            res_list.list.push( self.eval( &to_eval ) );
+            if is_else || list_elements( &data ).iter().any( |datum| is_eqv( datum, &key ) ) {
+                return self.eval_clause_body( key, body, k );
+            }
         }
 
-        res_list.list.push( NULL_SYM );
-
-        res_list
+        Ok( Step::Return( Data::Void ) )
     }
 
 
-    pub fn eval_proc_lambda( &mut self, data: &Data ) -> Data {
-        assert!( !data.list.is_empty() );
-
-        let proc = self.eval( &data.list[ 0 ] );
-        if is_invalid_data( &proc ) {
-            return proc;
+    /// `when` evaluates its body if the test is true, `unless` if it is false
+    fn eval_when( self: &Rc< Self >, form: &[ Data ], is_when: bool, k: &mut Cont ) -> Result< Step, SchemeError > {
+        if form.len() < 3 {
+            return Err( SchemeError::bad_syntax( if is_when { "when" } else { "unless" }, "a test and a body", "" ) );
         }
 
-        if !is_of_type( &DataType::Lambda, &proc ) && !is_of_type( &DataType::Procedure, &proc ) {
-            print_error( Error::NotAProcedure, "application", "", data.list[ 0 ].to_string().as_str() );
-            return INVALID_DATA;
+        let body                = new_pair_data( Data::Symbol( Symbol::new( "begin" ) ), new_list_data( form[ 2.. ].to_vec() ) );
+        let ( then, otherwise ) = if is_when { ( body, None ) } else { ( Data::Void, Some( body ) ) };
+        self.eval_into( &form[ 1 ], FrameKind::If { then, otherwise, env: Rc::clone( self ) }, k )
+    }
+
+
+    /// `(do ((var init step) ...) (test res ...) command ...)` is evaluated as a named
+    /// let that runs the commands and steps the variables until the test is true
+    fn eval_do( self: &Rc< Self >, form: &[ Data ] ) -> Result< Step, SchemeError > {
+        if form.len() < 3 || !is_list( &form[ 1 ] ) || !is_pair( &form[ 2 ] ) || !is_list( &form[ 2 ] ) {
+            return Err( SchemeError::bad_syntax( "do", "bindings, a test clause and a body", "" ) );
         }
 
-        let mut args = ProcedureArgsArr::with_capacity( data.list.len() - 1 );
-        for i in 1..data.list.len() {
-            let res = self.eval( &data.list[ i ] );
-            if is_invalid_data( &res ) {
-                return res;
-            }
-            else {
-                args.push( res );
+        let mut bindings    = ListValuesArr::new();
+        let mut steps       = ListValuesArr::new();
+        for binding in list_elements( &form[ 1 ] ) {
+            // A variable without a step keeps its value
+            let ( var, init, step ) = match list_elements( &binding ).as_slice() {
+                [ var @ Data::Symbol( _ ), init ] if is_list( &binding )          => ( var.clone(), init.clone(), var.clone() ),
+                [ var @ Data::Symbol( _ ), init, step ] if is_list( &binding )    => ( var.clone(), init.clone(), step.clone() ),
+                _                                                                   => return Err( SchemeError::bad_syntax( "do", "an identifier, an initial value and an optional step for a binding", &binding ) ),
+            };
+
+            if bindings.iter().any( |bound| list_elements( bound )[ 0 ] == var ) {
+                return Err( SchemeError::bad_syntax( "do", "distinct identifiers", var ) );
             }
+
+            bindings.push( new_list_data( vec![ var, init ] ) );
+            steps.push( step );
         }
 
-        if is_of_type( &DataType::Lambda, &proc ) {
-            let params = &proc.list[ 1 ].list;
-            if params.len() != args.len() {
-                print_error( Error::ArityMismatch, data.list[ 0 ].string.as_str(), params.len().to_string().as_str(), args.len().to_string().as_str() );
-                return INVALID_DATA;
-            }
+        // The loop is named by a fresh symbol, so it can't clash with the variables of the body
+        let loop_name   = Data::Symbol( Symbol::fresh( "do-loop" ) );
+        let keyword     = |name| Data::Symbol( Symbol::new( name ) );
+        let test_clause = list_elements( &form[ 2 ] );
+        let commands    = form[ 3.. ].iter().cloned().chain( std::iter::once( new_pair_data( loop_name.clone(), new_list_data( steps ) ) ) ).collect();
+        let body        = new_list_data( vec![
+            keyword( "if" ),
+            test_clause[ 0 ].clone(),
+            match test_clause.len() {
+                1   => Data::Void,
+                _   => new_pair_data( keyword( "begin" ), new_list_data( test_clause[ 1.. ].to_vec() ) ),
+            },
+            new_pair_data( keyword( "begin" ), new_list_data( commands ) ),
+        ] );
 
-            let mut res         = Data::new();
-            let mut lambda_env  = Environment::with_args( &params, &args, &self );
-            for i in 2..proc.list.len() {
-                let body = &proc.list[ i ];
-                res = lambda_env.eval( body );
-                if is_invalid_data( &res ) {
-                    return res;
-                }
-            }
+        Ok( Step::Eval( new_list_data( vec![ keyword( "let" ), loop_name, new_list_data( bindings ), body ] ), Rc::clone( self ) ) )
+    }
 
-            return res;
-        }
 
-        if is_of_type( &DataType::Procedure, &proc ) {
-            (proc.procedure)( &args )
-        }
-        else {
-            INVALID_DATA
+    fn eval_let( self: &Rc< Self >, form: &[ Data ], k: &mut Cont ) -> Result< Step, SchemeError > {
+        if form.len() > 1 && matches!( form[ 1 ], Data::Symbol( _ ) ) {
+            return self.eval_named_let( form, k );
         }
+
+        let ( params, inits )   = parse_bindings( "let", form, 1 )?;
+        let then                = ArgsThen::Bind { params, body: new_list_data( form[ 2.. ].to_vec() ), parent: Rc::clone( self ) };
+        eval_args( vec![], new_list_data( inits ), Rc::clone( self ), then, k )
     }
 
 
-    fn add_procedure( &mut self, proc_name: &str, proc: Procedure ) {
-        self.env_data.insert( proc_name.to_string(), Data::new_proc( proc_name, proc ) );
+    /// `(let name ((var init) ...) body ...)` binds `name` to a procedure over the
+    /// variables whose body is the let body, and calls it with the initial values
+    fn eval_named_let( self: &Rc< Self >, form: &[ Data ], k: &mut Cont ) -> Result< Step, SchemeError > {
+        let name                = identifier_arg( "let", &form[ 1 ] )?;
+        let ( params, inits )   = parse_bindings( "let", form, 2 )?;
+        let body                = new_list_data( form[ 3.. ].to_vec() );
+
+        let clause      = LambdaClause { params, rest: None, body };
+        let proc        = Lambda { name: Some( name.clone() ), clauses: vec![ clause ], env: Rc::clone( self ), binds_name: true };
+        self.track();
+
+        eval_args( vec![ Data::Lambda( Rc::new( proc ) ) ], new_list_data( inits ), Rc::clone( self ), ArgsThen::Apply, k )
     }
 
-}
 
+    /// Each binding of `let*` is evaluated in a frame that sees the previous ones
+    fn eval_let_star( self: &Rc< Self >, form: &[ Data ], k: &mut Cont ) -> Result< Step, SchemeError > {
+        let ( params, inits )   = parse_bindings( "let*", form, 1 )?;
+        let bindings            = params.into_iter().zip( inits ).rev().collect();
 
-impl Data {
+        eval_let_star_bindings( bindings, new_list_data( form[ 2.. ].to_vec() ), Environment::with_args( &[], vec![], self ), k )
+    }
 
-    pub fn new() -> Data {
-        Data { list: vec![], string: String::new(), procedure: NULL_PROC, data_type: DataType::Invalid, quote_level: 0 }
+
+    /// The bindings of `letrec` are evaluated in the new frame, so they can refer
+    /// to each other. `letrec*` also makes each value visible to the following ones.
+    fn eval_letrec( self: &Rc< Self >, form: &[ Data ], is_sequential: bool, k: &mut Cont ) -> Result< Step, SchemeError > {
+        let form_name           = if is_sequential { "letrec*" } else { "letrec" };
+        let ( params, inits )   = parse_bindings( form_name, form, 1 )?;
+        let letrec              = Letrec {
+            bindings    : params.into_iter().zip( inits ).rev().collect(),
+            values      : vec![],
+            body        : new_list_data( form[ 2.. ].to_vec() ),
+            env         : Environment::with_args( &[], vec![], self ),
+            is_sequential,
+        };
+
+        letrec.eval_next( k )
     }
 
-    pub fn from_string( dtype: DataType, string: String ) -> Data {
-        Data { list: vec![], string: string, procedure: NULL_PROC, data_type: dtype, quote_level: 0 }
+
+    fn eval_set( self: &Rc< Self >, form: &[ Data ], k: &mut Cont ) -> Result< Step, SchemeError > {
+        let list_len = form.len();
+
+        if list_len != 3 {
+            return Err( SchemeError::bad_syntax( "set!", "an identifier and an expression", list_len - 1 ) );
+        }
+
+        let identifier = identifier_arg( "set!", &form[ 1 ] )?.clone();
+        self.eval_into( &form[ 2 ], FrameKind::Set { name: identifier, env: Rc::clone( self ) }, k )
     }
 
-    pub fn from_string_quoted( dtype: DataType, string: String, quote_level: u16 ) -> Data {
-        Data { list: vec![], string: string, procedure: NULL_PROC, data_type: dtype, quote_level: quote_level }
+
+    /// `(define-syntax name (syntax-rules ...))`
+    fn eval_define_syntax( self: &Rc< Self >, form: &[ Data ] ) -> Result< Data, SchemeError > {
+        let list_len = form.len();
+
+        if list_len != 3 {
+            return Err( SchemeError::bad_syntax( "define-syntax", "an identifier and a transformer", list_len - 1 ) );
+        }
+
+        let identifier = identifier_arg( "define-syntax", &form[ 1 ] )?;
+        self.insert( identifier, Data::Macro( Rc::new( Macro::new( "define-syntax", &form[ 2 ], self )? ) ) );
+
+        Ok( Data::Void )
     }
 
-    pub fn new_list() -> Data {
-        Data { list: vec![], string: String::new(), procedure: NULL_PROC, data_type: DataType::List, quote_level: 0 }
+
+    /// `let-syntax` and `letrec-syntax` bind macros for their body only. The templates
+    /// of `let-syntax` refer to the variables around it, and those of `letrec-syntax`
+    /// can also use the macros it binds
+    fn eval_let_syntax( self: &Rc< Self >, form: &[ Data ], form_name: &str, k: &mut Cont ) -> Result< Step, SchemeError > {
+        let ( names, specs )    = parse_bindings( form_name, form, 1 )?;
+        let env                 = Environment::with_args( &[], vec![], self );
+        let definition_env      = if form_name == "letrec-syntax" { &env } else { self };
+        for ( name, spec ) in names.iter().zip( &specs ) {
+            env.insert( name, Data::Macro( Rc::new( Macro::new( form_name, spec, definition_env )? ) ) );
+        }
+
+        Ok( eval_body( new_list_data( form[ 2.. ].to_vec() ), env, k ) )
     }
 
-    pub fn from_list( list: ListValuesArr ) -> Data {
-        Data { list: list, string: String::new(), procedure: NULL_PROC, data_type: DataType::List, quote_level: 0 }
+
+    fn eval_load( self: &Rc< Self >, form: &[ Data ], k: &mut Cont ) -> Result< Step, SchemeError > {
+        let list_len = form.len();
+
+        if list_len != 2 {
+            return Err( SchemeError::arity_mismatch( "load", "1", list_len - 1 ) );
+        }
+
+        self.eval_into( &form[ 1 ], FrameKind::LoadPath { env: Rc::clone( self ) }, k )
     }
 
-    pub fn new_proc( proc_name: &str, proc: Procedure ) -> Data {
-        Data { list: vec![], string: proc_name.to_string(), procedure: proc, data_type: DataType::Procedure, quote_level: 0 }
+
+    /// `(guard (var clause ...) body ...)` evaluates the body with a handler that jumps
+    /// out of it and tests the clauses like `cond`, with the raised value bound to `var`.
+    /// If no clause applies, the value is raised again from the `guard`
+    fn eval_guard( self: &Rc< Self >, form: &[ Data ], k: &mut Cont ) -> Result< Step, SchemeError > {
+        if form.len() < 3 || !is_pair( &form[ 1 ] ) || !is_list( &form[ 1 ] ) {
+            return Err( SchemeError::bad_syntax( "guard", "a variable with clauses and a body", "" ) );
+        }
+
+        let spec    = list_elements( &form[ 1 ] );
+        let var     = identifier_arg( "guard", &spec[ 0 ] )?.clone();
+        let reraise = new_list_data( vec![ Data::Control( "raise", proc_raise ), Data::Symbol( var.clone() ) ] );
+        let clauses = spec[ 1.. ].iter().cloned()
+            .chain( std::iter::once( new_list_data( vec![ Data::Symbol( Symbol::new( "else" ) ), reraise ] ) ) )
+            .collect();
+
+        let mut handler = k.clone();
+        handler.push( FrameKind::Guard { var, clauses: new_list_data( clauses ), env: Rc::clone( self ) } );
+        k.push( FrameKind::Handlers( new_pair_data( Data::Continuation( Continuation( handler ) ), k.handlers() ) ) );
+
+        Ok( eval_body( new_list_data( form[ 2.. ].to_vec() ), Rc::clone( self ), k ) )
     }
 
-    pub fn display( &self, f: &mut fmt::Formatter<'_>, quote_level: u16 ) -> fmt::Result {
-        match self.data_type {
-            DataType::Lambda => {
-                if self.string.is_empty() {
-                    write!( f, "#<lambda>" )
-                }
-                else {
-                    write!( f, "#<procedure:{}>", self.string.as_str() )
-                }
-            },
-            DataType::Procedure => {
-                write!( f, "#<procedure:{}>", self.string.as_str() )
-            },
-            DataType::List => {
-                if quote_level == 0 {
-                    if let Err( e ) = write!( f, "'" ) {
-                        eprintln!( "{}", e );
-                        return Err( e );
-                    }
-                }
-                
-                if quote_level < self.quote_level {
-                    for _ in 0..(self.quote_level - quote_level) {
-                        if let Err( e ) = write!( f, "'" ) {
-                            eprintln!( "{}", e );
-                            return Err( e );
+
+    /// Hands the environment and its ancestors to the cycle collector, and collects
+    /// if enough environments were tracked since the last collection
+    fn track( self: &Rc< Self > ) {
+        let should_collect = TRACKED_ENVS.with( |tracked| {
+            let mut tracked = tracked.borrow_mut();
+
+            // The ancestors of a tracked environment are always tracked too
+            let mut env = Some( self );
+            while let Some( curr ) = env.filter( |env| !env.is_tracked.get() ) {
+                curr.is_tracked.set( true );
+                tracked.envs.push( Rc::downgrade( curr ) );
+                env = curr.parent_env.as_ref();
+            }
+
+            tracked.envs.len() >= tracked.threshold
+        } );
+
+        if should_collect {
+            Environment::collect_cycles();
+        }
+    }
+
+
+    /// Frees the tracked environments that are only kept alive by cycles.
+    ///
+    /// Each environment and each procedure bound in one starts with its reference count,
+    /// minus the references from other tracked environments and their procedures. Whatever
+    /// still has references left is used from outside, by the evaluator or by a value the
+    /// collector doesn't look into, and so is everything it reaches. The bindings of the
+    /// other environments are cleared, which breaks their cycles
+    pub fn collect_cycles() {
+        let envs = TRACKED_ENVS.with( |tracked| tracked.borrow_mut().envs.drain( .. ).filter_map( |env| env.upgrade() ).collect::< Vec< _ > >() );
+
+        let env_index: HashMap< *const Environment, usize > = envs.iter().enumerate().map( |( i, env )| ( Rc::as_ptr( env ), i ) ).collect();
+        let mut lambdas         = Vec::< Rc< Lambda > >::new();
+        let mut lambda_index    = HashMap::< *const Lambda, usize >::new();
+        let mut env_edges       = vec![ vec![]; envs.len() ];
+        let mut is_borrowed     = vec![ false; envs.len() ];
+
+        for ( i, env ) in envs.iter().enumerate() {
+            match env.env_data.try_borrow() {
+                Ok( env_data ) => {
+                    for value in env_data.values() {
+                        if let Data::Lambda( lambda ) = value {
+                            let j = *lambda_index.entry( Rc::as_ptr( lambda ) ).or_insert_with( || {
+                                lambdas.push( Rc::clone( lambda ) );
+                                lambdas.len() - 1
+                            } );
+                            env_edges[ i ].push( j );
                         }
                     }
-                }
+                },
+                // Something is using the bindings right now
+                Err( _ ) => {
+                    is_borrowed[ i ] = true;
+                },
+            }
+        }
 
-                if let Err( e ) = write!( f, "(" ) {
-                    eprintln!( "{}", e );
-                    return Err( e );
-                }
+        // The references held by `envs` and `lambdas` themselves don't count
+        let mut env_refs    = envs.iter().map( |env| Rc::strong_count( env ) - 1 ).collect::< Vec< usize > >();
+        let mut lambda_refs = lambdas.iter().map( |lambda| Rc::strong_count( lambda ) - 1 ).collect::< Vec< usize > >();
+        let parent_of       = |env: &Rc< Environment >| env.parent_env.as_ref().and_then( |parent| env_index.get( &Rc::as_ptr( parent ) ).copied() );
+        let env_of          = |lambda: &Rc< Lambda >| env_index.get( &Rc::as_ptr( &lambda.env ) ).copied();
 
-                assert!( !self.list.is_empty() );
+        for ( i, env ) in envs.iter().enumerate() {
+            if let Some( parent ) = parent_of( env ) {
+                env_refs[ parent ] -= 1;
+            }
 
-                if self.list.len() == 1 {
-                    assert!( is_null_sym( &self.list[ 0 ] ) )
-                }
+            for &j in &env_edges[ i ] {
+                lambda_refs[ j ] -= 1;
+            }
+        }
 
-                if let Err( e ) = self.list[ 0 ].display( f, quote_level + 1 ) {
-                    eprintln!( "{}", e );
-                    return Err( e );
-                }
+        for lambda in &lambdas {
+            if let Some( env ) = env_of( lambda ) {
+                env_refs[ env ] -= 1;
+            }
+        }
 
-                for i in 1..self.list.len() - 1 {
-                    if let Err( e ) = write!( f, " " ) {
-                        println!( "{}", e );
-                        return Err( e );
-                    }
-                    if let Err( e ) = self.list[ i ].display( f, quote_level + 1 ) {
-                        eprintln!( "{}", e );
-                        return Err( e );
-                    }
-                }
+        // Marks what the environments and procedures that are used from outside reach
+        let mut is_reachable        = vec![ false; envs.len() ];
+        let mut lambda_reachable    = lambda_refs.iter().map( |&refs| refs > 0 ).collect::< Vec< bool > >();
+        let mut pending             = ( 0..envs.len() ).filter( |&i| env_refs[ i ] > 0 || is_borrowed[ i ] ).collect::< Vec< usize > >();
+        pending.extend( lambdas.iter().zip( &lambda_reachable ).filter( |( _, &is_used )| is_used ).filter_map( |( lambda, _ )| env_of( lambda ) ) );
 
-                if self.list.len() > 1 && !is_null_sym( self.list.last().unwrap() ) {
-                    if let Err( e ) = write!( f, " . " ) {
-                        println!( "{}", e );
-                        return Err( e );
-                    }
-                    if let Err( e ) = self.list.last().unwrap().display( f, quote_level + 1 ) {
-                        println!( "{}", e );
-                        return Err( e );
-                    }
+        while let Some( i ) = pending.pop() {
+            if mem::replace( &mut is_reachable[ i ], true ) {
+                continue;
+            }
+
+            pending.extend( parent_of( &envs[ i ] ) );
+            for &j in &env_edges[ i ] {
+                if !mem::replace( &mut lambda_reachable[ j ], true ) {
+                    pending.extend( env_of( &lambdas[ j ] ) );
                 }
+            }
+        }
 
-                write!( f, ")" )
+        // The bindings are dropped only once nothing is borrowed anymore
+        let mut garbage = vec![];
+        let mut alive   = vec![];
+        for ( env, is_reachable ) in envs.iter().zip( is_reachable ) {
+            if is_reachable {
+                alive.push( Rc::downgrade( env ) );
             }
-            DataType::Symbol => {
-                if quote_level < self.quote_level {
-                    for _ in 0..( self.quote_level - quote_level ) {
-                        if let Err( e ) = write!( f, "'" ) {
-                            eprintln!( "{}", e );
-                            return Err( e );
-                        }
-                    }
-                }
+            else {
+                garbage.push( env.env_data.take() );
+            }
+        }
 
-                if !self.string.is_empty() {
-                    return write!( f, "{}", &self.string );
-                }
+        TRACKED_ENVS.with( |tracked| {
+            let mut tracked = tracked.borrow_mut();
+            tracked.threshold = MIN_COLLECT_THRESHOLD.max( 2 * alive.len() );
+            tracked.envs.append( &mut alive );
+        } );
 
-                if let Err( e ) = write!( f, "(" ) {
-                    eprintln!( "{}", e );
-                    return Err( e );
-                }
+        drop( lambdas );
+        drop( envs );
+        drop( garbage );
+    }
 
-                if !self.list.is_empty() {
-                    if let Err( e ) = self.list.first().unwrap().display( f, self.quote_level ) {
-                        println!( "{}", e );
-                        return Err( e );
-                    }
 
-                    for i in 1..self.list.len() - 1 {
-                        if let Err( e ) = write!( f, " " ) {
-                            eprintln!( "{}", e );
-                            return Err( e );
-                        }
-                        if let Err( e ) = self.list[ i ].display( f, self.quote_level ) {
-                            println!( "{}", e );
-                            return Err( e );
-                        }
-                    }
+    /// The number of environments the cycle collector tracks that are still alive
+    #[cfg( test )]
+    pub fn tracked_count() -> usize {
+        TRACKED_ENVS.with( |tracked| tracked.borrow().envs.iter().filter( |env| env.strong_count() > 0 ).count() )
+    }
 
-                    assert!( is_null_sym( self.list.last().unwrap() ) );
-                }
 
-                write!( f, ")" )
+    fn add_procedure( &self, proc_name: &'static str, proc: Procedure ) {
+        self.insert( &Symbol::new( proc_name ), Data::Procedure( proc_name, proc ) );
+    }
+
+
+    fn add_control( &self, proc_name: &'static str, proc: ControlProcedure ) {
+        self.insert( &Symbol::new( proc_name ), Data::Control( proc_name, proc ) );
+    }
+
+}
+
+
+/// Evaluates a special form, given the whole form
+type SpecialForm = fn( &Rc< Environment >, &[ Data ], &mut Cont ) -> Result< Step, SchemeError >;
+
+
+/// The special form with the given name. Special forms are recognized by name,
+/// so local variables can't shadow them
+fn special_form( name: &str ) -> Option< SpecialForm > {
+    let eval_form: SpecialForm = match name {
+        "quote"             => |env, form, _| Ok( Step::Return( env.eval_quote( form )? ) ),
+        "quasiquote"        => |env, form, _| env.eval_quasiquote( form ),
+        "unquote"           => |_, _, _| Err( SchemeError::bad_context( "unquote", "not in quasiquote" ) ),
+        "unquote-splicing"  => |_, _, _| Err( SchemeError::bad_context( "unquote-splicing", "not in quasiquote" ) ),
+        "begin"             => |env, form, k| match form.len() {
+            1   => Err( SchemeError::bad_syntax( "begin", "at least one expression", "" ) ),
+            _   => Ok( eval_body( new_list_data( form[ 1.. ].to_vec() ), Rc::clone( env ), k ) ),
+        },
+        "define"            => Environment::eval_define,
+        "lambda"            => |env, form, _| Ok( Step::Return( env.eval_lambda( form, None )? ) ),
+        "case-lambda"       => |env, form, _| Ok( Step::Return( env.eval_case_lambda( form, None )? ) ),
+        "if"                => Environment::eval_if,
+        "cond"              => Environment::eval_cond,
+        "case"              => Environment::eval_case,
+        "and"               => |env, form, k| eval_and_or( new_list_data( form[ 1.. ].to_vec() ), Rc::clone( env ), true, k ),
+        "or"                => |env, form, k| eval_and_or( new_list_data( form[ 1.. ].to_vec() ), Rc::clone( env ), false, k ),
+        "when"              => |env, form, k| env.eval_when( form, true, k ),
+        "unless"            => |env, form, k| env.eval_when( form, false, k ),
+        "do"                => |env, form, _| env.eval_do( form ),
+        "let"               => Environment::eval_let,
+        "let*"              => Environment::eval_let_star,
+        "letrec"            => |env, form, k| env.eval_letrec( form, false, k ),
+        "letrec*"           => |env, form, k| env.eval_letrec( form, true, k ),
+        "load"              => Environment::eval_load,
+        "set!"              => Environment::eval_set,
+        "define-syntax"     => |env, form, _| Ok( Step::Return( env.eval_define_syntax( form )? ) ),
+        "let-syntax"        => |env, form, k| env.eval_let_syntax( form, "let-syntax", k ),
+        "letrec-syntax"     => |env, form, k| env.eval_let_syntax( form, "letrec-syntax", k ),
+        "guard"             => Environment::eval_guard,
+        _                   => return None,
+    };
+
+    Some( eval_form )
+}
+
+
+/// Runs the evaluator until the continuation has nothing left to do. Each step
+/// returns here instead of recursing, so neither deep recursion nor long loops
+/// use up the Rust stack
+fn run( mut step: Step, mut k: Cont ) -> Result< Data, SchemeError > {
+    loop {
+        let next = match step {
+            Step::Eval( data, env )     => env.eval_step( &data, &mut k ),
+            Step::Apply( proc, args )   => apply( proc, args, &mut k ),
+            Step::Return( value )       => match k.pop() {
+                Some( frame )   => frame.resume( value, &mut k ),
+                None            => return Ok( value ),
             },
-            DataType::Integer => {
-                if !( quote_level == 0 && self.quote_level == 1 ) {
-                    if quote_level < self.quote_level {
-                        for _ in 0..( self.quote_level - quote_level ) {
-                            if let Err( e ) = write!( f, "'" ) {
-                                eprintln!( "{}", e );
-                                return Err( e );
-                            }
-                        }
-                    }
-                }
+        };
+
+        // Errors become exceptions when there is a handler to catch them
+        let next = match next {
+            Err( e ) if is_pair( &k.handlers() )    => raise( Data::ErrorObject( Rc::new( ErrorObject::from_error( e ) ) ), false, &mut k ),
+            next                                    => next,
+        };
+
+        step = match next {
+            Ok( step )  => step,
+            Err( e )    => return Err( k.annotate( e ) ),
+        };
+    }
+}
+
+
+/// Evaluates the expressions in `rest` from left to right, appending their values
+/// to `values`, and then does what `then` says with all of them
+fn eval_args( mut values: ProcedureArgsArr, mut rest: Data, env: Rc< Environment >, then: ArgsThen, k: &mut Cont ) -> Result< Step, SchemeError > {
+    while let Data::Pair( pair ) = rest {
+        let expr    = pair.car();
+        rest        = pair.cdr();
+
+        match env.atom_value( &expr )? {
+            Some( value ) => {
+                values.push( value );
+            },
+            None => {
+                k.push( FrameKind::Args { values, rest, env: Rc::clone( &env ), then } );
+                return Ok( Step::Eval( expr, env ) );
+            },
+        }
+    }
+
+    match then {
+        ArgsThen::Apply => {
+            let proc = values.remove( 0 );
+            Ok( Step::Apply( proc, values ) )
+        },
+        ArgsThen::Bind { params, body, parent } => {
+            Ok( eval_body( body, Environment::with_args( &params, values, &parent ), k ) )
+        },
+    }
+}
+
+
+/// Whether a form is a proper list. Code can't be circular, so unlike `is_list`
+/// this doesn't need to look for cycles
+fn is_proper_form( form: &Data ) -> bool {
+    let mut rest = form.clone();
+    loop {
+        match rest {
+            Data::Pair( pair )  => rest = pair.cdr(),
+            Data::Null          => return true,
+            _                   => return false,
+        }
+    }
+}
+
+
+/// Evaluates the expressions of an `and` or `or` until one of them decides the
+/// result. The last one is in tail position
+fn eval_and_or( exprs: Data, env: Rc< Environment >, is_and: bool, k: &mut Cont ) -> Result< Step, SchemeError > {
+    let ( expr, rest ) = match &exprs {
+        Data::Pair( pair )  => ( pair.car(), pair.cdr() ),
+        _                   => return Ok( Step::Return( Data::Bool( is_and ) ) ),
+    };
+
+    if matches!( rest, Data::Null ) {
+        return Ok( Step::Eval( expr, env ) );
+    }
+
+    env.eval_into( &expr, FrameKind::AndOr { rest, env: Rc::clone( &env ), is_and }, k )
+}
+
+
+/// Evaluates a body, a list of expressions, leaving the last one in tail position
+fn eval_body( body: Data, env: Rc< Environment >, k: &mut Cont ) -> Step {
+    match body {
+        Data::Pair( pair ) => {
+            let rest = pair.cdr();
+            if !matches!( rest, Data::Null ) {
+                k.push( FrameKind::Body { rest, env: Rc::clone( &env ) } );
+            }
+
+            Step::Eval( pair.car(), env )
+        },
+        _ => {
+            Step::Return( Data::Void )
+        },
+    }
+}
+
+
+/// Evaluates the next binding of a `let*`. `bindings` holds the ones left in reverse order
+fn eval_let_star_bindings( mut bindings: Vec< ( Symbol, Data ) >, body: Data, env: Rc< Environment >, k: &mut Cont ) -> Result< Step, SchemeError > {
+    match bindings.pop() {
+        Some( ( param, init ) ) => {
+            let frame = FrameKind::LetStar { param, bindings, body, env: Rc::clone( &env ) };
+            env.eval_into( &init, frame, k )
+        },
+        None => {
+            Ok( eval_body( body, env, k ) )
+        },
+    }
+}
+
+
+/// A `letrec` whose bindings are being evaluated
+#[derive( Clone )]
+struct Letrec {
+    /// The bindings left, in reverse order
+    bindings        : Vec< ( Symbol, Data ) >,
+    /// The values of a `letrec` are bound only once all of them are evaluated
+    values          : Vec< ( Symbol, Data ) >,
+    body            : Data,
+    env             : Rc< Environment >,
+    is_sequential   : bool,
+}
+
+
+impl Letrec {
+
+    fn eval_next( mut self, k: &mut Cont ) -> Result< Step, SchemeError > {
+        match self.bindings.pop() {
+            Some( ( param, init ) ) => {
+                let env = Rc::clone( &self.env );
+                env.eval_into( &init, FrameKind::Letrec( param, self ), k )
+            },
+            None => {
+                for ( param, value ) in self.values {
+                    self.env.insert( &param, value );
+                }
+
+                Ok( eval_body( self.body, self.env, k ) )
+            },
+        }
+    }
+
+}
+
+
+/// Reads the file named by `file_name` and starts evaluating its contents
+fn load_file( file_name: Data, env: Rc< Environment >, k: &mut Cont ) -> Result< Step, SchemeError > {
+    let file_name = match &file_name {
+        Data::String( name )    => name.as_ref(),
+        _                       => return Err( SchemeError::contract_violation( "load", "path-string?", file_name ) ),
+    };
+
+    // Relative paths are resolved against the directory of the file being loaded
+    let loading = k.loading_files();
+    let path    = match loading.first().and_then( |file| file.parent() ) {
+        Some( dir ) => dir.join( file_name ),
+        None        => PathBuf::from( file_name ),
+    };
+
+    let content = fs::read_to_string( &path ).map_err( |e| SchemeError::cannot_open_file( &path, e ) )?;
+    let path    = fs::canonicalize( &path ).unwrap_or( path );
+
+    if loading.contains( &path ) {
+        return Err( SchemeError::LoadCycle { file: path.display().to_string() } );
+    }
+
+    // Reading stops at the first syntax error, which is reported once the data before it are evaluated
+    let mut parser  = Parser::new();
+    let mut data    = vec![];
+    parser.load( content.as_str() );
+    for datum in &mut parser {
+        let is_err = datum.is_err();
+        data.push( datum );
+        if is_err {
+            break;
+        }
+    }
+
+    data.reverse();
+    load_next( data, path, env, Data::Void, k )
+}
+
+
+/// Evaluates the next datum of a file being loaded. `data` holds the ones left in
+/// reverse order, and the file evaluates to the value of its last datum
+fn load_next( mut data: Vec< Result< Data, SchemeError > >, path: PathBuf, env: Rc< Environment >, value: Data, k: &mut Cont ) -> Result< Step, SchemeError > {
+    match data.pop() {
+        Some( Ok( datum ) ) => {
+            k.push( FrameKind::Load { data, path, env: Rc::clone( &env ) } );
+            Ok( Step::Eval( datum, env ) )
+        },
+        Some( Err( e ) ) => {
+            Err( SchemeError::InFile { file: path.display().to_string(), error: Box::new( e ) } )
+        },
+        None => {
+            Ok( Step::Return( value ) )
+        },
+    }
+}
+
+
+/// Turns a quasiquote template into an expression that builds it, with the
+/// expressions marked with `unquote` and `unquote-splicing` in their places. Only
+/// the unquotes of the outermost quasiquote are evaluated, `depth` counts the nesting.
+/// The list procedures are put into the expression as values, so local variables can't shadow them
+fn quasiquote_expr( template: &Data, depth: usize ) -> Result< Data, SchemeError > {
+    if let Some( ( prefix, datum ) ) = template.quote_form() {
+        let inner_depth = match prefix {
+            ","     if depth == 1   => return Ok( datum ),
+            ",@"    if depth == 1   => return Err( SchemeError::bad_context( "unquote-splicing", "invalid context within quasiquote" ) ),
+            "," | ",@"              => depth - 1,
+            "`"                     => depth + 1,
+            _                       => depth,
+        };
+
+        let name = match template { Data::Pair( pair ) => pair.car(), _ => unreachable!() };
+        return Ok( new_list_data( vec![ Data::Procedure( "list", proc_list ), quote_expr( name ), quasiquote_expr( &datum, inner_depth )? ] ) );
+    }
+
+    if !is_pair( template ) {
+        return Ok( quote_expr( template.clone() ) );
+    }
+
+    let mut items   = ListValuesArr::new();
+    let mut rest    = template.clone();
+    while let Data::Pair( pair ) = &rest {
+        // `(a . ,b)` is the list `(a unquote b)`, so the rest of a list can be an unquote
+        if matches!( rest.quote_form(), Some( ( "," | ",@", _ ) ) ) {
+            break;
+        }
+
+        items.push( pair.car() );
+        rest = pair.cdr();
+    }
+
+    // The list is built starting from its tail
+    let mut res = quasiquote_expr( &rest, depth )?;
+    for item in items.into_iter().rev() {
+        res = match item.quote_form() {
+            Some( ( ",@", spliced ) ) if depth == 1 => new_list_data( vec![ Data::Procedure( "unquote-splicing", proc_unquote_splicing ), spliced, res ] ),
+            _                                       => new_list_data( vec![ Data::Procedure( "cons", proc_cons ), quasiquote_expr( &item, depth )?, res ] ),
+        };
+    }
+
+    Ok( res )
+}
+
+
+fn quote_expr( datum: Data ) -> Data {
+    new_list_data( vec![ Data::Symbol( Symbol::new( "quote" ) ), datum ] )
+}
+
+
+/// Puts the elements of a list spliced into a quasiquote template in front of the rest of the template
+fn proc_unquote_splicing( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    let ( list, rest ) = ( &args[ 0 ], &args[ 1 ] );
+    if !is_list( list ) {
+        return Err( SchemeError::contract_violation( "unquote-splicing", "list?", list ) );
+    }
+
+    Ok( new_improper_list_data( list_elements( list ), rest.clone() ) )
+}
+
+
+impl Lambda {
+
+    /// The error for a call that no clause accepts, with the accepted numbers of arguments.
+    /// Counts that a clause with a rest parameter already accepts are left out
+    fn arity_mismatch( &self, given: usize ) -> SchemeError {
+        let name        = self.name.as_ref().map_or( "#<procedure>", Symbol::as_str );
+        let at_least    = self.clauses.iter().filter( |clause| clause.rest.is_some() ).map( |clause| clause.params.len() ).min();
+
+        let mut counts  = self.clauses.iter()
+            .filter( |clause| clause.rest.is_none() )
+            .map( |clause| clause.params.len() )
+            .filter( |&count| at_least.is_none_or( |min| count < min ) )
+            .collect::< Vec< usize > >();
+        counts.sort_unstable();
+        counts.dedup();
+
+        let mut expected = counts.iter().map( usize::to_string ).collect::< Vec< String > >();
+        if let Some( min ) = at_least {
+            expected.push( format!( "at least {}", min ) );
+        }
+
+        SchemeError::arity_mismatch( name, expected.join( " or " ), given )
+    }
+
+}
+
+
+impl LambdaClause {
+
+    fn accepts( &self, arg_count: usize ) -> bool {
+        if self.rest.is_some() { arg_count >= self.params.len() } else { arg_count == self.params.len() }
+    }
+
+    /// Creates the frame for a call, binding the arguments to the parameters
+    fn bind( &self, mut args: ProcedureArgsArr, parent: &Rc< Environment > ) -> Rc< Environment > {
+        let rest_args   = args.split_off( self.params.len() );
+        let env         = Environment::with_args( &self.params, args, parent );
+        if let Some( rest ) = &self.rest {
+            env.insert( rest, new_list_data( rest_args ) );
+        }
+
+        env
+    }
+
+}
+
+
+/// Applies a procedure to evaluated arguments, leaving the body of a lambda in tail position
+fn apply( proc: Data, args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    match proc {
+        Data::Procedure( _, func ) => {
+            Ok( Step::Return( func( &args )? ) )
+        },
+        Data::Control( _, func ) => {
+            func( args, k )
+        },
+        Data::Lambda( lambda ) => {
+            let clause = match lambda.clauses.iter().find( |clause| clause.accepts( args.len() ) ) {
+                Some( clause )  => clause,
+                None            => return Err( lambda.arity_mismatch( args.len() ) ),
+            };
+
+            // The body is evaluated in a new frame whose parent is the environment
+            // the lambda was created in, not the one it is called from
+            let env = clause.bind( args, &lambda.env );
+            if let ( true, Some( name ) ) = ( lambda.binds_name, &lambda.name ) {
+                // A parameter with the same name shadows the loop
+                env.env_data.borrow_mut().entry( name.clone() ).or_insert_with( || Data::Lambda( Rc::clone( &lambda ) ) );
+            }
+
+            Ok( eval_body( clause.body.clone(), env, k ) )
+        },
+        Data::Continuation( cont ) => {
+            continue_with( &cont.0, args, k )
+        },
+        _ => {
+            Err( SchemeError::not_a_procedure( proc ) )
+        },
+    }
+}
+
+
+/// What the evaluator does next
+pub enum Step {
+    Eval( Data, Rc< Environment > ),
+    Apply( Data, ProcedureArgsArr ),
+    Return( Data ),
+}
+
+
+/// The rest of a computation: a chain of frames, each waiting for a value. Frames
+/// are shared, so capturing the continuation with `call/cc` doesn't copy anything
+#[derive( Clone, Default )]
+pub struct Cont( Option< Rc< Frame > > );
+
+
+struct Frame {
+    kind    : FrameKind,
+    next    : Cont,
+}
+
+
+/// What a frame does with the value it is waiting for
+#[derive( Clone )]
+enum FrameKind {
+    /// Evaluates the rest of a body
+    Body        { rest: Data, env: Rc< Environment > },
+    If          { then: Data, otherwise: Option< Data >, env: Rc< Environment > },
+    /// Evaluates the body of a `cond` clause, or tests the next clauses
+    Cond        { body: Data, clauses: Data, env: Rc< Environment > },
+    /// Finds the `case` clause for the key
+    Case        { clauses: Data, env: Rc< Environment > },
+    /// Evaluates the rest of an `and` or `or`, unless the value decides it
+    AndOr       { rest: Data, env: Rc< Environment >, is_and: bool },
+    /// Adds the value to the ones evaluated so far and evaluates the rest
+    Args        { values: ProcedureArgsArr, rest: Data, env: Rc< Environment >, then: ArgsThen },
+    Define      { name: Symbol, env: Rc< Environment > },
+    Set         { name: Symbol, env: Rc< Environment > },
+    LetStar     { param: Symbol, bindings: Vec< ( Symbol, Data ) >, body: Data, env: Rc< Environment > },
+    Letrec      ( Symbol, Letrec ),
+    /// Loads the file whose name is the value
+    LoadPath    { env: Rc< Environment > },
+    /// Evaluates the rest of a file being loaded
+    Load        { data: Vec< Result< Data, SchemeError > >, path: PathBuf, env: Rc< Environment > },
+    /// Marks the body of a `dynamic-wind`, and runs `after` once it returns
+    Wind        { before: Data, after: Data },
+    /// Marks the extent of `with-exception-handler`, with the handlers installed
+    /// there, innermost first
+    Handlers    ( Data ),
+    /// Tests the clauses of a `guard` on a raised value
+    Guard       { var: Symbol, clauses: Data, env: Rc< Environment > },
+    /// Ignores the value and returns this one instead
+    Return      ( Data ),
+    /// Continues a control procedure with the values it saved
+    Control     { resume: ControlResume, saved: ProcedureArgsArr },
+}
+
+
+/// What is done with a list of evaluated expressions
+#[derive( Clone )]
+enum ArgsThen {
+    /// Applies the first value to the others
+    Apply,
+    /// Evaluates the body of a `let` with the values bound to `params`
+    Bind        { params: Vec< Symbol >, body: Data, parent: Rc< Environment > },
+}
+
+
+impl Cont {
+
+    fn push( &mut self, kind: FrameKind ) {
+        let next    = Cont( self.0.take() );
+        self.0      = Some( Rc::new( Frame { kind, next } ) );
+    }
+
+    /// Takes the innermost frame off. A frame that is shared with a captured
+    /// continuation is copied, so the continuation can be resumed again later
+    fn pop( &mut self ) -> Option< FrameKind > {
+        let frame = self.0.take()?;
+        let Frame { kind, next } = Rc::try_unwrap( frame ).unwrap_or_else( |frame| Frame { kind: frame.kind.clone(), next: frame.next.clone() } );
+        *self = next;
+
+        Some( kind )
+    }
+
+    /// The frames from the innermost one outwards
+    fn frames( &self ) -> impl Iterator< Item = Rc< Frame > > {
+        std::iter::successors( self.0.clone(), |frame| frame.next.0.clone() )
+    }
+
+    /// The files being loaded, innermost first
+    fn loading_files( &self ) -> Vec< PathBuf > {
+        self.frames()
+            .filter_map( |frame| match &frame.kind {
+                FrameKind::Load { path, .. }    => Some( path.clone() ),
+                _                               => None,
+            } )
+            .collect()
+    }
+
+    /// The `dynamic-wind` frames with their `before` and `after` thunks, innermost first
+    fn winds( &self ) -> Vec< ( Rc< Frame >, Data, Data ) > {
+        self.frames()
+            .filter_map( |frame| match &frame.kind {
+                FrameKind::Wind { before, after }   => Some( ( Rc::clone( &frame ), before.clone(), after.clone() ) ),
+                _                                   => None,
+            } )
+            .collect()
+    }
+
+    /// The exception handlers of the innermost `with-exception-handler`, as a list
+    fn handlers( &self ) -> Data {
+        self.frames()
+            .find_map( |frame| match &frame.kind {
+                FrameKind::Handlers( handlers ) => Some( handlers.clone() ),
+                _                               => None,
+            } )
+            .unwrap_or( Data::Null )
+    }
+
+    /// Adds the files being loaded to an error, so it tells where it happened
+    fn annotate( &self, error: SchemeError ) -> SchemeError {
+        self.loading_files().into_iter().fold( error, |error, path| {
+            SchemeError::InFile { file: path.display().to_string(), error: Box::new( error ) }
+        } )
+    }
+
+}
+
+
+/// Frees the frames one by one, since dropping a long chain recursively could overflow the stack
+impl Drop for Cont {
+    fn drop( &mut self ) {
+        let mut next = self.0.take();
+        while let Some( frame ) = next {
+            next = match Rc::try_unwrap( frame ) {
+                Ok( mut frame ) => frame.next.0.take(),
+                Err( _ )        => None,
+            };
+        }
+    }
+}
+
+
+impl FrameKind {
+
+    /// Continues the computation with the value the frame was waiting for
+    fn resume( self, value: Data, k: &mut Cont ) -> Result< Step, SchemeError > {
+        match self {
+            FrameKind::Body { rest, env } => {
+                Ok( eval_body( rest, env, k ) )
+            },
+            FrameKind::If { then, otherwise, env } => {
+                // Only #f is false, everything else is true
+                match ( is_false( &value ), otherwise ) {
+                    ( false, _ )            => Ok( Step::Eval( then, env ) ),
+                    ( true, Some( expr ) )  => Ok( Step::Eval( expr, env ) ),
+                    ( true, None )          => Ok( Step::Return( Data::Void ) ),
+                }
+            },
+            FrameKind::Cond { body, clauses, env } => {
+                if is_false( &value ) {
+                    return env.eval_cond_clauses( clauses, k );
+                }
+
+                // A clause without a body evaluates to the value of its test
+                if matches!( body, Data::Null ) {
+                    return Ok( Step::Return( value ) );
+                }
+
+                env.eval_clause_body( value, body, k )
+            },
+            FrameKind::Case { clauses, env } => {
+                env.eval_case_clauses( value, clauses, k )
+            },
+            FrameKind::AndOr { rest, env, is_and } => {
+                // `and` stops at the first false value, `or` at the first true one
+                if is_false( &value ) == is_and {
+                    return Ok( Step::Return( value ) );
+                }
+
+                eval_and_or( rest, env, is_and, k )
+            },
+            FrameKind::Args { mut values, rest, env, then } => {
+                values.push( value );
+                eval_args( values, rest, env, then, k )
+            },
+            FrameKind::Define { name, env } => {
+                env.insert( &name, value.clone() );
+                Ok( Step::Return( value ) )
+            },
+            FrameKind::Set { name, env } => {
+                env.set( &name, value )?;
+                Ok( Step::Return( Data::Void ) )
+            },
+            FrameKind::LetStar { param, bindings, body, env } => {
+                eval_let_star_bindings( bindings, body, Environment::with_args( &[ param ], vec![ value ], &env ), k )
+            },
+            FrameKind::Letrec( param, mut letrec ) => {
+                if letrec.is_sequential {
+                    letrec.env.insert( &param, value );
+                }
+                else {
+                    letrec.values.push( ( param, value ) );
+                }
+
+                letrec.eval_next( k )
+            },
+            FrameKind::LoadPath { env } => {
+                load_file( value, env, k )
+            },
+            FrameKind::Load { data, path, env } => {
+                load_next( data, path, env, value, k )
+            },
+            FrameKind::Wind { after, .. } => {
+                k.push( FrameKind::Return( value ) );
+                Ok( Step::Apply( after, vec![] ) )
+            },
+            FrameKind::Handlers( _ ) => {
+                Ok( Step::Return( value ) )
+            },
+            FrameKind::Guard { var, clauses, env } => {
+                Environment::with_args( &[ var ], vec![ value ], &env ).eval_cond_clauses( clauses, k )
+            },
+            FrameKind::Return( value ) => {
+                Ok( Step::Return( value ) )
+            },
+            FrameKind::Control { resume, saved } => {
+                resume( value, saved, k )
+            },
+        }
+    }
+
+}
+
+
+/// A procedure that works on the continuation of its call, not just its arguments
+type ControlProcedure   = fn( ProcedureArgsArr, &mut Cont ) -> Result< Step, SchemeError >;
+/// The rest of a control procedure, run when the value it waits for is ready
+type ControlResume      = fn( Data, ProcedureArgsArr, &mut Cont ) -> Result< Step, SchemeError >;
+
+
+/// A continuation captured by `call/cc`
+#[derive( Clone )]
+pub struct Continuation( Cont );
+
+
+impl PartialEq for Continuation {
+    fn eq( &self, other: &Continuation ) -> bool {
+        match ( &self.0 .0, &other.0 .0 ) {
+            ( Some( lhs ), Some( rhs ) )    => Rc::ptr_eq( lhs, rhs ),
+            ( lhs, rhs )                    => lhs.is_none() && rhs.is_none(),
+        }
+    }
+}
+
+
+/// `(call/cc proc)` calls `proc` with the continuation of the `call/cc` call
+fn proc_call_cc( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "call-with-current-continuation", "1", args.len() ) );
+    }
+
+    let proc = args[ 0 ].clone();
+    if !is_procedure( &proc ) {
+        return Err( SchemeError::contract_violation( "call-with-current-continuation", "(any/c . -> . any)", proc ) );
+    }
+
+    Ok( Step::Apply( proc, vec![ Data::Continuation( Continuation( k.clone() ) ) ] ) )
+}
+
+
+/// Replaces the current continuation with a captured one. The `after` thunks of the
+/// `dynamic-wind`s that are left run first, innermost first, and then the `before`
+/// thunks of the ones that are entered, outermost first
+fn continue_with( target: &Cont, args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    let value = match args.len() {
+        0 => Data::Void,
+        1 => args[ 0 ].clone(),
+        _ => return Err( SchemeError::arity_mismatch( "continuation", "0 or 1", args.len() ) ),
+    };
+
+    // The winds shared by both continuations are neither left nor entered
+    let mut exits   = k.winds();
+    let mut entries = target.winds();
+    while let ( Some( ( exit, _, _ ) ), Some( ( entry, _, _ ) ) ) = ( exits.last(), entries.last() ) {
+        if !Rc::ptr_eq( exit, entry ) {
+            break;
+        }
+
+        exits.pop();
+        entries.pop();
+    }
+
+    let thunks = exits.into_iter().map( |( _, _, after )| after )
+        .chain( entries.into_iter().rev().map( |( _, before, _ )| before ) )
+        .collect::< Vec< Data > >();
+
+    // The thunks are chained through frames on top of the target, the first one on top
+    *k = target.clone();
+    k.push( FrameKind::Return( value ) );
+    for thunk in thunks.into_iter().rev() {
+        k.push( FrameKind::Control { resume: resume_thunk, saved: vec![ thunk ] } );
+    }
+
+    Ok( Step::Return( Data::Void ) )
+}
+
+
+/// Calls the thunk saved by `continue_with`
+fn resume_thunk( _: Data, saved: ProcedureArgsArr, _: &mut Cont ) -> Result< Step, SchemeError > {
+    Ok( Step::Apply( saved[ 0 ].clone(), vec![] ) )
+}
+
+
+/// `(dynamic-wind before thunk after)` calls the three thunks in order. `before` and
+/// `after` also run whenever a continuation jumps into or out of `thunk`
+fn proc_dynamic_wind( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() != 3 {
+        return Err( SchemeError::arity_mismatch( "dynamic-wind", "3", args.len() ) );
+    }
+
+    if let Some( arg ) = args.iter().find( |arg| !is_procedure( arg ) ) {
+        return Err( SchemeError::contract_violation( "dynamic-wind", "(-> any)", arg ) );
+    }
+
+    let before = args[ 0 ].clone();
+    k.push( FrameKind::Control { resume: resume_dynamic_wind, saved: args } );
+
+    Ok( Step::Apply( before, vec![] ) )
+}
+
+
+/// Calls the thunk of a `dynamic-wind` once `before` has returned
+fn resume_dynamic_wind( _: Data, saved: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    k.push( FrameKind::Wind { before: saved[ 0 ].clone(), after: saved[ 2 ].clone() } );
+
+    Ok( Step::Apply( saved[ 1 ].clone(), vec![] ) )
+}
+
+
+/// `(values v ...)` returns its arguments as the results of the expression.
+/// A single value is returned as it is
+fn proc_values( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    match args.as_slice() {
+        [ value ]   => Ok( value.clone() ),
+        _           => Ok( Data::Values( args.as_slice().into() ) ),
+    }
+}
+
+
+/// `(call-with-values producer consumer)` calls `consumer` with the values that `producer` returns
+fn proc_call_with_values( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "call-with-values", "2", args.len() ) );
+    }
+
+    if let Some( arg ) = args.iter().find( |arg| !is_procedure( arg ) ) {
+        return Err( SchemeError::contract_violation( "call-with-values", "procedure?", arg ) );
+    }
+
+    let producer = args[ 0 ].clone();
+    k.push( FrameKind::Control { resume: resume_call_with_values, saved: vec![ args[ 1 ].clone() ] } );
+
+    Ok( Step::Apply( producer, vec![] ) )
+}
+
+
+/// Calls the consumer of `call-with-values` once the producer has returned
+fn resume_call_with_values( values: Data, saved: ProcedureArgsArr, _: &mut Cont ) -> Result< Step, SchemeError > {
+    let args = match values {
+        Data::Values( values )  => values.to_vec(),
+        value                   => vec![ value ],
+    };
+
+    Ok( Step::Apply( saved[ 0 ].clone(), args ) )
+}
+
+
+/// Applies the procedure that is the value to the saved arguments
+fn resume_apply_to( proc: Data, saved: ProcedureArgsArr, _: &mut Cont ) -> Result< Step, SchemeError > {
+    Ok( Step::Apply( proc, saved ) )
+}
+
+
+/// `(apply proc arg ... list)` calls `proc` with the `arg`s followed by the elements of `list`
+fn proc_apply( mut args: ProcedureArgsArr, _: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() < 2 {
+        return Err( SchemeError::arity_mismatch( "apply", "at least 2", args.len() ) );
+    }
+
+    let proc = args.remove( 0 );
+    if !is_procedure( &proc ) {
+        return Err( SchemeError::contract_violation( "apply", "a procedure that can be applied to arguments", proc ) );
+    }
+
+    let list = args.pop().unwrap_or( Data::Null );
+    if !is_list( &list ) {
+        return Err( SchemeError::contract_violation( "apply", "list?", list ) );
+    }
+
+    args.extend( list_elements( &list ) );
+    Ok( Step::Apply( proc, args ) )
+}
+
+
+/// Checks the procedure and the lists given to `map` and the procedures like it.
+/// The lists must all have the same length
+fn check_proc_and_lists( proc_name: &str, proc: &Data, lists: &[ Data ] ) -> Result< (), SchemeError > {
+    if !is_procedure( proc ) {
+        return Err( SchemeError::contract_violation( proc_name, "a procedure that can be applied to arguments", proc ) );
+    }
+
+    if let Some( list ) = lists.iter().find( |list| !is_list( list ) ) {
+        return Err( SchemeError::contract_violation( proc_name, "list?", list ) );
+    }
+
+    let len = list_length( &lists[ 0 ] );
+    for list in &lists[ 1.. ] {
+        let other_len = list_length( list );
+        if other_len != len {
+            return Err( SchemeError::contract_violation( proc_name, "all lists must have same size", format!( "{} and {}", len, other_len ) ) );
+        }
+    }
+
+    Ok( () )
+}
+
+
+/// The first elements of the lists, leaving the rest of each list in its place,
+/// or `None` once the lists are exhausted
+fn next_elements( lists: &mut [ Data ] ) -> Option< ProcedureArgsArr > {
+    lists.iter_mut()
+        .map( |list| {
+            let pair = match list {
+                Data::Pair( pair )  => Rc::clone( pair ),
+                _                   => return None,
+            };
+
+            *list = pair.cdr();
+            Some( pair.car() )
+        } )
+        .collect()
+}
+
+
+/// `(map proc list ...)` is the list of the results of `proc` on the elements of the lists
+fn proc_map( mut args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() < 2 {
+        return Err( SchemeError::arity_mismatch( "map", "at least 2", args.len() ) );
+    }
+
+    check_proc_and_lists( "map", &args[ 0 ], &args[ 1.. ] )?;
+
+    args.insert( 1, Data::Null );
+    map_next( args, k )
+}
+
+
+/// Applies the procedure of a `map` to the next elements. `saved` holds the procedure,
+/// the results so far in reverse order and the rest of the lists
+fn map_next( mut saved: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    match next_elements( &mut saved[ 2.. ] ) {
+        Some( args ) => {
+            let proc = saved[ 0 ].clone();
+            k.push( FrameKind::Control { resume: resume_map, saved } );
+            Ok( Step::Apply( proc, args ) )
+        },
+        None => {
+            Ok( Step::Return( reverse_list( &saved[ 1 ] ) ) )
+        },
+    }
+}
+
+
+/// The results are kept in a list rather than a vector, so resuming a continuation
+/// captured inside `map` doesn't change the results of the earlier run
+fn resume_map( value: Data, mut saved: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    saved[ 1 ] = new_pair_data( value, saved[ 1 ].clone() );
+    map_next( saved, k )
+}
+
+
+/// `(for-each proc list ...)` calls `proc` on the elements of the lists in order
+fn proc_for_each( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() < 2 {
+        return Err( SchemeError::arity_mismatch( "for-each", "at least 2", args.len() ) );
+    }
+
+    check_proc_and_lists( "for-each", &args[ 0 ], &args[ 1.. ] )?;
+
+    for_each_next( args, k )
+}
+
+
+fn for_each_next( mut saved: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    match next_elements( &mut saved[ 1.. ] ) {
+        Some( args ) => {
+            let proc = saved[ 0 ].clone();
+            k.push( FrameKind::Control { resume: resume_for_each, saved } );
+            Ok( Step::Apply( proc, args ) )
+        },
+        None => {
+            Ok( Step::Return( Data::Void ) )
+        },
+    }
+}
+
+
+fn resume_for_each( _: Data, saved: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    for_each_next( saved, k )
+}
+
+
+/// `(filter pred list)` is the list of the elements for which `pred` is true
+fn proc_filter( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "filter", "2", args.len() ) );
+    }
+
+    check_proc_and_lists( "filter", &args[ 0 ], &args[ 1.. ] )?;
+
+    filter_next( vec![ args[ 0 ].clone(), Data::Null, args[ 1 ].clone(), Data::Void ], k )
+}
+
+
+/// Tests the next element of a `filter`. `saved` holds the predicate, the elements
+/// kept so far in reverse order, the rest of the list and the element being tested
+fn filter_next( mut saved: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    match next_elements( &mut saved[ 2..3 ] ) {
+        Some( args ) => {
+            let pred    = saved[ 0 ].clone();
+            saved[ 3 ]  = args[ 0 ].clone();
+            k.push( FrameKind::Control { resume: resume_filter, saved } );
+            Ok( Step::Apply( pred, args ) )
+        },
+        None => {
+            Ok( Step::Return( reverse_list( &saved[ 1 ] ) ) )
+        },
+    }
+}
+
+
+fn resume_filter( value: Data, mut saved: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if !is_false( &value ) {
+        saved[ 1 ] = new_pair_data( saved[ 3 ].clone(), saved[ 1 ].clone() );
+    }
+
+    filter_next( saved, k )
+}
+
+
+/// `(fold-left proc init list ...)` calls `(proc acc elem ...)` on the elements from
+/// left to right, where `acc` starts as `init` and then is the previous result
+fn proc_fold_left( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() < 3 {
+        return Err( SchemeError::arity_mismatch( "fold-left", "at least 3", args.len() ) );
+    }
+
+    check_proc_and_lists( "fold-left", &args[ 0 ], &args[ 2.. ] )?;
+
+    fold_next( args, true, k )
+}
+
+
+/// `(fold-right proc init list ...)` calls `(proc elem ... acc)` on the elements from
+/// right to left, where `acc` starts as `init` and then is the previous result
+fn proc_fold_right( mut args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() < 3 {
+        return Err( SchemeError::arity_mismatch( "fold-right", "at least 3", args.len() ) );
+    }
+
+    check_proc_and_lists( "fold-right", &args[ 0 ], &args[ 2.. ] )?;
+
+    for list in &mut args[ 2.. ] {
+        *list = reverse_list( list );
+    }
+
+    fold_next( args, false, k )
+}
+
+
+/// `(reduce proc default list)` folds the list with `(proc elem acc)` starting from
+/// its first element, or is `default` if the list is empty
+fn proc_reduce( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() != 3 {
+        return Err( SchemeError::arity_mismatch( "reduce", "3", args.len() ) );
+    }
+
+    check_proc_and_lists( "reduce", &args[ 0 ], &args[ 2.. ] )?;
+
+    match &args[ 2 ] {
+        Data::Pair( pair )  => fold_next( vec![ args[ 0 ].clone(), pair.car(), pair.cdr() ], false, k ),
+        _                   => Ok( Step::Return( args[ 1 ].clone() ) ),
+    }
+}
+
+
+/// Applies the procedure of a fold to the accumulated value and the next elements.
+/// `saved` holds the procedure, the accumulated value and the rest of the lists
+fn fold_next( mut saved: ProcedureArgsArr, is_acc_first: bool, k: &mut Cont ) -> Result< Step, SchemeError > {
+    let mut args = match next_elements( &mut saved[ 2.. ] ) {
+        Some( args )    => args,
+        None            => return Ok( Step::Return( saved[ 1 ].clone() ) ),
+    };
+
+    if is_acc_first {
+        args.insert( 0, saved[ 1 ].clone() );
+    }
+    else {
+        args.push( saved[ 1 ].clone() );
+    }
+
+    let proc    = saved[ 0 ].clone();
+    let resume  = if is_acc_first { resume_fold_left } else { resume_fold_right };
+    k.push( FrameKind::Control { resume, saved } );
+
+    Ok( Step::Apply( proc, args ) )
+}
+
+
+fn resume_fold_left( value: Data, mut saved: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    saved[ 1 ] = value;
+    fold_next( saved, true, k )
+}
+
+
+fn resume_fold_right( value: Data, mut saved: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    saved[ 1 ] = value;
+    fold_next( saved, false, k )
+}
+
+
+/// `(member x list [is-equal?])` is the first pair of `list` whose car is equal to `x`, or #f
+fn proc_member( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    search_list( args, "member", false, k )
+}
+
+
+/// `(assoc x alist [is-equal?])` is the first pair of `alist` whose car is equal to `x`, or #f
+fn proc_assoc( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    search_list( args, "assoc", true, k )
+}
+
+
+/// Searches a list for `x`, comparing with `equal?` unless a procedure is given
+/// to compare with. The elements of an association list are compared by their keys
+fn search_list( args: ProcedureArgsArr, proc_name: &str, is_assoc: bool, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() != 2 && args.len() != 3 {
+        return Err( SchemeError::arity_mismatch( proc_name, "2 to 3", args.len() ) );
+    }
+
+    check_search_list( proc_name, &args[ 1 ], is_assoc )?;
+
+    if args.len() == 2 {
+        return Ok( Step::Return( find_in_list( &args[ 0 ], &args[ 1 ], is_assoc, |lhs, rhs| lhs == rhs ) ) );
+    }
+
+    if !is_procedure( &args[ 2 ] ) {
+        return Err( SchemeError::contract_violation( proc_name, "(any/c any/c . -> . any/c)", &args[ 2 ] ) );
+    }
+
+    search_next( vec![ args[ 0 ].clone(), args[ 2 ].clone(), args[ 1 ].clone() ], is_assoc, k )
+}
+
+
+/// Checks the list given to `member`, `assoc` and the procedures like them.
+/// An association list must only hold pairs
+fn check_search_list( proc_name: &str, list: &Data, is_assoc: bool ) -> Result< (), SchemeError > {
+    if !is_list( list ) {
+        return Err( SchemeError::contract_violation( proc_name, "list?", list ) );
+    }
+
+    if is_assoc && list_elements( list ).iter().any( |elem| !is_pair( elem ) ) {
+        return Err( SchemeError::contract_violation( proc_name, "(listof pair?)", list ) );
+    }
+
+    Ok( () )
+}
+
+
+/// The first pair of a list whose element is the same as `x`, or with `is_assoc`
+/// the first element whose key is, or #f if there is none
+fn find_in_list( x: &Data, list: &Data, is_assoc: bool, is_same: fn( &Data, &Data ) -> bool ) -> Data {
+    let mut rest = list.clone();
+    while let Data::Pair( pair ) = rest {
+        let elem = pair.car();
+        if is_same( x, &search_key( &elem, is_assoc ) ) {
+            return if is_assoc { elem } else { Data::Pair( pair ) };
+        }
+
+        rest = pair.cdr();
+    }
+
+    Data::Bool( false )
+}
+
+
+/// The part of a list element that `member` or `assoc` compares
+fn search_key( elem: &Data, is_assoc: bool ) -> Data {
+    match elem {
+        Data::Pair( pair ) if is_assoc  => pair.car(),
+        _                               => elem.clone(),
+    }
+}
+
+
+/// Compares the next element with the procedure given to `member` or `assoc`.
+/// `saved` holds the value searched for, the procedure and the rest of the list
+fn search_next( saved: ProcedureArgsArr, is_assoc: bool, k: &mut Cont ) -> Result< Step, SchemeError > {
+    let key = match &saved[ 2 ] {
+        Data::Pair( pair )  => search_key( &pair.car(), is_assoc ),
+        _                   => return Ok( Step::Return( Data::Bool( false ) ) ),
+    };
+
+    let ( x, is_equal ) = ( saved[ 0 ].clone(), saved[ 1 ].clone() );
+    let resume          = if is_assoc { resume_assoc } else { resume_member };
+    k.push( FrameKind::Control { resume, saved } );
+
+    Ok( Step::Apply( is_equal, vec![ x, key ] ) )
+}
+
+
+fn resume_member( value: Data, saved: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    resume_search( value, saved, false, k )
+}
+
+
+fn resume_assoc( value: Data, saved: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    resume_search( value, saved, true, k )
+}
+
+
+fn resume_search( value: Data, mut saved: ProcedureArgsArr, is_assoc: bool, k: &mut Cont ) -> Result< Step, SchemeError > {
+    let pair = match &saved[ 2 ] {
+        Data::Pair( pair )  => Rc::clone( pair ),
+        _                   => unreachable!(),
+    };
+
+    if !is_false( &value ) {
+        return Ok( Step::Return( if is_assoc { pair.car() } else { Data::Pair( pair ) } ) );
+    }
+
+    saved[ 2 ] = pair.cdr();
+    search_next( saved, is_assoc, k )
+}
+
+
+impl ErrorObject {
+
+    /// The condition for an error of a built-in procedure. Its message is the whole error text
+    fn from_error( error: SchemeError ) -> ErrorObject {
+        ErrorObject { message: Data::String( error.to_string().into() ), irritants: Data::Null, error }
+    }
+
+}
+
+
+/// Calls the current exception handler with `obj`. The handler runs with the outer
+/// handlers installed. If it returns, its value is the value of `raise-continuable`,
+/// but returning from the handler of `raise` is an error
+fn raise( obj: Data, is_continuable: bool, k: &mut Cont ) -> Result< Step, SchemeError > {
+    let ( handler, outer ) = match k.handlers() {
+        Data::Pair( pair ) => ( pair.car(), pair.cdr() ),
+        _ => {
+            return match obj {
+                Data::ErrorObject( error )  => Err( error.error.clone() ),
+                _                           => Err( SchemeError::Uncaught { value: obj.to_string() } ),
+            };
+        },
+    };
+
+    k.push( FrameKind::Handlers( outer ) );
+    if !is_continuable {
+        k.push( FrameKind::Control { resume: resume_raise, saved: vec![] } );
+    }
+
+    Ok( Step::Apply( handler, vec![ obj ] ) )
+}
+
+
+/// Runs when the handler of a non-continuable `raise` returns
+fn resume_raise( _: Data, _: ProcedureArgsArr, _: &mut Cont ) -> Result< Step, SchemeError > {
+    Err( SchemeError::bad_context( "raise", "exception handler returned from a non-continuable exception" ) )
+}
+
+
+fn proc_raise( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "raise", "1", args.len() ) );
+    }
+
+    raise( args[ 0 ].clone(), false, k )
+}
+
+
+fn proc_raise_continuable( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "raise-continuable", "1", args.len() ) );
+    }
+
+    raise( args[ 0 ].clone(), true, k )
+}
+
+
+/// `(error message irritant ...)` raises an error object. The message can also
+/// start with the name of the procedure that failed, as in `(error 'who "message")`.
+/// Then the message is a format string, which the arguments after it fill in
+fn proc_error( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    let ( message, irritants, is_formatted ) = match args.as_slice() {
+        [ Data::String( message ), irritants @ .. ]                         => ( message.to_string(), irritants, false ),
+        // Racket style, naming the procedure that failed
+        [ Data::Symbol( who ), Data::String( format ), irritants @ .. ]     => ( format!( "{}: {}", who, format_message( format, irritants )? ), irritants, true ),
+        [ Data::Symbol( who ), irritants @ .. ]                             => ( who.to_string(), irritants, false ),
+        [ arg, .. ]                                                         => return Err( SchemeError::contract_violation( "error", "(or/c string? symbol?)", arg ) ),
+        []                                                                  => return Err( SchemeError::arity_mismatch( "error", "at least 1", "0" ) ),
+    };
+
+    // Unhandled, the error shows the irritants after the message, unless they are already in it
+    let mut text = message.clone();
+    for irritant in irritants.iter().filter( |_| !is_formatted ) {
+        text += &format!( " {}", irritant.printed( PrintMode::Write ) );
+    }
+
+    let error = ErrorObject {
+        message     : Data::String( message.into() ),
+        irritants   : new_list_data( irritants.to_vec() ),
+        error       : SchemeError::Failure { message: text },
+    };
+
+    raise( Data::ErrorObject( Rc::new( error ) ), false, k )
+}
+
+
+/// Fills in a format string the way Racket's `format` does: `~a` displays the next
+/// argument, `~s` writes it and `~v` prints it, `~~` is a tilde and `~n` a newline.
+/// Every argument must be used
+fn format_message( format: &str, args: &[ Data ] ) -> Result< String, SchemeError > {
+    let mut res     = String::new();
+    let mut used    = 0;
+    let mut chars   = format.chars();
+
+    while let Some( ch ) = chars.next() {
+        if ch != '~' {
+            res.push( ch );
+            continue;
+        }
+
+        let mode = match chars.next() {
+            Some( 'a' | 'A' )       => PrintMode::Display,
+            Some( 's' | 'S' )       => PrintMode::Write,
+            Some( 'v' | 'V' )       => PrintMode::Print,
+            Some( '~' )             => { res.push( '~' ); continue; },
+            Some( 'n' | 'N' | '%' ) => { res.push( '\n' ); continue; },
+            _                       => return Err( SchemeError::Failure { message: format!( "error: ill-formed pattern string\n  pattern string: {}", Data::String( format.into() ) ) } ),
+        };
+
+        if let Some( arg ) = args.get( used ) {
+            res += &arg.printed( mode ).to_string();
+        }
+        used += 1;
+    }
+
+    if used != args.len() {
+        return Err( SchemeError::Failure { message: format!( "error: format string requires {} arguments, given {}", used, args.len() ) } );
+    }
+
+    Ok( res )
+}
+
+
+/// `(with-exception-handler handler thunk)` calls `thunk` with `handler` installed
+fn proc_with_exception_handler( args: ProcedureArgsArr, k: &mut Cont ) -> Result< Step, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "with-exception-handler", "2", args.len() ) );
+    }
+
+    if let Some( arg ) = args.iter().find( |arg| !is_procedure( arg ) ) {
+        return Err( SchemeError::contract_violation( "with-exception-handler", "procedure?", arg ) );
+    }
+
+    k.push( FrameKind::Handlers( new_pair_data( args[ 0 ].clone(), k.handlers() ) ) );
+
+    Ok( Step::Apply( args[ 1 ].clone(), vec![] ) )
+}
+
+
+fn proc_is_error_object( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "error-object?", "1", args.len() ) );
+    }
+
+    Ok( Data::Bool( matches!( args[ 0 ], Data::ErrorObject( _ ) ) ) )
+}
+
+
+fn proc_error_object_message( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "error-object-message", "1", args.len() ) );
+    }
+
+    match &args[ 0 ] {
+        Data::ErrorObject( error )  => Ok( error.message.clone() ),
+        _                           => Err( SchemeError::contract_violation( "error-object-message", "error-object?", &args[ 0 ] ) ),
+    }
+}
+
+
+fn proc_error_object_irritants( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "error-object-irritants", "1", args.len() ) );
+    }
+
+    match &args[ 0 ] {
+        Data::ErrorObject( error )  => Ok( error.irritants.clone() ),
+        _                           => Err( SchemeError::contract_violation( "error-object-irritants", "error-object?", &args[ 0 ] ) ),
+    }
+}
+
+
+impl Data {
+
+    /// Splits a list like `(quote x)` or `(unquote x)`, which the reader abbreviates
+    /// as `'x` and `,x`, into the reader prefix and `x`
+    fn quote_form( &self ) -> Option< ( &'static str, Data ) > {
+        let pair = match self {
+            Data::Pair( pair )  => pair,
+            _                   => return None,
+        };
+
+        let prefix = match &*pair.car.borrow() {
+            Data::Symbol( sym ) => QUOTE_PREFIXES.iter().find( |( name, _ )| *name == sym.as_str() )?.1,
+            _                   => return None,
+        };
+
+        match &*pair.cdr.borrow() {
+            Data::Pair( rest ) if matches!( *rest.cdr.borrow(), Data::Null ) => Some( ( prefix, rest.car() ) ),
+            _ => None,
+        }
+    }
+
+    /// Wraps the value so that formatting it prints it in the given mode
+    pub fn printed( &self, mode: PrintMode ) -> Printed< '_ > {
+        Printed { data: self, mode }
+    }
+
+    /// `is_quoted` tells whether the value is part of a datum that already got its leading quote
+    fn fmt_data( &self, f: &mut fmt::Formatter<'_>, mode: PrintMode, is_quoted: bool ) -> fmt::Result {
+        self.fmt_labeled( f, mode, is_quoted, &mut DatumLabels::of( self ) )
+    }
+
+    /// Formats the value, writing the pairs in `labels` as `#n=` the first time
+    /// and as `#n#` after that, so that circular lists are printed in finite text
+    fn fmt_labeled( &self, f: &mut fmt::Formatter<'_>, mode: PrintMode, is_quoted: bool, labels: &mut DatumLabels ) -> fmt::Result {
+        if let Data::Pair( pair ) = self {
+            match labels.labels.get_mut( &Rc::as_ptr( pair ) ) {
+                Some( Some( label ) )   => return write!( f, "#{}#", label ),
+                Some( label )           => {
+                    *label = Some( labels.next );
+                    write!( f, "#{}=", labels.next )?;
+                    labels.next += 1;
+                },
+                None                    => {},
+            }
+        }
+
+        // Print mode shows symbols and lists the way they would be written in code
+        let needs_quote = mode == PrintMode::Print && !is_quoted && matches!( self, Data::Symbol( _ ) | Data::Null | Data::Pair( _ ) );
+        if needs_quote {
+            if let Err( e ) = write!( f, "'" ) {
+                eprintln!( "{}", e );
+                return Err( e );
+            }
+        }
+        let is_quoted = is_quoted || needs_quote;
+
+        match self {
+            Data::Void => {
+                // The REPL shows nothing for void, but it is visible inside lists
+                if is_quoted { write!( f, "#<void>" ) } else { write!( f, "" ) }
+            },
+            Data::Bool( value ) => {
+                write!( f, "{}", if *value { "#t" } else { "#f" } )
+            },
+            Data::Number( num ) => {
+                write!( f, "{}", num )
+            },
+            Data::Char( ch ) => {
+                if mode == PrintMode::Display {
+                    write!( f, "{}", ch )
+                }
+                else if let Some( ( name, _ ) ) = CHAR_NAMES.iter().find( |( _, named )| named == ch ) {
+                    write!( f, "#\\{}", name )
+                }
+                else {
+                    write!( f, "#\\{}", ch )
+                }
+            },
+            Data::String( string ) => {
+                if mode == PrintMode::Display {
+                    write!( f, "{}", string )
+                }
+                else {
+                    write_string_literal( f, string )
+                }
+            },
+            Data::Symbol( sym ) => {
+                // Racket writes symbols that wouldn't read back as themselves between bars
+                if mode != PrintMode::Display && !is_plain_identifier( sym.as_str() ) {
+                    write!( f, "{}", bar_symbol( sym.as_str() ) )
+                }
+                else {
+                    write!( f, "{}", sym )
+                }
+            },
+            Data::Null => {
+                write!( f, "()" )
+            },
+            Data::Pair( pair ) => {
+                // The abbreviation would leave out the label of the second pair
+                if let Some( ( prefix, quoted ) ) = self.quote_form().filter( |_| mode == PrintMode::Print && !labels.has_label( &pair.cdr() ) ) {
+                    if let Err( e ) = write!( f, "{}", prefix ) {
+                        eprintln!( "{}", e );
+                        return Err( e );
+                    }
+                    return quoted.fmt_labeled( f, mode, true, labels );
+                }
+
+                if let Err( e ) = write!( f, "(" ) {
+                    eprintln!( "{}", e );
+                    return Err( e );
+                }
+
+                if let Err( e ) = pair.car.borrow().fmt_labeled( f, mode, true, labels ) {
+                    eprintln!( "{}", e );
+                    return Err( e );
+                }
+
+                // The rest of the list is walked here rather than printed recursively,
+                // so that its pairs don't get parentheses of their own. A labeled
+                // pair is printed as the tail, so the label has somewhere to go
+                let mut rest = pair.cdr();
+                loop {
+                    match rest {
+                        Data::Pair( next ) if !labels.has_label( &rest ) => {
+                            if let Err( e ) = write!( f, " " ) {
+                                eprintln!( "{}", e );
+                                return Err( e );
+                            }
+                            if let Err( e ) = next.car.borrow().fmt_labeled( f, mode, true, labels ) {
+                                eprintln!( "{}", e );
+                                return Err( e );
+                            }
+                            rest = next.cdr();
+                        },
+                        Data::Null => {
+                            break;
+                        },
+                        tail => {
+                            if let Err( e ) = write!( f, " . " ) {
+                                eprintln!( "{}", e );
+                                return Err( e );
+                            }
+                            if let Err( e ) = tail.fmt_labeled( f, mode, true, labels ) {
+                                eprintln!( "{}", e );
+                                return Err( e );
+                            }
+                            break;
+                        },
+                    }
+                }
+
+                write!( f, ")" )
+            },
+            Data::Procedure( name, _ ) => {
+                write!( f, "#<procedure:{}>", name )
+            },
+            Data::Lambda( lambda ) => {
+                match &lambda.name {
+                    Some( name )    => write!( f, "#<procedure:{}>", name ),
+                    None            => write!( f, "#<lambda>" ),
+                }
+            },
+            Data::Macro( _ ) => {
+                write!( f, "#<macro>" )
+            },
+            Data::Control( name, _ ) => {
+                write!( f, "#<procedure:{}>", name )
+            },
+            Data::Continuation( _ ) => {
+                write!( f, "#<continuation>" )
+            },
+            Data::ErrorObject( _ ) => {
+                write!( f, "#<error-object>" )
+            },
+            Data::Values( values ) => {
+                // Each value goes on a line of its own, like the REPL shows them
+                for ( i, value ) in values.iter().enumerate() {
+                    if i > 0 {
+                        writeln!( f )?;
+                    }
+                    value.fmt_labeled( f, mode, is_quoted, labels )?;
+                }
+                Ok( () )
+            },
+        }
+    }
+
+}
+
+
+impl fmt::Display for Data {
+    fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+        self.fmt_data( f, PrintMode::Print, false )
+    }
+}
+
+
+/// The pairs of a value that are printed with a label, and the label each one got, if it was printed yet
+struct DatumLabels {
+    labels  : HashMap< *const Pair, Option< usize > >,
+    next    : usize,
+}
+
+
+impl DatumLabels {
+
+    /// Labels the pairs that can be reached from themselves. Pairs that are only
+    /// shared are printed in full each time, like Racket's `write` does
+    fn of( data: &Data ) -> DatumLabels {
+        let mut labels  = HashMap::new();
+        let mut visited = HashSet::new();
+        let mut on_path = HashSet::new();
+
+        // The pairs are walked depth first without recursion, since lists can be long.
+        // The flag tells whether the pair is entered, or left once its car and cdr are done
+        let mut stack = vec![ ( data.clone(), true ) ];
+        while let Some( ( data, is_entered ) ) = stack.pop() {
+            let pair = match data {
+                Data::Pair( pair )  => pair,
+                _                   => continue,
+            };
+
+            let ptr = Rc::as_ptr( &pair );
+            if !is_entered {
+                on_path.remove( &ptr );
+            }
+            else if on_path.contains( &ptr ) {
+                labels.insert( ptr, None );
+            }
+            else if visited.insert( ptr ) {
+                on_path.insert( ptr );
+                stack.push( ( Data::Pair( Rc::clone( &pair ) ), false ) );
+                stack.push( ( pair.cdr(), true ) );
+                stack.push( ( pair.car(), true ) );
+            }
+        }
+
+        DatumLabels { labels, next: 0 }
+    }
+
+    fn has_label( &self, data: &Data ) -> bool {
+        matches!( data, Data::Pair( pair ) if self.labels.contains_key( &Rc::as_ptr( pair ) ) )
+    }
+
+}
+
+
+/// How a value is turned into text. `Print` is what the REPL shows, `Write`
+/// leaves out the leading quotes and `Display` also shows strings as raw text
+#[derive( Clone, Copy, PartialEq, Debug )]
+pub enum PrintMode {
+    Print,
+    Write,
+    Display,
+}
+
+
+pub struct Printed< 'a > {
+    data    : &'a Data,
+    mode    : PrintMode,
+}
+
+
+impl fmt::Display for Printed< '_ > {
+    fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+        self.data.fmt_data( f, self.mode, self.mode != PrintMode::Print )
+    }
+}
+
+
+/// Writes the string in double quotes, escaped so that reading it back gives the same string
+fn write_string_literal( f: &mut fmt::Formatter<'_>, string: &str ) -> fmt::Result {
+    write!( f, "\"" )?;
+
+    for ch in string.chars() {
+        match ch {
+            '"'                     => write!( f, "\\\"" )?,
+            '\\'                   => write!( f, "\\\\" )?,
+            '\n'                    => write!( f, "\\n" )?,
+            '\t'                    => write!( f, "\\t" )?,
+            '\r'                    => write!( f, "\\r" )?,
+            _ if ch.is_control()    => write!( f, "\\x{:x};", ch as u32 )?,
+            _                       => write!( f, "{}", ch )?,
+        }
+    }
+
+    write!( f, "\"" )
+}
+
+
+/// The forms that the reader has abbreviations for
+const QUOTE_PREFIXES: [ ( &str, &str ); 4 ] = [
+    ( "quote"               , "'"   ),
+    ( "quasiquote"          , "`"   ),
+    ( "unquote"             , ","   ),
+    ( "unquote-splicing"    , ",@"  ),
+];
+
+
+/// The characters written by name, e.g. `#\space`
+pub const CHAR_NAMES: [ ( &str, char ); 10 ] = [
+    ( "nul"         , '\0'     ),
+    ( "backspace"   , '\x08'   ),
+    ( "tab"         , '\t'     ),
+    ( "newline"     , '\n'     ),
+    ( "vtab"        , '\x0b'   ),
+    ( "page"        , '\x0c'   ),
+    ( "return"      , '\r'     ),
+    ( "space"       , ' '      ),
+    ( "rubout"      , '\x7f'   ),
+    ( "alarm"       , '\x07'   ),
+];
+
+
+pub fn is_false( data: &Data ) -> bool {
+    matches!( data, Data::Bool( false ) )
+}
+
+
+/// Compares like `eqv?`: pairs and strings are only equal to themselves, and
+/// numbers must also agree in exactness
+fn is_eqv( lhs: &Data, rhs: &Data ) -> bool {
+    match ( lhs, rhs ) {
+        ( Data::Pair( lhs ), Data::Pair( rhs ) )        => Rc::ptr_eq( lhs, rhs ),
+        ( Data::String( lhs ), Data::String( rhs ) )    => Rc::ptr_eq( lhs, rhs ),
+        ( Data::Number( lhs ), Data::Number( rhs ) )    => lhs.is_exact() == rhs.is_exact() && lhs == rhs,
+        _                                               => lhs == rhs,
+    }
+}
+
+
+fn is_procedure( data: &Data ) -> bool {
+    matches!( data, Data::Procedure( .. ) | Data::Lambda( _ ) | Data::Control( .. ) | Data::Continuation( _ ) )
+}
+
+
+/// Takes the symbol out of an identifier that is part of a special form
+fn identifier_arg< 'a >( form: &str, data: &'a Data ) -> Result< &'a Symbol, SchemeError > {
+    match data {
+        Data::Symbol( sym ) => Ok( sym ),
+        _                   => Err( SchemeError::bad_syntax( form, "identifier?", data ) ),
+    }
+}
+
+
+/// Parses the parameters and the body of a lambda. The parameters are a list of
+/// identifiers, which may end in `. rest`, or a single identifier for all arguments
+fn parse_lambda_clause( form: &str, params: &Data, body: &[ Data ] ) -> Result< LambdaClause, SchemeError > {
+    let ( items, tail ) = list_parts( params );
+    let rest            = match &tail {
+        Data::Null          => None,
+        Data::Symbol( sym ) => Some( sym.clone() ),
+        _                   => return Err( SchemeError::bad_syntax( form, "a list of parameters and a body", "" ) ),
+    };
+
+    let params = items.iter()
+        .map( |param| identifier_arg( form, param ).cloned() )
+        .collect::< Result< Vec< Symbol >, SchemeError > >()?;
+
+    for ( index, param ) in params.iter().chain( &rest ).enumerate() {
+        if params[ .. index.min( params.len() ) ].contains( param ) {
+            return Err( SchemeError::bad_syntax( form, "distinct identifiers", param ) );
+        }
+    }
+
+    Ok( LambdaClause { params, rest, body: new_list_data( body.to_vec() ) } )
+}
+
+
+/// Splits the `((var init) ...)` bindings found at `index` of a let form into
+/// the variables and their initial expressions. Only `let*` may bind a variable twice.
+fn parse_bindings( form_name: &str, form: &[ Data ], index: usize ) -> Result< ( Vec< Symbol >, ListValuesArr ), SchemeError > {
+    if form.len() <= index + 1 {
+        return Err( SchemeError::bad_syntax( form_name, "bindings and a body", "" ) );
+    }
+
+    let bindings = &form[ index ];
+    if !is_list( bindings ) {
+        return Err( SchemeError::bad_syntax( form_name, "a sequence of bindings", "" ) );
+    }
+
+    let bindings    = list_elements( bindings );
+    let mut params  = Vec::with_capacity( bindings.len() );
+    let mut inits   = Vec::with_capacity( bindings.len() );
+    for binding in &bindings {
+        let ( param, init ) = match list_elements( binding ).as_slice() {
+            [ Data::Symbol( param ), init ] if is_list( binding )   => ( param.clone(), init.clone() ),
+            _                                                       => return Err( SchemeError::bad_syntax( form_name, "an identifier and an expression for a binding", "" ) ),
+        };
+
+        if form_name != "let*" && params.contains( &param ) {
+            return Err( SchemeError::bad_syntax( form_name, "distinct identifiers", param ) );
+        }
+
+        params.push( param );
+        inits.push( init );
+    }
+
+    Ok( ( params, inits ) )
+}
+
+
+fn proc_arithmetic_inner( args: &ProcedureArgsArr, is_mul: bool, is_inv: bool ) -> Result< Data, SchemeError > {
+    let proc_name   = if is_mul { if is_inv { "/" } else { "*" } } else { if is_inv { "-" } else { "+" } };
+
+    if is_inv && args.is_empty() {
+        return Err( SchemeError::arity_mismatch( proc_name, "at least 1", "0" ) );
+    }
+
+    // Two fixnums, the usual case, don't need the list of numbers
+    if let [ Data::Number( lhs @ Number::Int( _ ) ), Data::Number( rhs @ Number::Int( _ ) ) ] = args.as_slice() {
+        if !is_mul {
+            return Ok( if is_inv { lhs.sub( rhs ) } else { lhs.add( rhs ) }.into_data() );
+        }
+    }
+
+    let nums = number_args( proc_name, args )?;
+
+    // (- x) and (/ x) apply the operation to the identity element and x
+    let identity            = Number::Int( if is_mul { 1 } else { 0 } );
+    let ( mut res, rest )   = if is_inv && nums.len() > 1 { ( nums[ 0 ].clone(), &nums[ 1.. ] ) } else { ( identity, &nums[ .. ] ) };
+
+    for num in rest {
+        res = match ( is_mul, is_inv ) {
+            ( false, false )    => res.add( num ),
+            ( false, true )     => res.sub( num ),
+            ( true, false )     => res.mul( num ),
+            ( true, true )      => res.div( num ).ok_or_else( || SchemeError::DivisionByZero { procedure: proc_name.to_string() } )?,
+        };
+    }
+
+    Ok( res.into_data() )
+}
+
+
+/// Takes the numbers out of the arguments, failing on the first one that isn't a number
+fn number_args( proc_name: &str, args: &ProcedureArgsArr ) -> Result< Vec< Number >, SchemeError > {
+    args.iter()
+        .map( |arg| Number::from_data( arg ).ok_or_else( || SchemeError::contract_violation( proc_name, "number?", arg ) ) )
+        .collect()
+}
+
+
+fn proc_add( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_arithmetic_inner( args, false, false )
+}
+
+
+fn proc_subtract( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_arithmetic_inner( args, false, true )
+}
+
+
+fn proc_multiply( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_arithmetic_inner( args, true, false )
+}
+
+
+fn proc_divide( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_arithmetic_inner( args, true, true )
+}
+
+
+fn proc_list( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    Ok( new_list_data( args.clone() ) )
+}
+
+
+/// Builds a proper list holding the given values
+pub fn new_list_data( values: ListValuesArr ) -> Data {
+    new_improper_list_data( values, Data::Null )
+}
+
+
+/// Builds a list of the given values whose last cdr is `tail`, e.g. `(1 2 . 3)`
+pub fn new_improper_list_data( values: ListValuesArr, tail: Data ) -> Data {
+    values.into_iter().rev().fold( tail, |rest, value| new_pair_data( value, rest ) )
+}
+
+
+fn new_pair_data( car: Data, cdr: Data ) -> Data {
+    Data::Pair( Rc::new( Pair { car: RefCell::new( car ), cdr: RefCell::new( cdr ) } ) )
+}
+
+
+/// Builds a string holding a copy of the given text
+fn new_string_data( string: impl Into< Rc< str > > ) -> Data {
+    Data::String( string.into() )
+}
+
+
+/// The elements of a list, without the '() or the value that terminates it
+fn list_elements( arg: &Data ) -> ListValuesArr {
+    list_parts( arg ).0
+}
+
+
+/// Splits a list into its elements and the value that terminates it
+pub fn list_parts( arg: &Data ) -> ( ListValuesArr, Data ) {
+    let mut res     = ListValuesArr::new();
+    let mut rest    = arg.clone();
+
+    while let Data::Pair( pair ) = rest {
+        res.push( pair.car() );
+        rest = pair.cdr();
+    }
+
+    ( res, rest )
+}
+
+
+/// The number of elements of a list
+fn list_length( arg: &Data ) -> usize {
+    let mut res     = 0;
+    let mut rest    = arg.clone();
+
+    while let Data::Pair( pair ) = rest {
+        res    += 1;
+        rest    = pair.cdr();
+    }
+
+    res
+}
+
+
+/// A new list with the elements of a list in reverse order
+fn reverse_list( arg: &Data ) -> Data {
+    let mut res     = Data::Null;
+    let mut rest    = arg.clone();
+
+    while let Data::Pair( pair ) = rest {
+        res     = new_pair_data( pair.car(), res );
+        rest    = pair.cdr();
+    }
+
+    res
+}
+
+
+fn proc_is_null( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        Err( SchemeError::arity_mismatch( "null?", "1", args.len() ) )
+    }
+    else {
+        if matches!( args[ 0 ], Data::Null ) {
+            Ok( Data::Bool( true ) )
+        }
+        else {
+            Ok( Data::Bool( false ) )
+        }
+    }
+}
+
+
+fn proc_is_list( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "list?", "1", args.len() ) );
+    }
+
+    Ok( Data::Bool( is_list( &args[ 0 ] ) ) )
+}
+
+
+/// Checks whether the pairs starting at `arg` end in '(). `set-cdr!` can make
+/// a list circular, which is detected by walking it at two speeds
+fn is_list( arg: &Data ) -> bool {
+    let mut slow = arg.clone();
+    let mut fast = arg.clone();
+
+    loop {
+        for _ in 0..2 {
+            fast = match fast {
+                Data::Null          => return true,
+                Data::Pair( pair )  => pair.cdr(),
+                _                   => return false,
+            };
+        }
+
+        slow = match slow {
+            Data::Pair( pair )  => pair.cdr(),
+            _                   => unreachable!(),
+        };
+
+        if let ( Data::Pair( lhs ), Data::Pair( rhs ) ) = ( &slow, &fast ) {
+            if Rc::ptr_eq( lhs, rhs ) {
+                return false;
+            }
+        }
+    }
+}
+
+
+fn proc_is_pair( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "pair?", "1", args.len() ) );
+    }
+
+    Ok( Data::Bool( is_pair( &args[ 0 ] ) ) )
+}
+
+
+fn is_pair( arg: &Data ) -> bool {
+    matches!( arg, Data::Pair( _ ) )
+}
+
+
+fn proc_is_string( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "string?", "1", args.len() ) );
+    }
+
+    Ok( Data::Bool( is_string( &args[ 0 ] ) ) )
+}
+
+
+fn is_string( arg: &Data ) -> bool {
+    matches!( arg, Data::String( _ ) )
+}
+
+
+fn proc_cons( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "cons", "2", args.len() ) );
+    }
+
+    Ok( new_pair_data( args[ 0 ].clone(), args[ 1 ].clone() ) )
+}
+
+
+fn proc_car( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "car", "1", args.len() ) );
+    }
+
+    match &args[ 0 ] {
+        Data::Pair( pair )  => Ok( pair.car() ),
+        _                   => Err( SchemeError::contract_violation( "car", "pair?", &args[ 0 ] ) ),
+    }
+}
+
+
+fn proc_cdr( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "cdr", "1", args.len() ) );
+    }
+
+    match &args[ 0 ] {
+        Data::Pair( pair )  => Ok( pair.cdr() ),
+        _                   => Err( SchemeError::contract_violation( "cdr", "pair?", &args[ 0 ] ) ),
+    }
+}
+
+
+/// Applies the `car`s and `cdr`s that the letters of a name like `cadr` stand for,
+/// the last letter first
+fn proc_cxr_helper( args: &ProcedureArgsArr, proc_name: &str ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( proc_name, "1", args.len() ) );
+    }
+
+    let ops     = &proc_name[ 1..proc_name.len() - 1 ];
+    let mut res = args[ 0 ].clone();
+    for op in ops.chars().rev() {
+        res = match &res {
+            Data::Pair( pair ) if op == 'a' => pair.car(),
+            Data::Pair( pair )              => pair.cdr(),
+            _                               => return Err( SchemeError::contract_violation( proc_name, &cxr_contract( ops ), &args[ 0 ] ) ),
+        };
+    }
+
+    Ok( res )
+}
+
+
+/// The contract of a composition of `car`s and `cdr`s the way Racket writes it,
+/// e.g. `(cons/c any/c pair?)` for `cadr`
+fn cxr_contract( ops: &str ) -> String {
+    ops.chars().skip( 1 ).fold( "pair?".to_string(), |inner, op| {
+        if op == 'a' { format!( "(cons/c {} any/c)", inner ) } else { format!( "(cons/c any/c {})", inner ) }
+    } )
+}
+
+
+fn proc_caar( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "caar" )
+}
+
+
+fn proc_cadr( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "cadr" )
+}
+
+
+fn proc_cdar( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "cdar" )
+}
+
+
+fn proc_cddr( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "cddr" )
+}
+
+
+fn proc_caaar( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "caaar" )
+}
+
+
+fn proc_caadr( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "caadr" )
+}
+
+
+fn proc_cadar( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "cadar" )
+}
+
+
+fn proc_caddr( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "caddr" )
+}
+
+
+fn proc_cdaar( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "cdaar" )
+}
+
+
+fn proc_cdadr( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "cdadr" )
+}
+
+
+fn proc_cddar( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "cddar" )
+}
+
+
+fn proc_cdddr( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_cxr_helper( args, "cdddr" )
+}
+
+
+/// Takes a list argument, which must be a proper list
+fn list_arg< 'a >( proc_name: &str, arg: &'a Data ) -> Result< &'a Data, SchemeError > {
+    if is_list( arg ) { Ok( arg ) } else { Err( SchemeError::contract_violation( proc_name, "list?", arg ) ) }
+}
+
+
+fn proc_length( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "length", "1", args.len() ) );
+    }
+
+    let list = list_arg( "length", &args[ 0 ] )?;
+    Ok( Number::Int( list_length( list ) as i64 ).into_data() )
+}
+
+
+/// `(append list ... tail)` copies the lists in front of the last argument, which
+/// is shared with the result and doesn't have to be a list
+fn proc_append( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    let ( tail, lists ) = match args.split_last() {
+        Some( ( tail, lists ) ) => ( tail.clone(), lists ),
+        None                    => return Ok( Data::Null ),
+    };
+
+    let mut values = ListValuesArr::new();
+    for list in lists {
+        values.extend( list_elements( list_arg( "append", list )? ) );
+    }
+
+    Ok( new_improper_list_data( values, tail ) )
+}
+
+
+fn proc_reverse( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "reverse", "1", args.len() ) );
+    }
+
+    Ok( reverse_list( list_arg( "reverse", &args[ 0 ] )? ) )
+}
+
+
+fn proc_list_ref( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "list-ref", "2", args.len() ) );
+    }
+
+    match list_tail( "list-ref", &args[ 0 ], &args[ 1 ] )? {
+        Data::Pair( pair )  => Ok( pair.car() ),
+        _                   => Err( list_index_error( "list-ref", &args[ 0 ], &args[ 1 ] ) ),
+    }
+}
+
+
+fn proc_list_tail( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "list-tail", "2", args.len() ) );
+    }
+
+    list_tail( "list-tail", &args[ 0 ], &args[ 1 ] )
+}
+
+
+/// What is left of `list` after dropping `index` pairs
+fn list_tail( proc_name: &str, list: &Data, index: &Data ) -> Result< Data, SchemeError > {
+    let mut rest = list.clone();
+    for _ in 0..index_arg( proc_name, index )? {
+        rest = match rest {
+            Data::Pair( pair )  => pair.cdr(),
+            _                   => return Err( list_index_error( proc_name, list, index ) ),
+        };
+    }
+
+    Ok( rest )
+}
+
+
+/// The error for an index that goes past the pairs of a list, which depends on
+/// whether the list ends in '() or in something else
+fn list_index_error( proc_name: &str, list: &Data, index: &Data ) -> SchemeError {
+    let reason = if is_list( list ) { "index too large for list" } else { "index reaches a non-pair" };
+    SchemeError::bad_list_index( proc_name, reason, index, list )
+}
+
+
+fn proc_last_pair( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "last-pair", "1", args.len() ) );
+    }
+
+    let mut pair = match &args[ 0 ] {
+        Data::Pair( pair )  => Rc::clone( pair ),
+        _                   => return Err( SchemeError::contract_violation( "last-pair", "pair?", &args[ 0 ] ) ),
+    };
+
+    // The slow pointer moves every other step, and meets the other one on a circular list
+    let mut slow = Rc::clone( &pair );
+    let mut step = 0;
+    while let Data::Pair( next ) = pair.cdr() {
+        pair = next;
+        step += 1;
+        if step % 2 == 0 {
+            slow = match slow.cdr() {
+                Data::Pair( next )  => next,
+                _                   => unreachable!(),
+            };
+        }
+
+        if Rc::ptr_eq( &pair, &slow ) {
+            return Err( SchemeError::contract_violation( "last-pair", "(and/c pair? (not/c circular?))", &args[ 0 ] ) );
+        }
+    }
+
+    Ok( Data::Pair( pair ) )
+}
+
+
+/// Copies the pairs of a list, but not its elements
+fn proc_list_copy( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "list-copy", "1", args.len() ) );
+    }
+
+    Ok( new_list_data( list_elements( list_arg( "list-copy", &args[ 0 ] )? ) ) )
+}
+
+
+/// `eq?` on numbers and characters is unspecified, so `memq` and `assq` compare like `eqv?`
+fn proc_memq( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_search_helper( args, "memq", false )
+}
+
+
+fn proc_memv( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_search_helper( args, "memv", false )
+}
+
+
+fn proc_assq( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_search_helper( args, "assq", true )
+}
+
+
+fn proc_assv( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_search_helper( args, "assv", true )
+}
+
+
+fn proc_search_helper( args: &ProcedureArgsArr, proc_name: &str, is_assoc: bool ) -> Result< Data, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( proc_name, "2", args.len() ) );
+    }
+
+    check_search_list( proc_name, &args[ 1 ], is_assoc )?;
+
+    Ok( find_in_list( &args[ 0 ], &args[ 1 ], is_assoc, is_eqv ) )
+}
+
+
+/// `(iota count [start [step]])` is the list `(start start+step ...)` of `count` numbers
+fn proc_iota( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.is_empty() || args.len() > 3 {
+        return Err( SchemeError::arity_mismatch( "iota", "1 to 3", args.len() ) );
+    }
+
+    let count   = index_arg( "iota", &args[ 0 ] )?;
+    let nums    = number_args( "iota", &args[ 1.. ].to_vec() )?;
+    let start   = nums.first().cloned().unwrap_or( Number::Int( 0 ) );
+    let step    = nums.get( 1 ).cloned().unwrap_or( Number::Int( 1 ) );
+
+    let values = ( 0..count )
+        .map( |i| start.add( &step.mul( &Number::Int( i as i64 ) ) ).into_data() )
+        .collect();
+
+    Ok( new_list_data( values ) )
+}
+
+
+/// `(delete x list)` is `list` without the elements that are `equal?` to `x`
+fn proc_delete( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "delete", "2", args.len() ) );
+    }
+
+    let mut values = list_elements( list_arg( "delete", &args[ 1 ] )? );
+    values.retain( |value| *value != args[ 0 ] );
+
+    Ok( new_list_data( values ) )
+}
+
+
+/// `(remove x list)` is `list` without its first element that is `equal?` to `x`
+fn proc_remove( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "remove", "2", args.len() ) );
+    }
+
+    let mut values = list_elements( list_arg( "remove", &args[ 1 ] )? );
+    if let Some( i ) = values.iter().position( |value| *value == args[ 0 ] ) {
+        values.remove( i );
+    }
+
+    Ok( new_list_data( values ) )
+}
+
+
+fn proc_set_car( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_set_pair_helper( args, "set-car!", true )
+}
+
+
+fn proc_set_cdr( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_set_pair_helper( args, "set-cdr!", false )
+}
+
+
+/// Changes the pair in place, so every list that shares it sees the new value
+fn proc_set_pair_helper( args: &ProcedureArgsArr, proc_name: &str, is_car: bool ) -> Result< Data, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( proc_name, "2", args.len() ) );
+    }
+
+    let pair = match &args[ 0 ] {
+        Data::Pair( pair )  => pair,
+        _                   => return Err( SchemeError::contract_violation( proc_name, "pair?", &args[ 0 ] ) ),
+    };
+
+    let field = if is_car { &pair.car } else { &pair.cdr };
+    *field.borrow_mut() = args[ 1 ].clone();
+
+    Ok( Data::Void )
+}
+
+
+fn proc_is_number( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "number?", "1", args.len() ) );
+    }
+
+    if Number::from_data( &args[ 0 ] ).is_some() {
+        Ok( Data::Bool( true ) )
+    }
+    else {
+        Ok( Data::Bool( false ) )
+    }
+}
+
+
+fn proc_is_integer( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "integer?", "1", args.len() ) );
+    }
+
+    Ok( Data::Bool( is_integer( &args[ 0 ] ) ) )
+}
+
+
+fn is_integer( arg: &Data ) -> bool {
+    Number::from_data( arg ).is_some_and( |num| num.is_integer() )
+}
+
+
+/// Every number is real, since complex numbers aren't supported
+fn proc_is_real( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "real?", "1", args.len() ) );
+    }
+
+    let is_real = matches!( &args[ 0 ], Data::Number( Number::Int( _ ) | Number::Big( _ ) | Number::Rational( _ ) | Number::Real( _ ) ) );
+    Ok( Data::Bool( is_real ) )
+}
+
+
+fn proc_equals( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_compare_helper( args, CompareOrder::Equal, "=" )
+}
+
+
+enum CompareOrder {
+    Less,
+    LessEq,
+    Equal,
+    GreaterEq,
+    Greater
+}
+
+
+fn proc_less( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_compare_helper( args, CompareOrder::Less, "<" )
+}
+
+
+fn proc_less_or_equal( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_compare_helper( args, CompareOrder::LessEq, "<=" )
+}
+
+
+fn proc_greater( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_compare_helper( args, CompareOrder::Greater, ">" )
+}
+
+fn proc_greater_or_equal( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_compare_helper( args, CompareOrder::GreaterEq, ">=" )
+}
+
+
+fn proc_compare_helper( args: &ProcedureArgsArr, cmp_ord: CompareOrder , op: &str ) -> Result< Data, SchemeError > {
+    if args.is_empty() {
+        return Err( SchemeError::arity_mismatch( op, "at least 1", "0" ) );
+    }
+
+    if let [ Data::Number( Number::Int( lhs ) ), Data::Number( Number::Int( rhs ) ) ] = args.as_slice() {
+        return Ok( Data::Bool( cmp_exactly( &cmp_ord, lhs.cmp( rhs ) ) ) );
+    }
+
+    // Every argument is checked, even after the result is known
+    let nums    = number_args( op, args )?;
+    let res     = nums.windows( 2 ).all( |pair| pair[ 0 ].compare( &pair[ 1 ] ).is_some_and( |ord| cmp_exactly( &cmp_ord, ord ) ) );
+
+    Ok( Data::Bool( res ) )
+}
+
+
+fn cmp_exactly( cmp_ord: &CompareOrder, ord: Ordering ) -> bool {
+    match ord {
+        Ordering::Less      => matches!( cmp_ord, CompareOrder::Less | CompareOrder::LessEq ),
+        Ordering::Equal     => matches!( cmp_ord, CompareOrder::Equal | CompareOrder::GreaterEq | CompareOrder::LessEq ),
+        Ordering::Greater   => matches!( cmp_ord, CompareOrder::Greater | CompareOrder::GreaterEq ),
+    }
+}
+
+
+fn proc_remainder( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_div_inner( args, "remainder", Number::remainder )
+}
+
+
+fn proc_quotient( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_div_inner( args, "quotient", Number::quotient )
+}
+
+
+fn proc_modulo( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_div_inner( args, "modulo", Number::modulo )
+}
+
+
+fn proc_div_inner( args: &ProcedureArgsArr, proc: &str, div: fn( &Number, &Number ) -> Option< Number > ) -> Result< Data, SchemeError > {
+    if args.len() != 2 { 
+        return Err( SchemeError::arity_mismatch( proc, "2", args.len() ) );
+    }
+
+    for arg in args {
+        if !is_integer( arg ) {
+            return Err( SchemeError::contract_violation( proc, "integer?", arg ) );
+        }
+    }
+
+    let lhs = Number::from_data( &args[ 0 ] ).unwrap();
+    let rhs = Number::from_data( &args[ 1 ] ).unwrap();
+
+    match div( &lhs, &rhs ) {
+        Some( res ) => Ok( res.into_data() ),
+        None        => Err( SchemeError::UndefinedFor { procedure: proc.to_string(), given: args[ 1 ].to_string() } ),
+    }
+}
+
+
+fn proc_expt( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "expt", "2", args.len() ) );
+    }
+
+    let nums = number_args( "expt", args )?;
+
+    nums[ 0 ].expt( &nums[ 1 ] ).map( Number::into_data )
+}
+
+
+fn proc_max( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_extremum_helper( args, "max", Ordering::Less )
+}
+
+
+fn proc_min( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_extremum_helper( args, "min", Ordering::Greater )
+}
+
+
+/// Finds the largest or smallest argument: the current result is replaced by
+/// every argument it compares to with `replace_on`
+fn proc_extremum_helper( args: &ProcedureArgsArr, op: &str, replace_on: Ordering ) -> Result< Data, SchemeError > {
+    if args.is_empty() {
+        return Err( SchemeError::arity_mismatch( op, "at least 1", "0" ) );
+    }
+
+    let nums    = number_args( op, args )?;
+
+    // NaN isn't ordered with anything, so it is the result as soon as it appears
+    if nums.iter().any( |num| matches!( num, Number::Real( val ) if val.is_nan() ) ) {
+        return Ok( Number::Real( f64::NAN ).into_data() );
+    }
+
+    let mut res = nums[ 0 ].clone();
+
+    for num in &nums[ 1.. ] {
+        if res.compare( num ) == Some( replace_on ) {
+            res = num.clone();
+        }
+    }
+
+    // The result is inexact if any of the arguments is
+    if nums.iter().any( |num| !num.is_exact() ) {
+        res = res.to_inexact();
+    }
+
+    Ok( res.into_data() )
+}
+
+
+/// Checks that there is exactly one argument and takes the number out of it,
+/// requiring it to pass `is_valid`, which is described by `expected`
+fn single_number_arg( proc_name: &str, args: &ProcedureArgsArr, expected: &str, is_valid: fn( &Number ) -> bool ) -> Result< Number, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( proc_name, "1", args.len() ) );
+    }
+
+    match Number::from_data( &args[ 0 ] ) {
+        Some( num ) if is_valid( &num ) => Ok( num ),
+        _                               => Err( SchemeError::contract_violation( proc_name, expected, &args[ 0 ] ) ),
+    }
+}
+
+
+fn proc_abs( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    Ok( single_number_arg( "abs", args, "real?", |_| true )?.abs().into_data() )
+}
+
+
+fn proc_floor( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    Ok( single_number_arg( "floor", args, "real?", |_| true )?.round( Rounding::Floor ).into_data() )
+}
+
+
+fn proc_ceiling( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    Ok( single_number_arg( "ceiling", args, "real?", |_| true )?.round( Rounding::Ceiling ).into_data() )
+}
+
+
+fn proc_truncate( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    Ok( single_number_arg( "truncate", args, "real?", |_| true )?.round( Rounding::Truncate ).into_data() )
+}
+
+
+fn proc_round( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    Ok( single_number_arg( "round", args, "real?", |_| true )?.round( Rounding::Round ).into_data() )
+}
+
+
+fn proc_gcd( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_integer_fold_helper( args, "gcd", Number::Int( 0 ), Number::gcd )
+}
+
+
+fn proc_lcm( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_integer_fold_helper( args, "lcm", Number::Int( 1 ), Number::lcm )
+}
+
+
+fn proc_integer_fold_helper( args: &ProcedureArgsArr, op: &str, init: Number, fold: fn( &Number, &Number ) -> Number ) -> Result< Data, SchemeError > {
+    let mut res = init;
+
+    for arg in args {
+        if !is_integer( arg ) {
+            return Err( SchemeError::contract_violation( op, "integer?", arg ) );
+        }
+
+        res = fold( &res, &Number::from_data( arg ).unwrap() );
+    }
+
+    // A single argument is still made non-negative
+    Ok( res.abs().into_data() )
+}
+
+
+fn proc_sqrt( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    // Complex numbers aren't supported, so negative numbers have no square root
+    let num = single_number_arg( "sqrt", args, "(>=/c 0)", |num| !num.is_negative() )?;
+    Ok( num.sqrt().unwrap().into_data() )
+}
+
+
+/// `(exact-integer-sqrt n)` returns two values, `s` and `r`, where `s` is the largest
+/// integer whose square is at most `n` and `r` is `n - s^2`
+fn proc_exact_integer_sqrt( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    let num             = single_number_arg( "exact-integer-sqrt", args, "exact-nonnegative-integer?", |num| num.is_exact() && num.is_integer() && !num.is_negative() )?;
+    let ( root, rest )  = num.exact_integer_sqrt();
+    Ok( Data::Values( Rc::new( [ root.into_data(), rest.into_data() ] ) ) )
+}
+
+
+/// Applies a floating point function, except that the exact number `exact_case.0`
+/// gives the exact result `exact_case.1`, like Racket's `(sin 0)` gives `0`
+fn proc_float_fn_helper( args: &ProcedureArgsArr, op: &str, func: fn( f64 ) -> f64, exact_case: ( i64, i64 ) ) -> Result< Data, SchemeError > {
+    let num = single_number_arg( op, args, "number?", |_| true )?;
+
+    if num == Number::Int( exact_case.0 ) {
+        return Ok( Number::Int( exact_case.1 ).into_data() );
+    }
+
+    Ok( Number::Real( func( num.to_f64() ) ).into_data() )
+}
+
+
+fn proc_exp( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_float_fn_helper( args, "exp", f64::exp, ( 0, 1 ) )
+}
+
+
+fn proc_sin( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_float_fn_helper( args, "sin", f64::sin, ( 0, 0 ) )
+}
+
+
+fn proc_cos( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_float_fn_helper( args, "cos", f64::cos, ( 0, 1 ) )
+}
 
-                write!( f, "{}", &self.string )
-            },
-            DataType::Real => {
-                if !( quote_level == 0 && self.quote_level == 1 ) {
-                    if quote_level < self.quote_level {
-                        for _ in 0..( self.quote_level - quote_level ) {
-                            if let Err( e ) = write!( f, "'" ) {
-                                eprintln!( "{}", e );
-                                return Err( e );
-                            }
-                        }
-                    }
-                }
 
-                write!( f, "{}", (&self.string).parse::<f64>().unwrap().to_string() )
-            },
-            DataType::Invalid => {
-                write!( f, "" )
-            },
-            _ => {
-                write!( f, "{}", self.string )
-            }
+fn proc_tan( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_float_fn_helper( args, "tan", f64::tan, ( 0, 0 ) )
+}
+
+
+/// `(log z)` is the natural logarithm, `(log z b)` the logarithm in base `b`
+fn proc_log( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 && args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "log", "1 to 2", args.len() ) );
+    }
+
+    // Complex numbers aren't supported, so negative numbers have no logarithm
+    for arg in args {
+        match Number::from_data( arg ) {
+            Some( num ) if num.is_exact() && num.is_zero()  => return Err( SchemeError::UndefinedFor { procedure: "log".to_string(), given: "0".to_string() } ),
+            Some( num ) if !num.is_negative()               => {},
+            _                                               => return Err( SchemeError::contract_violation( "log", "(>=/c 0)", arg ) ),
         }
     }
 
+    if args.len() == 1 {
+        return proc_float_fn_helper( args, "log", f64::ln, ( 1, 0 ) );
+    }
+
+    let nums = number_args( "log", args )?;
+    Ok( Number::Real( nums[ 0 ].to_f64().ln() / nums[ 1 ].to_f64().ln() ).into_data() )
 }
 
 
-impl fmt::Display for Data {
-    fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
-        self.display( f, 0 )
+/// `(atan z)` is the arctangent of `z`, `(atan y x)` the angle of the point `(x, y)`
+fn proc_atan( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 && args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "atan", "1 to 2", args.len() ) );
     }
-}
 
+    if args.len() == 1 {
+        return proc_float_fn_helper( args, "atan", f64::atan, ( 0, 0 ) );
+    }
 
-pub const NULL_PROC: fn( &Vec< Data > ) -> Data = |_| NULL_SYM;
+    let nums = number_args( "atan", args )?;
+    if nums.iter().all( |num| num.is_exact() && num.is_zero() ) {
+        return Err( SchemeError::DivisionByZero { procedure: "atan".to_string() } );
+    }
 
-pub const NULL_SYM      : Data = Data { list: vec![], string: String::new(), procedure: NULL_PROC, data_type: DataType::Symbol, quote_level: 1 };
-pub const INVALID_DATA  : Data = Data { list: vec![], string: String::new(), procedure: NULL_PROC, data_type: DataType::Invalid, quote_level: 0 };
+    Ok( Number::Real( nums[ 0 ].to_f64().atan2( nums[ 1 ].to_f64() ) ).into_data() )
+}
 
 
-pub fn new_true_sym() -> Data {
-    Data::from_string( DataType::Symbol, "#t".to_string() )
+fn proc_is_zero( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    Ok( Data::Bool( single_number_arg( "zero?", args, "number?", |_| true )?.is_zero() ) )
 }
 
 
-pub fn new_false_sym() -> Data {
-    Data::from_string( DataType::Symbol, "#f".to_string() )
+fn proc_is_positive( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    Ok( Data::Bool( single_number_arg( "positive?", args, "real?", |_| true )?.is_positive() ) )
 }
 
 
-pub fn new_void_data() -> Data {
-    Data::from_string( DataType::Invalid, "#<void>".to_string() )
+fn proc_is_negative( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    Ok( Data::Bool( single_number_arg( "negative?", args, "real?", |_| true )?.is_negative() ) )
 }
 
 
-pub fn is_null_sym( data: &Data ) -> bool {
-    is_of_type( &DataType::Symbol, &data )  &&
-    data.quote_level == 1                   &&
-    data.string.is_empty()                  &&
-    data.list.is_empty()
+fn proc_is_odd( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    Ok( Data::Bool( !single_number_arg( "odd?", args, "integer?", Number::is_integer )?.is_even() ) )
 }
 
 
-// pub fn is_true_sym( data: &Data ) -> bool {
-//     is_of_type( &DataType::Symbol, &data )  &&
-//     data.quote_level    == 0                &&
-//     data.string         == "#t"             &&
-//     data.list.is_empty()
-// }
+fn proc_is_even( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    Ok( Data::Bool( single_number_arg( "even?", args, "integer?", Number::is_integer )?.is_even() ) )
+}
 
 
-pub fn is_false_sym( data: &Data ) -> bool {
-    is_of_type( &DataType::Symbol, &data )  &&
-    data.quote_level    == 0                &&
-    data.string         == "#f"             &&
-    data.list.is_empty()
+/// Takes a rational number out of the argument, i.e. any number except infinities and NaN
+fn rational_arg( proc_name: &str, arg: &Data ) -> Result< Number, SchemeError > {
+    match Number::from_data( arg ) {
+        Some( num ) if num.to_exact().is_some() => Ok( num ),
+        _                                       => Err( SchemeError::contract_violation( proc_name, "rational?", arg ) ),
+    }
 }
 
 
-// pub fn is_void_data( data: &Data ) -> bool {
-//     is_of_type( &DataType::Invalid, &data ) &&
-//     data.quote_level    == 0                &&
-//     data.string         == "#<void>"        &&
-//     data.list.is_empty()
-// }
-
+fn proc_numerator( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "numerator", "1", args.len() ) );
+    }
 
-pub fn is_invalid_data( data: &Data ) -> bool {
-    is_of_type( &DataType::Invalid, &data ) &&
-    data.quote_level    == 0                &&
-    data.string.is_empty()                  &&
-    data.list.is_empty()
+    Ok( rational_arg( "numerator", &args[ 0 ] )?.numerator().into_data() )
 }
 
 
-enum Error {
-    // Unknown,
-    ArityMismatch,
-    NotAProcedure,
-    // NotImplemented,
-    ContractViolation,
-    Undefined,
-    MissingProcedure,
-    BadSyntax,
+fn proc_denominator( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "denominator", "1", args.len() ) );
+    }
+
+    Ok( rational_arg( "denominator", &args[ 0 ] )?.denominator().into_data() )
 }
 
 
-fn print_error( err: Error, proc: &str, expected: &str, given: &str ) {
-    print!( "{}: ", proc );
+fn proc_exact_to_inexact( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "exact->inexact", "1", args.len() ) );
+    }
+
+    Ok( number_args( "exact->inexact", args )?[ 0 ].to_inexact().into_data() )
+}
+
 
-    match err {
-        Error::ArityMismatch        => print!( "arity mismatch;\n the expected number of arguments does not match the given number" ),
-        Error::NotAProcedure        => print!( "not a procedure;\n expected a procedure that can be applied to arguments" ),
-        // Error::NotImplemented       => print!( "not implemented;" ),
-        Error::ContractViolation    => print!( "contract violation;" ),
-        Error::MissingProcedure     => print!( "missing procedure expression;\n probably originally (), which is an illegal empty application in: ({})", proc ),
-        Error::Undefined            => print!( "undefined;\n cannot reference an identifier before its definition" ),
-        Error::BadSyntax            => print!( "bad syntax;" ),
+fn proc_inexact_to_exact( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "inexact->exact", "1", args.len() ) );
     }
 
-    if !expected.is_empty() {
-        print!( "\n  expected: {}", expected );
+    Ok( rational_arg( "inexact->exact", &args[ 0 ] )?.to_exact().unwrap().into_data() )
+}
+
+
+fn proc_display( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "display", "1", args.len() ) );
     }
 
-    if !expected.is_empty() || !given.is_empty() {
-        print!( "\n  given: {}", given );
+    print!( "{}", args[ 0 ].printed( PrintMode::Display ) );
+
+    Ok( Data::Void )
+}
+
+
+/// Frees the environments that are only kept alive by reference cycles
+fn proc_collect_garbage( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if !args.is_empty() {
+        return Err( SchemeError::arity_mismatch( "collect-garbage", "0", args.len() ) );
     }
+
+    Environment::collect_cycles();
+
+    Ok( Data::Void )
 }
 
 
-fn is_of_type( data_type: &DataType, data: &Data ) -> bool {
-    match ( data_type, &data.data_type ) {
-        ( x, y ) if x == y  => true,
-        _                   => false,
+fn proc_write( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "write", "1", args.len() ) );
     }
+
+    print!( "{}", args[ 0 ].printed( PrintMode::Write ) );
+
+    Ok( Data::Void )
 }
 
 
-fn arithmetic_general_case( res_type: &mut DataType, ires: &mut i64, fres: &mut f64, i: usize, args: &ProcedureArgsArr, is_div: bool, is_mul: bool, is_sub: bool ) {
-    if let DataType::Integer = res_type {
-        if is_div {
-            unreachable!();
-        }
-        else if is_mul {
-            *ires *= args[ i ].string.parse::<f64>().unwrap() as i64;
-        }
-        else if is_sub {
-            *ires -= args[ i ].string.parse::<f64>().unwrap() as i64;
-        }
-        else {
-            *ires += args[ i ].string.parse::<f64>().unwrap() as i64;
-        }
+fn string_value( arg: &Data ) -> Option< &str > {
+    match arg {
+        Data::String( string )  => Some( string ),
+        _                       => None,
     }
-    else {
-        if is_div {
-            *fres /= args[ i ].string.parse::<f64>().unwrap();
-        }
-        else if is_mul {
-            *fres *= args[ i ].string.parse::<f64>().unwrap();
-        }
-        else if is_sub {
-            *fres -= args[ i ].string.parse::<f64>().unwrap();
-        }
-        else {
-            *fres += args[ i ].string.parse::<f64>().unwrap();
-        }
+}
+
+
+fn string_arg< 'a >( proc_name: &str, arg: &'a Data ) -> Result< &'a str, SchemeError > {
+    string_value( arg ).ok_or_else( || SchemeError::contract_violation( proc_name, "string?", arg ) )
+}
+
+
+fn index_arg( proc_name: &str, arg: &Data ) -> Result< usize, SchemeError > {
+    match arg {
+        Data::Number( Number::Int( index ) ) if *index >= 0 => Ok( *index as usize ),
+        _ => Err( SchemeError::contract_violation( proc_name, "exact-nonnegative-integer?", arg ) ),
     }
 }
 
 
-fn proc_arithmetic_inner( args: &ProcedureArgsArr, is_mul: bool, is_inv: bool ) -> Data {
-    let is_div      = is_mul && is_inv;
-    let is_sub      = !is_mul && is_inv;
-    let proc_name   = if is_mul { if is_inv { "/" } else { "*" } } else { if is_inv { "-" } else { "+" } };
+fn proc_string_length( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "string-length", "1", args.len() ) );
+    }
 
-    if is_inv && args.is_empty() {
-        print_error( Error::ArityMismatch, proc_name, "at least 1", "0" );
-        return INVALID_DATA;
+    let string = string_arg( "string-length", &args[ 0 ] )?;
+    Ok( Number::Int( string.chars().count() as i64 ).into_data() )
+}
+
+
+fn proc_string_ref( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "string-ref", "2", args.len() ) );
     }
 
-    let mut res_type    = DataType::Integer;
-    let mut ires        = if is_mul { 1 as i64 } else { 0 as i64 };
-    let mut fres        = if is_mul { 1 as f64 } else { 0 as f64 };
+    let string  = string_arg( "string-ref", &args[ 0 ] )?;
+    let index   = index_arg( "string-ref", &args[ 1 ] )?;
+    let len     = string.chars().count();
 
-    if is_inv {
-        if !is_of_type( &DataType::Integer, &args[ 0 ] ) && !is_of_type( &DataType::Real, &args[ 0 ] ){
-            print_error( Error::ContractViolation, proc_name, "number?", args[ 0 ].to_string().as_str() );
-            return INVALID_DATA;
-        }
+    match string.chars().nth( index ) {
+        Some( ch )  => Ok( Data::Char( ch ) ),
+        None        => Err( SchemeError::index_out_of_range( "string-ref", index, len.checked_sub( 1 ).map( |last| ( 0, last ) ), "string", &args[ 0 ] ) ),
+    }
+}
 
-        if args.len() == 1 {
-            if is_div {
-                return Data::from_string( DataType::Real, ( 1.0 as f64 / args[ 0 ].string.parse::<f64>().unwrap() ).to_string() );
-            }
 
-            // It's subtraction
-            if is_of_type( &DataType::Real, &args[ 0 ] ) {
-                return Data::from_string( DataType::Real, ( -args[ 0 ].string.parse::<f64>().unwrap() ).to_string() );
-            }
+fn proc_substring( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 2 && args.len() != 3 {
+        return Err( SchemeError::arity_mismatch( "substring", "2 to 3", args.len() ) );
+    }
 
-            return Data::from_string( DataType::Integer, ( -args[ 0 ].string.parse::<i64>().unwrap() ).to_string() );
-        }
+    let chars: Vec< char >  = string_arg( "substring", &args[ 0 ] )?.chars().collect();
+    let start               = index_arg( "substring", &args[ 1 ] )?;
+    let end                 = if args.len() == 3 { index_arg( "substring", &args[ 2 ] )? } else { chars.len() };
 
-        if is_div || is_of_type( &DataType::Real, &args[ 0 ] ) {
-            fres        = args[ 0 ].string.parse::<f64>().unwrap();
-            res_type    = DataType::Real;
-        }
-        else {  // is_of_type( &DataType::Integer, &args[ 0 ]
-            ires        = args[ 0 ].string.parse::<i64>().unwrap();
-        }
+    if start > chars.len() {
+        return Err( SchemeError::index_out_of_range( "substring", start, Some( ( 0, chars.len() ) ), "string", &args[ 0 ] ) );
     }
 
-    for i in ( if is_inv { 1 } else { 0 } )..( args.len() ) {
-        match args[ i ].data_type {
-            DataType::Real => {
-                if let DataType::Integer = res_type {
-                    res_type    = DataType::Real;
-                    fres        = ires as f64;
-                }
-                arithmetic_general_case( &mut res_type, &mut ires, &mut fres, i, args, is_div, is_mul, is_sub );
-            },
-            DataType::Integer => {
-                arithmetic_general_case( &mut res_type, &mut ires, &mut fres, i, args, is_div, is_mul, is_sub );
-            },
-            _ => {
-                print_error( Error::ContractViolation, proc_name, "number?", args[ i ].to_string().as_str() );
-                return INVALID_DATA;
-            }
-        }
+    if end < start || end > chars.len() {
+        return Err( SchemeError::index_out_of_range( "substring", end, Some( ( start, chars.len() ) ), "string", &args[ 0 ] ) );
     }
 
-    match res_type {
-        DataType::Integer   => Data::from_string( DataType::Integer,    ires.to_string() ),
-        DataType::Real      => Data::from_string( DataType::Real,       fres.to_string() ),
-        _                   => INVALID_DATA
+    Ok( new_string_data( chars[ start..end ].iter().collect::< String >() ) )
+}
+
+
+fn proc_string_append( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    let mut res = String::new();
+
+    for arg in args {
+        res.push_str( string_arg( "string-append", arg )? );
     }
+
+    Ok( new_string_data( res ) )
 }
 
 
-fn proc_add( args: &ProcedureArgsArr ) -> Data {
-    proc_arithmetic_inner( args, false, false )
+fn proc_string_equals( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_string_compare_helper( args, CompareOrder::Equal, "string=?" )
 }
 
 
-fn proc_subtract( args: &ProcedureArgsArr ) -> Data {
-    proc_arithmetic_inner( args, false, true )
+fn proc_string_less( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_string_compare_helper( args, CompareOrder::Less, "string<?" )
 }
 
 
-fn proc_multiply( args: &ProcedureArgsArr ) -> Data {
-    proc_arithmetic_inner( args, true, false )
+fn proc_string_less_eq( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_string_compare_helper( args, CompareOrder::LessEq, "string<=?" )
 }
 
 
-fn proc_divide( args: &ProcedureArgsArr ) -> Data {
-    proc_arithmetic_inner( args, true, true )
+fn proc_string_greater( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_string_compare_helper( args, CompareOrder::Greater, "string>?" )
 }
 
 
-fn proc_list( args: &ProcedureArgsArr ) -> Data {
-    if args.is_empty() {
-        NULL_SYM
-    }
-    else {
-        let mut res = Data::from_list( args.clone() );
-        res.list.push( NULL_SYM );
-        res
-    }
+fn proc_string_greater_eq( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_string_compare_helper( args, CompareOrder::GreaterEq, "string>=?" )
 }
 
 
-fn proc_is_null( args: &ProcedureArgsArr ) -> Data {
-    if args.len() != 1 {
-        print_error( Error::ArityMismatch, "null?", "1", args.len().to_string().as_str() );
-        INVALID_DATA
+fn proc_string_compare_helper( args: &ProcedureArgsArr, cmp_ord: CompareOrder, op: &str ) -> Result< Data, SchemeError > {
+    proc_text_compare_helper( args, cmp_ord, op, "string?", string_value )
+}
+
+
+/// Compares strings or chars, taken out of the arguments by `value`, by code point
+fn proc_text_compare_helper< 'a, T: Ord >( args: &'a ProcedureArgsArr, cmp_ord: CompareOrder, op: &str, expected: &str, value: fn( &'a Data ) -> Option< T > ) -> Result< Data, SchemeError > {
+    if args.is_empty() {
+        return Err( SchemeError::arity_mismatch( op, "at least 1", "0" ) );
     }
-    else {
-        if is_null_sym( &args[ 0 ] ) {
-            new_true_sym()
-        }
-        else {
-            new_false_sym()
+
+    // Every argument is checked, even after the result is known
+    let mut res     = true;
+    let mut prev    = None::< T >;
+    for arg in args {
+        let curr = value( arg ).ok_or_else( || SchemeError::contract_violation( op, expected, arg ) )?;
+
+        if let Some( prev ) = prev {
+            res = res && cmp_exactly( &cmp_ord, prev.cmp( &curr ) );
         }
+
+        prev = Some( curr );
     }
+
+    Ok( Data::Bool( res ) )
 }
 
 
-fn proc_is_list( args: &ProcedureArgsArr ) -> Data {
+fn proc_string_upcase( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
     if args.len() != 1 {
-        print_error( Error::ArityMismatch, "list?", "1", args.len().to_string().as_str() );
-        return INVALID_DATA;
+        return Err( SchemeError::arity_mismatch( "string-upcase", "1", args.len() ) );
     }
 
-    let arg = &args[ 0 ];
+    Ok( new_string_data( string_arg( "string-upcase", &args[ 0 ] )?.to_uppercase() ) )
+}
 
-    if is_null_sym( &arg ) {
-        new_true_sym()
-    }
-    else if is_of_type( &DataType::List, &arg ) && arg.list.len() > 1 && is_null_sym( &arg.list.last().unwrap() ) {
-        new_true_sym()
-    }
-    else if is_of_type( &DataType::Symbol, &arg ) && !arg.list.is_empty() && is_null_sym( arg.list.last().unwrap() ) {
-        new_true_sym()
-    }
-    else {
-        new_false_sym()
+
+fn proc_string_downcase( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "string-downcase", "1", args.len() ) );
     }
+
+    Ok( new_string_data( string_arg( "string-downcase", &args[ 0 ] )?.to_lowercase() ) )
 }
 
 
-fn proc_is_pair( args: &ProcedureArgsArr ) -> Data {
+fn proc_string_to_list( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
     if args.len() != 1 {
-        print_error( Error::ArityMismatch, "pair?", "1", args.len().to_string().as_str() );
-        return INVALID_DATA;
+        return Err( SchemeError::arity_mismatch( "string->list", "1", args.len() ) );
     }
 
-    if args[ 0 ].list.len() > 1 {
-        new_true_sym()
-    }
-    else {
-        new_false_sym()
-    }
+    let string = string_arg( "string->list", &args[ 0 ] )?;
+    Ok( new_list_data( string.chars().map( Data::Char ).collect() ) )
 }
 
 
-fn proc_is_string( args: &ProcedureArgsArr ) -> Data {
+fn proc_list_to_string( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
     if args.len() != 1 {
-        print_error( Error::ArityMismatch, "string?", "1", args.len().to_string().as_str() );
-        return INVALID_DATA;
+        return Err( SchemeError::arity_mismatch( "list->string", "1", args.len() ) );
     }
 
-    let string_len  =   args[ 0 ].string.len();
-    let is_string   =   is_of_type( &DataType::Symbol, &args[ 0 ] )                         &&
-                        string_len >= 2                                                     &&
-                        args[ 0 ].string.chars().nth( 0 ).unwrap() == '"'                   &&
-                        args[ 0 ].string.chars().nth( string_len - 1 ).unwrap()  == '"';
+    let are_chars = is_list( &args[ 0 ] ) && list_elements( &args[ 0 ] ).iter().all( |elem| char_value( elem ).is_some() );
+    if !are_chars {
+        return Err( SchemeError::contract_violation( "list->string", "(listof char?)", &args[ 0 ] ) );
+    }
+
+    Ok( new_string_data( list_elements( &args[ 0 ] ).iter().filter_map( char_value ).collect::< String >() ) )
+}
+
 
-    if is_string {
-        new_true_sym()
+fn radix_arg( proc_name: &str, args: &ProcedureArgsArr ) -> Result< u32, SchemeError > {
+    if args.len() < 2 {
+        return Ok( 10 );
     }
-    else {
-        new_false_sym()
+
+    match args[ 1 ] {
+        Data::Number( Number::Int( radix @ ( 2 | 8 | 10 | 16 ) ) ) => Ok( radix as u32 ),
+        _ => Err( SchemeError::contract_violation( proc_name, "(or/c 2 8 10 16)", &args[ 1 ] ) ),
     }
 }
 
 
-fn proc_cons( args: &ProcedureArgsArr ) -> Data {
-    if args.len() != 2 {
-        print_error( Error::ArityMismatch, "cons", "2", args.len().to_string().as_str() );
-        return INVALID_DATA;
+fn proc_string_to_number( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 && args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "string->number", "1 to 2", args.len() ) );
     }
 
-    let mut res = Data::new_list();
-    res.list.push( args[ 0 ].clone() );
+    let string  = string_arg( "string->number", &args[ 0 ] )?;
+    let radix   = radix_arg( "string->number", args )?;
 
-    let rhs = &args[ 1 ];
-    if rhs.list.is_empty() {
-        res.list.push( rhs.clone() );
-    } else {
-        res.list.reserve( rhs.list.len() );
-        res.list.append( &mut rhs.list.clone() );
+    if let Some( int_val ) = Number::from_str_radix( string, radix ) {
+        return Ok( int_val.into_data() );
     }
 
-    res
+    // Rust also parses words like "inf" and "NaN", which aren't numbers in Scheme
+    let is_decimal = radix == 10 && string.chars().any( |ch| ch.is_ascii_digit() ) && string.chars().all( |ch| "0123456789+-.eE".contains( ch ) );
+    match string.parse::< f64 >() {
+        Ok( real_val ) if is_decimal    => Ok( Number::Real( real_val ).into_data() ),
+        _                               => Ok( Data::Bool( false ) ),
+    }
 }
 
 
-fn proc_car( args: &ProcedureArgsArr ) -> Data {
-    if args.len() != 1 {
-        print_error( Error::ArityMismatch, "car", "1", args.len().to_string().as_str() );
-        return INVALID_DATA;
+fn proc_number_to_string( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 && args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "number->string", "1 to 2", args.len() ) );
     }
 
-    if is_false_sym( &proc_is_pair( &args ) ) {
-        print_error( Error::ContractViolation, "car", "pair?", args[ 0 ].to_string().as_str() );
-        return INVALID_DATA;
-    }
+    let radix   = radix_arg( "number->string", args )?;
+    let num     = Number::from_data( &args[ 0 ] ).ok_or_else( || SchemeError::contract_violation( "number->string", "number?", &args[ 0 ] ) )?;
 
-    return args[ 0 ].list[ 0 ].clone();
+    Ok( new_string_data( num.to_str_radix( radix ) ) )
 }
 
 
-fn proc_cdr( args: &ProcedureArgsArr ) -> Data {
+fn proc_string_to_symbol( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
     if args.len() != 1 {
-        print_error( Error::ArityMismatch, "cdr", "1", args.len().to_string().as_str() );
-        return INVALID_DATA;
+        return Err( SchemeError::arity_mismatch( "string->symbol", "1", args.len() ) );
     }
 
-    if is_false_sym( &proc_is_pair( &args ) ) {
-        print_error( Error::ContractViolation, "cdr", "pair?", args[ 0 ].to_string().as_str() );
-        return INVALID_DATA;
-    }
+    let string = string_arg( "string->symbol", &args[ 0 ] )?;
+    Ok( Data::Symbol( Symbol::new( string ) ) )
+}
 
-    if args[ 0 ].list.len() == 2 && is_null_sym( &args[ 0 ].list[ 1 ] ) {
-        return NULL_SYM;
-    }
 
-    let mut res = Data::new_list();
-    res.list.reserve( args[ 0 ].list.len() - 1 );
-    for i in 1..args[ 0 ].list.len() {
-        res.list.push( args[ 0 ].list[ i ].clone() );
+fn proc_symbol_to_string( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "symbol->string", "1", args.len() ) );
     }
 
-    res
+    match &args[ 0 ] {
+        Data::Symbol( sym ) => Ok( new_string_data( sym.as_str() ) ),
+        _                   => Err( SchemeError::contract_violation( "symbol->string", "symbol?", &args[ 0 ] ) ),
+    }
 }
 
 
-fn proc_is_number( args: &ProcedureArgsArr ) -> Data {
-    if args.len() != 1 {
-        print_error( Error::ArityMismatch, "number?", "1", args.len().to_string().as_str() );
-        return INVALID_DATA;
+/// `(string-split str [sep])` splits on runs of whitespace, or on every `sep`
+/// after trimming one `sep` from both ends, like Racket's `string-split`
+fn proc_string_split( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 && args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "string-split", "1 to 2", args.len() ) );
     }
 
-    if is_of_type( &DataType::Integer, &args[ 0 ] ) || is_of_type( &DataType::Real, &args[ 0 ] ) {
-        new_true_sym()
+    let string  = string_arg( "string-split", &args[ 0 ] )?;
+    let parts   = if args.len() == 1 {
+        string.split_whitespace().collect()
     }
     else {
-        new_false_sym()
-    }
+        let sep = string_arg( "string-split", &args[ 1 ] )?;
+        if sep.is_empty() {
+            return Err( SchemeError::contract_violation( "string-split", "non-empty-string?", &args[ 1 ] ) );
+        }
+
+        let string  = string.strip_prefix( sep ).unwrap_or( string );
+        let string  = string.strip_suffix( sep ).unwrap_or( string );
+        if string.is_empty() { vec![] } else { string.split( sep ).collect() }
+    };
+
+    Ok( new_list_data( parts.into_iter().map( new_string_data ).collect() ) )
 }
 
 
-fn proc_is_integer( args: &ProcedureArgsArr ) -> Data {
-    if args.len() != 1 {
-        print_error( Error::ArityMismatch, "integer?", "1", args.len().to_string().as_str() );
-        return INVALID_DATA;
+fn proc_string_join( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 && args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "string-join", "1 to 2", args.len() ) );
     }
 
-    if is_of_type( &DataType::Integer, &args[ 0 ] ) {
-        return new_true_sym();
-    }
-    
-    if is_of_type( &DataType::Real, &args[ 0 ] ) {
-        let float = args[ 0 ].string.parse::<f64>().unwrap();
-        if float.fract() == 0.0 {
-            return new_true_sym();
-        }
+    let are_strings = is_list( &args[ 0 ] ) && list_elements( &args[ 0 ] ).iter().all( is_string );
+    if !are_strings {
+        return Err( SchemeError::contract_violation( "string-join", "(listof string?)", &args[ 0 ] ) );
     }
 
-    new_false_sym()
-}
-
+    let sep     = if args.len() == 2 { string_arg( "string-join", &args[ 1 ] )? } else { " " };
+    let strings = list_elements( &args[ 0 ] );
+    let parts   : Vec< &str > = strings.iter().filter_map( string_value ).collect();
 
-fn proc_is_real( args: &ProcedureArgsArr ) -> Data {
-    proc_is_number( args )  // Since fractions aren't implemented
+    Ok( new_string_data( parts.join( sep ) ) )
 }
 
 
-fn proc_equals( args: &ProcedureArgsArr ) -> Data {
-    if args.is_empty() {
-        print_error( Error::ArityMismatch, "=", "at least 1", "0" );
-        return INVALID_DATA;
+/// `(string-index str needle)` returns the index of the first occurrence of the
+/// char or string `needle` in `str`, or #f if there is none
+fn proc_string_index( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 2 {
+        return Err( SchemeError::arity_mismatch( "string-index", "2", args.len() ) );
     }
 
-    let first_is_int    = is_of_type( &DataType::Integer, &args[ 0 ] );
-    let int_val         = if first_is_int { args[ 0 ].string.parse::<i64>().unwrap() } else { 0 };
-    let real_val        = if first_is_int { int_val as f64 } else { args[ 0 ].string.parse::<f64>().unwrap() };
-    for arg in args {
-        if !is_of_type( &DataType::Integer, &arg ) && !is_of_type( &DataType::Real, &arg ) {
-            print_error( Error::ContractViolation, "=", "number?", arg.to_string().as_str() );
-            return INVALID_DATA;
-        }
+    let string = string_arg( "string-index", &args[ 0 ] )?;
 
-        if first_is_int && is_of_type( &DataType::Integer, &arg ) {
-            if int_val != arg.string.parse::<i64>().unwrap() {
-                return new_false_sym()
-            }
-        }
-        else {
-            if real_val != arg.string.parse::<f64>().unwrap() {
-                return new_false_sym()
-            }
-        }
-    }
+    let found = match &args[ 1 ] {
+        Data::String( needle )  => string.find( needle.as_ref() ),
+        Data::Char( needle )    => string.find( *needle ),
+        _                       => return Err( SchemeError::contract_violation( "string-index", "(or/c char? string?)", &args[ 1 ] ) ),
+    };
 
-    new_true_sym()
+    match found {
+        Some( byte_index )  => Ok( Number::Int( string[ ..byte_index ].chars().count() as i64 ).into_data() ),
+        None                => Ok( Data::Bool( false ) ),
+    }
 }
 
 
-enum CompareOrder {
-    Less,
-    LessEq,
-    GreaterEq,
-    Greater
+fn char_value( arg: &Data ) -> Option< char > {
+    match arg {
+        Data::Char( ch )    => Some( *ch ),
+        _                   => None,
+    }
 }
 
 
-fn proc_less( args: &ProcedureArgsArr ) -> Data {
-    proc_compare_helper( args, CompareOrder::Less, "<" )
+fn char_arg( proc_name: &str, arg: &Data ) -> Result< char, SchemeError > {
+    char_value( arg ).ok_or_else( || SchemeError::contract_violation( proc_name, "char?", arg ) )
 }
 
 
-fn proc_less_or_equal( args: &ProcedureArgsArr ) -> Data {
-    proc_compare_helper( args, CompareOrder::LessEq, "<=" )
+fn proc_is_char( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "char?", "1", args.len() ) );
+    }
+
+    Ok( Data::Bool( char_value( &args[ 0 ] ).is_some() ) )
 }
 
 
-fn proc_greater( args: &ProcedureArgsArr ) -> Data {
-    proc_compare_helper( args, CompareOrder::Greater, ">" )
-}
+fn proc_char_to_integer( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "char->integer", "1", args.len() ) );
+    }
 
-fn proc_greater_or_equal( args: &ProcedureArgsArr ) -> Data {
-    proc_compare_helper( args, CompareOrder::GreaterEq, ">=" )
+    let ch = char_arg( "char->integer", &args[ 0 ] )?;
+    Ok( Number::Int( ch as i64 ).into_data() )
 }
 
 
-fn proc_compare_helper( args: &ProcedureArgsArr, cmp_ord: CompareOrder , op: &str ) -> Data {
-    if args.is_empty() {
-        print_error( Error::ArityMismatch, op, "at least 1", "0" );
-        return INVALID_DATA;
+fn proc_integer_to_char( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( "integer->char", "1", args.len() ) );
     }
 
-    if args.len() == 1 {
-        return new_true_sym();
-    }
+    let code = match args[ 0 ] {
+        Data::Number( Number::Int( code ) ) => u32::try_from( code ).ok(),
+        _                                   => None,
+    };
 
-    let mut last_arg_int_val = 0 as i64;
-    let mut last_arg_real_val = 0 as f64;
-    let mut curr_arg_int_val: i64;
-    let mut curr_arg_real_val: f64;
-    let mut check_type_is_int = true;
-    
-    for i in 0..args.len() {
-        let arg = &args[ i ];
+    match code.and_then( char::from_u32 ) {
+        Some( ch )  => Ok( Data::Char( ch ) ),
+        None        => Err( SchemeError::contract_violation( "integer->char", "valid-unicode-scalar-value?", &args[ 0 ] ) ),
+    }
+}
 
-        if !is_of_type( &DataType::Integer, &arg ) && !is_of_type( &DataType::Real, &arg ) {
-            print_error( Error::ContractViolation, op, "number?", arg.to_string().as_str() );
-            return INVALID_DATA;
-        }
 
-        check_type_is_int   = check_type_is_int && is_of_type( &DataType::Integer, &arg );
-        curr_arg_int_val    = if check_type_is_int { arg.string.parse::<i64>().unwrap() } else { 0 };
-        curr_arg_real_val   = if check_type_is_int { curr_arg_int_val as f64 } else { arg.string.parse::<f64>().unwrap() };
+/// Maps a char to another char with `convert`, leaving it as it is
+/// when the result isn't a single char (e.g. `ß` upcases to `SS`)
+fn proc_char_map_helper< I: Iterator< Item = char > >( args: &ProcedureArgsArr, op: &str, convert: fn( char ) -> I ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( op, "1", args.len() ) );
+    }
 
-        if i != 0 {
-            if check_type_is_int {
-                if !cmp_exactly( &cmp_ord, last_arg_int_val.cmp( &curr_arg_int_val ) ) {
-                    return new_false_sym();
-                }
-            }
-            else {
-                if !cmp_exactly( &cmp_ord, last_arg_real_val.partial_cmp( &curr_arg_real_val ).unwrap_or( Ordering::Equal ) ) {
-                    return new_false_sym();
-                }
-            }
-        }
+    let ch          = char_arg( op, &args[ 0 ] )?;
+    let mut mapped  = convert( ch );
 
-        last_arg_int_val    = curr_arg_int_val;
-        last_arg_real_val   = curr_arg_real_val;
+    match ( mapped.next(), mapped.next() ) {
+        ( Some( res ), None )   => Ok( Data::Char( res ) ),
+        _                       => Ok( Data::Char( ch ) ),
     }
+}
 
-    new_true_sym()
+
+fn proc_char_upcase( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_char_map_helper( args, "char-upcase", char::to_uppercase )
 }
 
 
-fn cmp_exactly( cmp_ord: &CompareOrder, ord: Ordering ) -> bool {
-    match ord {
-        Ordering::Less      => {
-            match cmp_ord {
-                CompareOrder::Less | CompareOrder::LessEq       => true,
-                _ => false,
-            }
-        },
-        Ordering::Equal     => {
-            match cmp_ord {
-                CompareOrder::GreaterEq | CompareOrder::LessEq  => true,
-                _ => false,
-            }
-        },
-        Ordering::Greater   => {
-            match cmp_ord {
-                CompareOrder::Greater | CompareOrder::GreaterEq => true,
-                _ => false,
-            }
-        },
-    }
+fn proc_char_downcase( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_char_map_helper( args, "char-downcase", char::to_lowercase )
 }
 
 
-fn proc_and( args: &ProcedureArgsArr ) -> Data {
-    for arg in args {
-        if is_false_sym( arg ) {
-            return new_false_sym()
-        }
+fn proc_char_test_helper( args: &ProcedureArgsArr, op: &str, test: fn( char ) -> bool ) -> Result< Data, SchemeError > {
+    if args.len() != 1 {
+        return Err( SchemeError::arity_mismatch( op, "1", args.len() ) );
     }
 
-    new_true_sym()
+    Ok( Data::Bool( test( char_arg( op, &args[ 0 ] )? ) ) )
 }
 
 
-fn proc_or( args: &ProcedureArgsArr ) -> Data {
-    for arg in args {
-        if !is_false_sym( arg ) {
-            return new_true_sym()
-        }
-    }
+fn proc_is_char_alphabetic( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_char_test_helper( args, "char-alphabetic?", char::is_alphabetic )
+}
+
 
-    new_false_sym()
+fn proc_is_char_numeric( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_char_test_helper( args, "char-numeric?", char::is_numeric )
 }
 
 
-fn proc_remainder( args: &ProcedureArgsArr ) -> Data {
-    proc_div_inner( args, true )
+fn proc_is_char_whitespace( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_char_test_helper( args, "char-whitespace?", char::is_whitespace )
 }
 
 
-fn proc_quotient( args: &ProcedureArgsArr ) -> Data {
-    proc_div_inner( args, false )
+fn proc_is_char_upper_case( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_char_test_helper( args, "char-upper-case?", char::is_uppercase )
 }
 
 
-fn proc_div_inner( args: &ProcedureArgsArr, get_rem: bool ) -> Data {
-    let proc = if get_rem { "remainder" } else { "quotient" };
+fn proc_is_char_lower_case( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_char_test_helper( args, "char-lower-case?", char::is_lowercase )
+}
 
-    if args.len() != 2 { 
-        print_error( Error::ArityMismatch, proc, "2", args.len().to_string().as_str() );
-        return INVALID_DATA;
-    }
 
-    for arg in args {
-        if is_false_sym( &proc_is_integer( &vec![ arg.clone() ] ) ) {
-            print_error( Error::ContractViolation, proc, "integer?", arg.to_string().as_str() );
-            return INVALID_DATA;
-        }
-    }
+fn proc_char_equals( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_text_compare_helper( args, CompareOrder::Equal, "char=?", "char?", char_value )
+}
 
-    let lhs = args[ 0 ].string.parse::<i64>().unwrap();
-    let rhs = args[ 1 ].string.parse::<i64>().unwrap();
-    let res_str;
-    if get_rem {
-        res_str = ( lhs % rhs ).to_string();
-    } else {
-        res_str = ( lhs / rhs ).to_string();
-    }
 
-    Data::from_string( DataType::Integer, res_str )
+fn proc_char_less( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_text_compare_helper( args, CompareOrder::Less, "char<?", "char?", char_value )
 }
 
 
-fn proc_expt( args: &ProcedureArgsArr ) -> Data {
-    if args.len() != 2 {
-        print_error( Error::ArityMismatch, "expt", "2", args.len().to_string().as_str() );
-        return INVALID_DATA;
-    }
+fn proc_char_less_eq( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_text_compare_helper( args, CompareOrder::LessEq, "char<=?", "char?", char_value )
+}
 
-    let are_nums    =       ( is_of_type( &DataType::Integer, &args[ 0 ] ) || is_of_type( &DataType::Real, &args[ 0 ] ) )
-                        &&  ( is_of_type( &DataType::Integer, &args[ 1 ] ) || is_of_type( &DataType::Real, &args[ 1 ] ) );
 
-    if !are_nums {
-        print_error( Error::ContractViolation, "expt", "number?", ( args[ 0 ].to_string() + " and " + args[ 0 ].to_string().as_str() ).as_str() );
-        return INVALID_DATA;
-    }
+fn proc_char_greater( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_text_compare_helper( args, CompareOrder::Greater, "char>?", "char?", char_value )
+}
+
 
-    let res = args[ 0 ].to_string().parse::<f64>().unwrap().powf( args[ 1 ].to_string().parse::<f64>().unwrap() );
-    Data::from_string( DataType::Real, res.to_string() )
+fn proc_char_greater_eq( args: &ProcedureArgsArr ) -> Result< Data, SchemeError > {
+    proc_text_compare_helper( args, CompareOrder::GreaterEq, "char>=?", "char?", char_value )
 }
 
 
-fn proc_max( args: &ProcedureArgsArr ) -> Data {
-    if args.is_empty() {
-        print_error( Error::ArityMismatch, "max", "at least 1", "0" );
-        return INVALID_DATA;
+impl Pair {
+
+    pub fn car( &self ) -> Data {
+        self.car.borrow().clone()
     }
 
-    let mut res_is_int  = is_of_type( &DataType::Integer, &args[ 0 ] );
-    let mut int_res     = if res_is_int { args[ 0 ].string.parse::<i64>().unwrap() } else { 0 };
-    let mut real_res    = if res_is_int { int_res as f64 } else { args[ 0 ].string.parse::<f64>().unwrap() };
-    let mut curr_is_int: bool;
-    let mut int_curr: i64;
-    let mut real_curr: f64;
+    pub fn cdr( &self ) -> Data {
+        self.cdr.borrow().clone()
+    }
 
-    for arg in args {
-        if !is_of_type( &DataType::Integer, arg ) && !is_of_type( &DataType::Real, arg ) {
-            print_error( Error::ContractViolation, "max", "number?", arg.to_string().as_str() );
-            return INVALID_DATA;
-        }
+}
 
-        curr_is_int = is_of_type( &DataType::Integer, &arg );
 
-        if res_is_int && !curr_is_int {
-            res_is_int  = false;
-            real_res    = int_res as f64;
-        }
+/// Frees the rest of the list pair by pair. Dropping it recursively would
+/// overflow the stack on long lists
+impl Drop for Pair {
 
-        int_curr    = if curr_is_int { arg.string.parse::<i64>().unwrap() } else { 0 };
-        real_curr   = if curr_is_int { int_curr as f64 } else { arg.string.parse::<f64>().unwrap() };
+    fn drop( &mut self ) {
+        let mut rest = mem::replace( self.cdr.get_mut(), Data::Null );
 
-        if res_is_int && curr_is_int {
-            int_res = if int_res < int_curr { int_curr } else { int_res };
-        }
-        else {
-            real_res = if real_res < real_curr { real_curr } else { real_res };
+        while let Data::Pair( pair ) = rest {
+            rest = match Rc::try_unwrap( pair ) {
+                Ok( mut pair )  => mem::replace( pair.cdr.get_mut(), Data::Null ),
+                Err( _ )        => break,       // Still used elsewhere
+            };
         }
     }
 
-    if res_is_int {
-        Data::from_string( DataType::Integer, int_res.to_string() )
-    }
-    else {
-        Data::from_string( DataType::Real, real_res.to_string() )
-    }
 }
 
 
-fn proc_display( args: &ProcedureArgsArr ) -> Data {
-    if args.len() != 1 {
-        print_error( Error::ArityMismatch, "display", "1", args.len().to_string().as_str() );
-        return INVALID_DATA;
-    }
+/// Pairs are equal when their cars and cdrs are. The parts are compared from a
+/// stack, so long lists don't use up the Rust stack. Two pairs that are already being
+/// compared are taken to be equal when they meet again, so circular structures end
+impl PartialEq for Pair {
 
-    print!( "{}", args[ 0 ].to_string() );
+    fn eq( &self, other: &Pair ) -> bool {
+        let mut comparing   = HashSet::new();
+        let mut todo        = vec![ ( self.car(), other.car() ), ( self.cdr(), other.cdr() ) ];
+        comparing.insert( ( self as *const Pair, other as *const Pair ) );
+
+        while let Some( ( lhs, rhs ) ) = todo.pop() {
+            match ( &lhs, &rhs ) {
+                ( Data::Pair( lhs_pair ), Data::Pair( rhs_pair ) ) => {
+                    if Rc::ptr_eq( lhs_pair, rhs_pair ) || !comparing.insert( ( Rc::as_ptr( lhs_pair ), Rc::as_ptr( rhs_pair ) ) ) {
+                        continue;
+                    }
+
+                    todo.push( ( lhs_pair.cdr(), rhs_pair.cdr() ) );
+                    todo.push( ( lhs_pair.car(), rhs_pair.car() ) );
+                },
+                _ => if lhs != rhs {
+                    return false;
+                },
+            }
+        }
+
+        true
+    }
 
-    new_void_data()
 }
 
 
 impl fmt::Debug for Data {
 
     fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
-        f.debug_tuple( "" )
-            .field( &self.data_type )
-            .field( &self.string )
-            .field( &self.quote_level )
-            .field( &self.list )
-            .finish()
+        write!( f, "{}", self.printed( PrintMode::Write ) )
     }
 
 }
 
 
+/// Values are equal when they have the same structure, like `equal?`.
+/// Procedures are only equal to themselves
 impl PartialEq for Data {
 
     fn eq( &self, other: &Data ) -> bool {
-        self.data_type      == other.data_type      &&
-        self.string         == other.string         &&
-        self.quote_level    == other.quote_level    &&
-        self.list           == other.list
+        match ( self, other ) {
+            ( Data::Void, Data::Void ) | ( Data::Null, Data::Null )     => true,
+            ( Data::Bool( lhs ), Data::Bool( rhs ) )                    => lhs == rhs,
+            ( Data::Number( lhs ), Data::Number( rhs ) )                => lhs == rhs,
+            ( Data::Char( lhs ), Data::Char( rhs ) )                    => lhs == rhs,
+            ( Data::String( lhs ), Data::String( rhs ) )                => lhs == rhs,
+            ( Data::Symbol( lhs ), Data::Symbol( rhs ) )                => lhs == rhs,
+            ( Data::Pair( lhs ), Data::Pair( rhs ) )                    => lhs == rhs,
+            ( Data::Procedure( lhs, _ ), Data::Procedure( rhs, _ ) )    => lhs == rhs,
+            ( Data::Lambda( lhs ), Data::Lambda( rhs ) )                => Rc::ptr_eq( lhs, rhs ),
+            ( Data::Macro( lhs ), Data::Macro( rhs ) )                  => Rc::ptr_eq( lhs, rhs ),
+            ( Data::Control( lhs, _ ), Data::Control( rhs, _ ) )        => lhs == rhs,
+            ( Data::Continuation( lhs ), Data::Continuation( rhs ) )    => lhs == rhs,
+            ( Data::ErrorObject( lhs ), Data::ErrorObject( rhs ) )      => Rc::ptr_eq( lhs, rhs ),
+            ( Data::Values( lhs ), Data::Values( rhs ) )                => lhs == rhs,
+            _                                                           => false,
+        }
     }
 
 }
@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use std::rc::{ Rc, Weak };
+use crate::error::SchemeError;
+use crate::interpreter::Data;
+use crate::interpreter::Environment;
+use crate::interpreter::PrintMode;
+use crate::interpreter::list_parts;
+use crate::interpreter::new_improper_list_data;
+use crate::symbol::Symbol;
+use crate::symbol::BuildSymbolHasher;
+
+
+/// A macro defined with `syntax-rules`. A use of the macro is rewritten by the
+/// first rule whose pattern matches it, and the result is evaluated in its place
+pub struct Macro {
+    literals    : Vec< Symbol >,
+    ellipsis    : Symbol,
+    rules       : Vec< ( Data, Data ) >,       // The pattern and the template of each rule
+    /// Where the free identifiers of the templates are looked up. Uses of the macro
+    /// are always inside this environment, so it outlives them
+    env         : Weak< Environment >,
+}
+
+
+/// What a pattern variable matched. Variables under an ellipsis match a sequence
+#[derive( Clone )]
+enum Binding {
+    One( Data ),
+    Many( Vec< Binding > ),
+}
+
+
+type Bindings = HashMap< Symbol, Binding, BuildSymbolHasher >;
+type Renames  = HashMap< Symbol, Symbol, BuildSymbolHasher >;
+/// The number of ellipses each pattern variable is under
+type Depths   = HashMap< Symbol, usize, BuildSymbolHasher >;
+
+
+impl Macro {
+
+    /// Reads `(syntax-rules (literal ...) (pattern template) ...)`, optionally with a
+    /// custom ellipsis identifier before the literals
+    pub fn new( form_name: &str, spec: &Data, env: &Rc< Environment > ) -> Result< Macro, SchemeError > {
+        let ( spec_items, spec_tail ) = list_parts( spec );
+
+        let is_syntax_rules = matches!( spec_items.first(), Some( Data::Symbol( sym ) ) if sym.as_str() == "syntax-rules" );
+        if !is_syntax_rules || !matches!( spec_tail, Data::Null ) {
+            return Err( SchemeError::bad_syntax( form_name, "a syntax-rules transformer", spec.printed( PrintMode::Write ) ) );
+        }
+
+        let ( ellipsis, rest ) = match &spec_items[ 1.. ] {
+            [ Data::Symbol( ellipsis ), rest @ .. ] => ( ellipsis.clone(), rest ),
+            rest                                    => ( Symbol::new( "..." ), rest ),
+        };
+
+        let ( literals, rules ) = match rest.split_first() {
+            Some( ( literals, rules ) ) => ( literals, rules ),
+            None                        => return Err( SchemeError::bad_syntax( "syntax-rules", "a list of literals and rules", spec.printed( PrintMode::Write ) ) ),
+        };
+
+        let ( literal_items, literals_tail ) = list_parts( literals );
+        if !matches!( literals_tail, Data::Null ) {
+            return Err( SchemeError::bad_syntax( "syntax-rules", "a list of literal identifiers", literals.printed( PrintMode::Write ) ) );
+        }
+
+        let literals = literal_items.iter()
+            .map( |literal| match literal {
+                Data::Symbol( sym ) => Ok( sym.clone() ),
+                _                   => Err( SchemeError::bad_syntax( "syntax-rules", "identifier?", literal.printed( PrintMode::Write ) ) ),
+            } )
+            .collect::< Result< Vec< Symbol >, SchemeError > >()?;
+
+        let rules = rules.iter()
+            .map( |rule| match list_parts( rule ) {
+                ( items, Data::Null ) if items.len() == 2 && matches!( items[ 0 ], Data::Pair( _ ) ) => Ok( ( items[ 0 ].clone(), items[ 1 ].clone() ) ),
+                _ => Err( SchemeError::bad_syntax( "syntax-rules", "a pattern and a template for a rule", rule.printed( PrintMode::Write ) ) ),
+            } )
+            .collect::< Result< Vec< ( Data, Data ) >, SchemeError > >()?;
+
+        let res = Macro { literals, ellipsis, rules, env: Rc::downgrade( env ) };
+        for ( pattern, template ) in &res.rules {
+            res.check_rule( pattern, template )?;
+        }
+
+        Ok( res )
+    }
+
+
+    /// Rewrites a use of the macro. The identifiers that the template introduces are
+    /// renamed to fresh symbols, so they can't capture or be captured by the caller's
+    /// variables. The free ones still refer to the variables where the macro was defined
+    pub fn expand( &self, form: &Data ) -> Result< Data, SchemeError > {
+        let ( name, args ) = match form {
+            Data::Pair( pair )  => ( pair.car(), pair.cdr() ),
+            _                   => unreachable!(),
+        };
+
+        for ( pattern, template ) in &self.rules {
+            // The keyword position of the pattern is ignored
+            let pattern_args    = match pattern { Data::Pair( pair ) => pair.cdr(), _ => unreachable!() };
+            let mut bindings    = Bindings::default();
+
+            if self.match_pattern( &pattern_args, &args, &mut bindings ) {
+                let mut renames = Renames::default();
+                return self.expand_template( template, &bindings, &mut renames, true, true );
+            }
+        }
+
+        Err( SchemeError::bad_syntax( &name.printed( PrintMode::Write ).to_string(), "", form.printed( PrintMode::Write ) ) )
+    }
+
+
+    /// The ellipsis is compared by name, since a macro defined by another macro gets a renamed one
+    fn is_ellipsis( &self, data: &Data ) -> bool {
+        matches!( data, Data::Symbol( sym ) if sym.as_str() == self.ellipsis.as_str() )
+    }
+
+
+    fn match_pattern( &self, pattern: &Data, form: &Data, bindings: &mut Bindings ) -> bool {
+        match pattern {
+            Data::Symbol( sym ) if sym.as_str() == "_" => {
+                true
+            },
+            Data::Symbol( sym ) if self.literals.contains( sym ) => {
+                matches!( form, Data::Symbol( other ) if other.as_str() == sym.as_str() )
+            },
+            Data::Symbol( sym ) => {
+                bindings.insert( sym.clone(), Binding::One( form.clone() ) );
+                true
+            },
+            Data::Pair( _ ) => {
+                self.match_list_pattern( pattern, form, bindings )
+            },
+            _ => {
+                pattern == form
+            },
+        }
+    }
+
+
+    /// Matches `(p ...)`, `(p ... . tail)` and `(a b p ... c d)`. A pattern element
+    /// followed by the ellipsis matches as many elements as the others leave over
+    fn match_list_pattern( &self, pattern: &Data, form: &Data, bindings: &mut Bindings ) -> bool {
+        let ( items, tail )             = list_parts( pattern );
+        let ( form_items, form_tail )   = list_parts( form );
+
+        let ellipsis_index = items.iter().position( |item| self.is_ellipsis( item ) );
+        let ( before, repeated, after ) = match ellipsis_index {
+            Some( index ) if index > 0  => ( &items[ ..index - 1 ], Some( &items[ index - 1 ] ), &items[ index + 1.. ] ),
+            _                           => ( &items[ .. ], None, &items[ ..0 ] ),
+        };
+
+        let fixed_len = before.len() + after.len();
+        if form_items.len() < fixed_len || ( repeated.is_none() && matches!( tail, Data::Null ) && form_items.len() != fixed_len ) {
+            return false;
+        }
+
+        for ( item, form_item ) in before.iter().zip( &form_items ) {
+            if !self.match_pattern( item, form_item, bindings ) {
+                return false;
+            }
+        }
+
+        let rest_start = match repeated {
+            Some( repeated ) => {
+                let repeated_end = form_items.len() - after.len();
+                if !self.match_repeated( repeated, &form_items[ before.len()..repeated_end ], bindings ) {
+                    return false;
+                }
+
+                for ( item, form_item ) in after.iter().zip( &form_items[ repeated_end.. ] ) {
+                    if !self.match_pattern( item, form_item, bindings ) {
+                        return false;
+                    }
+                }
+
+                form_items.len()
+            },
+            None => before.len(),
+        };
+
+        // A dotted tail matches whatever is left of the form
+        let form_rest = new_improper_list_data( form_items[ rest_start.. ].to_vec(), form_tail );
+        self.match_pattern( &tail, &form_rest, bindings )
+    }
+
+
+    fn match_repeated( &self, pattern: &Data, form_items: &[ Data ], bindings: &mut Bindings ) -> bool {
+        let mut vars = vec![];
+        self.pattern_vars( pattern, &mut vars );
+
+        let mut matches = vec![ vec![]; vars.len() ];
+        for form_item in form_items {
+            let mut item_bindings = Bindings::default();
+            if !self.match_pattern( pattern, form_item, &mut item_bindings ) {
+                return false;
+            }
+
+            for ( var, var_matches ) in vars.iter().zip( &mut matches ) {
+                var_matches.push( item_bindings.remove( var ).unwrap() );
+            }
+        }
+
+        for ( var, var_matches ) in vars.into_iter().zip( matches ) {
+            bindings.insert( var, Binding::Many( var_matches ) );
+        }
+
+        true
+    }
+
+
+    fn is_pattern_var( &self, data: &Data ) -> bool {
+        matches!( data, Data::Symbol( sym ) if sym.as_str() != "_" && !self.literals.contains( sym ) && !self.is_ellipsis( data ) )
+    }
+
+
+    fn pattern_vars( &self, pattern: &Data, vars: &mut Vec< Symbol > ) {
+        match pattern {
+            Data::Symbol( sym ) if self.is_pattern_var( pattern ) => {
+                vars.push( sym.clone() );
+            },
+            Data::Pair( pair ) => {
+                self.pattern_vars( &pair.car(), vars );
+                self.pattern_vars( &pair.cdr(), vars );
+            },
+            _ => {},
+        }
+    }
+
+
+    /// Checks a rule when the macro is defined, so that a mistake in it is reported
+    /// even if the rule never matches
+    fn check_rule( &self, pattern: &Data, template: &Data ) -> Result< (), SchemeError > {
+        let pattern_args    = match pattern { Data::Pair( pair ) => pair.cdr(), _ => unreachable!() };
+        let mut depths      = Depths::default();
+        self.check_pattern( &pattern_args, 0, &mut depths )?;
+        self.check_template( template, 0, &depths, true )
+    }
+
+
+    /// Records how many ellipses each pattern variable is under. A variable can
+    /// only be used once, and a list can only have one ellipsis
+    fn check_pattern( &self, pattern: &Data, depth: usize, depths: &mut Depths ) -> Result< (), SchemeError > {
+        match pattern {
+            Data::Symbol( sym ) if self.is_pattern_var( pattern ) => {
+                match depths.insert( sym.clone(), depth ) {
+                    Some( _ )   => Err( SchemeError::bad_syntax( "syntax-rules", "each pattern variable only once", sym ) ),
+                    None        => Ok( () ),
+                }
+            },
+            Data::Pair( _ ) => {
+                let ( items, tail )         = list_parts( pattern );
+                let ellipses: Vec< usize > = items.iter().enumerate().filter( |( _, item )| self.is_ellipsis( item ) ).map( |( i, _ )| i ).collect();
+                if ellipses.len() > 1 || ellipses.first() == Some( &0 ) {
+                    return Err( SchemeError::bad_syntax( "syntax-rules", "at most one ellipsis, after a pattern", pattern.printed( PrintMode::Write ) ) );
+                }
+
+                for ( i, item ) in items.iter().enumerate().filter( |( _, item )| !self.is_ellipsis( item ) ) {
+                    let is_repeated = ellipses.first() == Some( &( i + 1 ) );
+                    self.check_pattern( item, depth + usize::from( is_repeated ), depths )?;
+                }
+
+                self.check_pattern( &tail, depth, depths )
+            },
+            _ => Ok( () ),
+        }
+    }
+
+
+    /// Checks that every pattern variable is followed by as many ellipses as it was in
+    /// the pattern, and that every ellipsis follows a template with a variable to repeat.
+    /// The template is walked the way `expand_template` walks it
+    fn check_template( &self, template: &Data, depth: usize, depths: &Depths, can_repeat: bool ) -> Result< (), SchemeError > {
+        match template {
+            Data::Symbol( sym ) => {
+                match depths.get( sym ) {
+                    Some( var_depth ) if *var_depth > depth => Err( SchemeError::bad_syntax( "syntax-rules", "an ellipsis after the pattern variable", sym ) ),
+                    _                                       => Ok( () ),
+                }
+            },
+            Data::Pair( _ ) => {
+                let ( items, tail ) = list_parts( template );
+
+                if can_repeat && items.len() == 2 && self.is_ellipsis( &items[ 0 ] ) && matches!( tail, Data::Null ) {
+                    return self.check_template( &items[ 1 ], depth, depths, false );
+                }
+
+                let mut i = 0;
+                while i < items.len() {
+                    let mut ellipses = 0;
+                    while can_repeat && i + ellipses + 1 < items.len() && self.is_ellipsis( &items[ i + ellipses + 1 ] ) {
+                        ellipses += 1;
+                    }
+
+                    if ellipses > 0 && self.max_depth( &items[ i ], depths ) < depth + ellipses {
+                        return Err( SchemeError::bad_syntax( "syntax-rules", "a pattern variable before the ellipsis", items[ i ].printed( PrintMode::Write ) ) );
+                    }
+
+                    self.check_template( &items[ i ], depth + ellipses, depths, can_repeat )?;
+                    i += ellipses + 1;
+                }
+
+                self.check_template( &tail, depth, depths, can_repeat )
+            },
+            _ => Ok( () ),
+        }
+    }
+
+
+    /// The most ellipses that any pattern variable in the template was under
+    fn max_depth( &self, template: &Data, depths: &Depths ) -> usize {
+        let mut vars = vec![];
+        self.pattern_vars( template, &mut vars );
+        vars.iter().filter_map( |var| depths.get( var ) ).copied().max().unwrap_or( 0 )
+    }
+
+
+    /// `can_rename` is false inside `quote` and `quasiquote`, whose symbols are data rather than identifiers,
+    /// and `can_repeat` is false inside `(... template)`, where the ellipsis stands for itself
+    fn expand_template( &self, template: &Data, bindings: &Bindings, renames: &mut Renames, can_rename: bool, can_repeat: bool ) -> Result< Data, SchemeError > {
+        match template {
+            Data::Symbol( sym ) => {
+                match bindings.get( sym ) {
+                    Some( Binding::One( value ) )   => Ok( value.clone() ),
+                    Some( Binding::Many( _ ) )      => Err( SchemeError::bad_syntax( "syntax-rules", "an ellipsis after the pattern variable", sym ) ),
+                    None if !can_rename             => Ok( template.clone() ),
+                    None                            => {
+                        let renamed = renames.entry( sym.clone() ).or_insert_with( || sym.renamed( &self.env ) );
+                        Ok( Data::Symbol( renamed.clone() ) )
+                    },
+                }
+            },
+            Data::Pair( _ ) => {
+                let ( items, tail ) = list_parts( template );
+
+                if can_repeat && items.len() == 2 && self.is_ellipsis( &items[ 0 ] ) && matches!( tail, Data::Null ) {
+                    return self.expand_template( &items[ 1 ], bindings, renames, can_rename, false );
+                }
+
+                // Inside quasiquote only the unquoted parts are code
+                let can_rename = match &items[ 0 ] {
+                    Data::Symbol( sym ) if sym.as_str() == "quote" || sym.as_str() == "quasiquote"          => false,
+                    Data::Symbol( sym ) if sym.as_str() == "unquote" || sym.as_str() == "unquote-splicing"  => true,
+                    _                                                                                       => can_rename,
+                };
+
+                let mut res = vec![];
+                let mut i   = 0;
+                while i < items.len() {
+                    let mut depth = 0;
+                    while can_repeat && i + depth + 1 < items.len() && self.is_ellipsis( &items[ i + depth + 1 ] ) {
+                        depth += 1;
+                    }
+
+                    if depth == 0 {
+                        res.push( self.expand_template( &items[ i ], bindings, renames, can_rename, can_repeat )? );
+                    }
+                    else {
+                        self.expand_repeated( &items[ i ], depth, bindings, renames, can_rename, &mut res )?;
+                    }
+
+                    i += depth + 1;
+                }
+
+                let tail = self.expand_template( &tail, bindings, renames, can_rename, can_repeat )?;
+                Ok( new_improper_list_data( res, tail ) )
+            },
+            _ => {
+                Ok( template.clone() )
+            },
+        }
+    }
+
+
+    /// Expands a template followed by `depth` ellipses once for every match of the
+    /// sequence variables it uses, which must all have matched the same number of times
+    fn expand_repeated( &self, template: &Data, depth: usize, bindings: &Bindings, renames: &mut Renames, can_rename: bool, res: &mut Vec< Data > ) -> Result< (), SchemeError > {
+        let mut vars = vec![];
+        self.pattern_vars( template, &mut vars );
+        vars.retain( |var| matches!( bindings.get( var ), Some( Binding::Many( _ ) ) ) );
+
+        let mut len = None;
+        for var in &vars {
+            let var_len = match bindings.get( var ) { Some( Binding::Many( values ) ) => values.len(), _ => unreachable!() };
+            if len.is_some_and( |len| len != var_len ) {
+                return Err( SchemeError::bad_syntax( "syntax-rules", "sequences of the same length", template.printed( PrintMode::Write ) ) );
+            }
+            len = Some( var_len );
+        }
+
+        let len = match len {
+            Some( len ) => len,
+            None        => return Err( SchemeError::bad_syntax( "syntax-rules", "a pattern variable before the ellipsis", template.printed( PrintMode::Write ) ) ),
+        };
+
+        for i in 0..len {
+            let mut item_bindings = bindings.clone();
+            for var in &vars {
+                if let Some( Binding::Many( values ) ) = bindings.get( var ) {
+                    item_bindings.insert( var.clone(), values[ i ].clone() );
+                }
+            }
+
+            if depth > 1 {
+                self.expand_repeated( template, depth - 1, &item_bindings, renames, can_rename, res )?;
+            }
+            else {
+                res.push( self.expand_template( template, &item_bindings, renames, can_rename, true )? );
+            }
+        }
+
+        Ok( () )
+    }
+
+}
@@ -1,35 +1,64 @@
 mod parser;
 mod interpreter;
+mod error;
+mod number;
+mod symbol;
+mod macros;
 use parser::*;
 use interpreter::*;
 use std::io;
-use std::fs;
+use std::io::Write;
 use regex::Regex;
 
 
+const PROMPT                : &str = "> ";
+const CONTINUATION_PROMPT   : &str = "... ";
+
+
 fn main() {
 
-    let mut environment = Environment::new();
+    let environment     = Environment::new();
     let mut parser      = Parser::new();
     let mut input       = String::new();
 
     loop {
         input.clear();
-        if let Err( e ) = io::stdin().read_line( &mut input ) {
-            println!( "{}", e );
-            break;
+
+        // Keep reading lines until the expression is complete
+        loop {
+            print!( "{}", if input.is_empty() { PROMPT } else { CONTINUATION_PROMPT } );
+            if let Err( e ) = io::stdout().flush() {
+                println!( "{}", e );
+            }
+
+            match io::stdin().read_line( &mut input ) {
+                Ok( 0 )     => {    // EOF
+                    println!();
+                    return;
+                },
+                Ok( _ )     => {},
+                Err( e )    => {
+                    println!( "{}", e );
+                    return;
+                },
+            }
+
+            if is_input_complete( input.as_str() ) {
+                break;
+            }
         }
 
         if is_exit_input( input.as_str() ) {
             break;
         }
 
-        input = load_file( input );
-
         parser.load( input.as_str() );
 
         for data in &mut parser {
-            println!( "{}", environment.eval( &data ) );
+            match data.and_then( |data| environment.eval( &data ) ) {
+                Ok( res )   => println!( "{}", res ),
+                Err( e )    => println!( "{}", e ),
+            }
         }
     }
 
@@ -42,48 +71,43 @@ fn is_exit_input( string: &str ) -> bool {
 }
 
 
-fn load_file( input: String ) -> String {
-    let re = Regex::new( "^ *\\( *load \"([^\\(\\)]+)\" *\\) *$" ).unwrap();
-    if let Some( capts ) = re.captures( input.as_str().trim() ) {
-        if capts.len() == 2 {
-            let file_name = capts.get( 1 ).map_or( "", |m| m.as_str() );
-            let result = fs::read_to_string( file_name );
-            if let Ok( content ) = result {
-                content
-            }
-            else {
-                println!( "open-input-file: cannot open input file\n  file: `{}`", file_name );
-                input
-            }
-        }
-        else {
-            input
-        }
-    }
-    else {
-        input
-    }
-}
-
-
-
-
-
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use error::SchemeError;
+    use number::Number;
+    use symbol::Symbol;
+    use std::fs;
+    use std::rc::Rc;
+
+    fn eval_all( environment: &Rc< Environment >, input: &str ) -> String {
+        let mut parser  = Parser::new();
+        let mut res     = String::new();
+        parser.load( input );
+        for data in &mut parser {
+            res = match data.and_then( |data| environment.eval( &data ) ) {
+                Ok( res )   => res.to_string(),
+                Err( e )    => e.to_string(),
+            };
+        }
+        res
+    }
 
     #[test]
     fn test_parser_basics() {
         let mut parser = Parser::new();
         parser.load( "       0  \r\n    1   \r   2  \n 3   \t4" );
         for ( i, data ) in (&mut parser).enumerate() {
-            assert_eq!( data, Data::from_string( DataType::Integer, i.to_string() ) );
+            assert_eq!( data, Ok( Data::Number( Number::Int( i as i64 ) ) ) );
         }
 
         parser.load( " (         define    x 5.0)          " );
-        assert_eq!( parser.next().unwrap(), Data::from_list( vec![ Data::from_string( DataType::Variable, "define".to_string() ), Data::from_string( DataType::Variable, "x".to_string() ), Data::from_string( DataType::Real, "5.0".to_string() ) ] ) );
+        assert_eq!( parser.next().unwrap().unwrap(), new_list_data( vec![ Data::Symbol( Symbol::new( "define" ) ), Data::Symbol( Symbol::new( "x" ) ), Data::Number( Number::Real( 5.0 ) ) ] ) );
+
+        parser.load( "'a #t #false" );
+        assert_eq!( parser.next().unwrap().unwrap(), new_list_data( vec![ Data::Symbol( Symbol::new( "quote" ) ), Data::Symbol( Symbol::new( "a" ) ) ] ) );
+        assert_eq!( parser.next().unwrap().unwrap(), Data::Bool( true ) );
+        assert_eq!( parser.next().unwrap().unwrap(), Data::Bool( false ) );
     
         parser.load( "" );
         assert!( parser.next().is_none() );
@@ -92,43 +116,946 @@ mod tests {
     #[test]
     fn test_eval_basics() {
         let mut parser      = Parser::new();
-        let mut environment = Environment::new();
+        let environment     = Environment::new();
         parser.load( "+ car (define x 5.0) x (x) (define (1+ x) (+ x 1)) (1+ 5) (if (<= 2 3) 1.0 0) #t #f (null? '())" );
         
-        assert_eq!( environment.eval( &parser.next().unwrap() ), Data::from_string( DataType::Procedure, "+".to_string() ) );
-        assert_eq!( environment.eval( &parser.next().unwrap() ), Data::from_string( DataType::Procedure, "car".to_string() ) );
-        assert_eq!( environment.eval( &parser.next().unwrap() ), Data::from_string( DataType::Real, "5.0".to_string() ) );
-        assert_eq!( environment.eval( &parser.next().unwrap() ), Data::from_string( DataType::Real, "5.0".to_string() ) );
-        assert_eq!( environment.eval( &parser.next().unwrap() ), INVALID_DATA );
-        assert!( !is_invalid_data( &environment.eval( &parser.next().unwrap() ) ) );
-        assert_eq!( environment.eval( &parser.next().unwrap() ), Data::from_string( DataType::Integer, "6".to_string() ) );
-        assert_eq!( environment.eval( &parser.next().unwrap() ), Data::from_string( DataType::Real, "1.0".to_string() ) );
-        assert_eq!( environment.eval( &parser.next().unwrap() ), new_true_sym() );
-        assert_eq!( environment.eval( &parser.next().unwrap() ), new_false_sym() );
-        assert_eq!( environment.eval( &parser.next().unwrap() ), new_true_sym() );
+        assert_eq!( environment.eval( &parser.next().unwrap().unwrap() ).unwrap().to_string(), "#<procedure:+>" );
+        assert_eq!( environment.eval( &parser.next().unwrap().unwrap() ).unwrap().to_string(), "#<procedure:car>" );
+        assert_eq!( environment.eval( &parser.next().unwrap().unwrap() ), Ok( Data::Number( Number::Real( 5.0 ) ) ) );
+        assert_eq!( environment.eval( &parser.next().unwrap().unwrap() ), Ok( Data::Number( Number::Real( 5.0 ) ) ) );
+        assert_eq!( environment.eval( &parser.next().unwrap().unwrap() ), Err( SchemeError::not_a_procedure( "x" ) ) );
+        assert!( environment.eval( &parser.next().unwrap().unwrap() ).is_ok() );
+        assert_eq!( environment.eval( &parser.next().unwrap().unwrap() ), Ok( Data::Number( Number::Int( 6 ) ) ) );
+        assert_eq!( environment.eval( &parser.next().unwrap().unwrap() ), Ok( Data::Number( Number::Real( 1.0 ) ) ) );
+        assert_eq!( environment.eval( &parser.next().unwrap().unwrap() ), Ok( Data::Bool( true ) ) );
+        assert_eq!( environment.eval( &parser.next().unwrap().unwrap() ), Ok( Data::Bool( false ) ) );
+        assert_eq!( environment.eval( &parser.next().unwrap().unwrap() ), Ok( Data::Bool( true ) ) );
     }
 
     #[test]
     fn test_knapsack() {
-        let mut input       = "(load \"basic-procs.scm\")".to_string();
-        let mut parser      = Parser::new();
-        let mut environment = Environment::new();
+        let environment = Environment::new();
 
-        input = load_file( input );
-        parser.load( input.as_str() );
-        for data in &mut parser {
-            assert!( !is_invalid_data( &environment.eval( &data ) ) );
-        }
+        assert_eq!( eval_all( &environment, "(load \"basic-procs.scm\")" ), "#<procedure:flatten>" );
+        assert_eq!( eval_all( &environment, "(load \"2.scm\")" ), "#<procedure:p>" );
+        assert_eq!( eval_all( &environment, "(knapsack 50 3 w p)" ), "6" );
+    }
 
-        input = "(load \"2.scm\")".to_string();
-        input = load_file( input );
-        parser.load( input.as_str() );
-        for data in &mut parser {
-            assert!( !is_invalid_data( &environment.eval( &data ) ) );
+    #[test]
+    fn test_closures() {
+        let environment = Environment::new();
+
+        eval_all( &environment, "(define (make-adder n) (lambda (x) (+ x n))) (define add5 (make-adder 5))" );
+        assert_eq!( eval_all( &environment, "(add5 10)" ), "15" );
+        assert_eq!( eval_all( &environment, "((make-adder 1) 1)" ), "2" );
+
+        eval_all( &environment, "(define (curry f) (lambda (x) (lambda (y) (f x y))))" );
+        assert_eq!( eval_all( &environment, "(((curry -) 10) 3)" ), "7" );
+
+        // Free variables are looked up where the lambda was defined, not where it is called
+        eval_all( &environment, "(define x 1) (define (get-x) x) (define (f x) (get-x))" );
+        assert_eq!( eval_all( &environment, "(f 2)" ), "1" );
+
+        eval_all( &environment, "(define (get-y) y) (define y 3)" );
+        assert_eq!( eval_all( &environment, "(get-y)" ), "3" );
+    }
+
+    #[test]
+    fn test_closure_cycles() {
+        let environment = Environment::new();
+
+        // Every call binds a procedure in the environment the procedure closes over
+        eval_all( &environment, "(define (f n) (define (g) n) (g))" );
+        eval_all( &environment, "(define (run i) (if (> i 0) (begin (f i) (run (- i 1)))))" );
+        eval_all( &environment, "(run 50000) (collect-garbage)" );
+        assert!( Environment::tracked_count() < 10 );
+
+        // A closure that is still used keeps the environments it refers to
+        eval_all( &environment, "(define (make-counter) (define n 0) (define (next) (set! n (+ n 1)) n) next)" );
+        eval_all( &environment, "(define counter (make-counter)) (counter) (run 50000) (collect-garbage)" );
+        assert_eq!( eval_all( &environment, "(counter)" ), "2" );
+        assert_eq!( eval_all( &environment, "(let ((counters (list (make-counter)))) (collect-garbage) ((car counters)))" ), "1" );
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let environment = Environment::new();
+
+        eval_all( &environment, "(define (count-down n) (if (= n 0) 'done (count-down (- n 1))))" );
+        assert_eq!( eval_all( &environment, "(count-down 100000)" ), "'done" );
+        assert_eq!( eval_all( &environment, "(count-down 1000000)" ), "'done" );
+
+        eval_all( &environment, "(define (count-cond n acc) (cond ((= n 0) acc) (else (count-cond (- n 1) (+ acc 1)))))" );
+        assert_eq!( eval_all( &environment, "(count-cond 100000 0)" ), "100000" );
+
+        eval_all( &environment, "(define (my-even? n) (if (= n 0) #t (my-odd? (- n 1)))) (define (my-odd? n) (if (= n 0) #f (my-even? (- n 1))))" );
+        assert_eq!( eval_all( &environment, "(my-even? 100001)" ), "#f" );
+
+        // Named loop defined inside a procedure, like the ones in basic-procs.scm
+        eval_all( &environment, "(define (sum-to n) (define (loop i acc) (if (> i n) acc (loop (+ i 1) (+ acc i)))) (loop 0 0))" );
+        assert_eq!( eval_all( &environment, "(sum-to 100000)" ), "5000050000" );
+    }
+
+    #[test]
+    fn test_errors() {
+        let environment = Environment::new();
+
+        assert_eq!( environment.eval( &Data::Symbol( Symbol::new( "y" ) ) ), Err( SchemeError::undefined( "y" ) ) );
+        assert_eq!( eval_all( &environment, "(car 1)" ), "car: contract violation;\n  expected: pair?\n  given: 1" );
+        assert_eq!( eval_all( &environment, "(null? 1 2)" ), "null?: arity mismatch;\n the expected number of arguments does not match the given number\n  expected: 1\n  given: 2" );
+        assert_eq!( eval_all( &environment, "(+ 1 (car '()))" ), "car: contract violation;\n  expected: pair?\n  given: '()" );
+        assert_eq!( eval_all( &environment, "(1 2)" ), "application: not a procedure;\n expected a procedure that can be applied to arguments\n  given: 1" );
+        assert_eq!( eval_all( &environment, "(define (f) (display 1))" ), "#<procedure:f>" );
+        assert!( eval_all( &environment, "(f 1)" ).starts_with( "f: arity mismatch;" ) );
+
+        let mut parser = Parser::new();
+        parser.load( "(+ 1 2" );
+        assert_eq!( parser.next(), Some( Err( SchemeError::read_syntax( "expected a `)` to close `(`" ) ) ) );
+        assert!( parser.next().is_none() );
+    }
+
+    #[test]
+    fn test_input_completeness() {
+        assert!( is_input_complete( "" ) );
+        assert!( is_input_complete( "(define x 5)" ) );
+        assert!( is_input_complete( "1 2 (+ 1 2)\n" ) );
+        assert!( is_input_complete( "(car '(1 2)))" ) );
+        assert!( is_input_complete( "(display \")(\")" ) );
+
+        assert!( !is_input_complete( "(define (f x)\n" ) );
+        assert!( !is_input_complete( "(define (f x)\n  (+ x\n" ) );
+        assert!( !is_input_complete( "(display \"a\n" ) );
+        assert!( !is_input_complete( "'" ) );
+        assert!( !is_input_complete( "(car ' \n" ) );
+        assert!( !is_input_complete( "(list '|a )" ) );
+        assert!( is_input_complete( "(list '|a )| 1)" ) );
+
+        assert!( is_input_complete( "(define (f x)\n  (+ x\n     1))\n" ) );
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join( format!( "scheme-load-test-{}", std::process::id() ) );
+        fs::create_dir_all( dir.join( "lib" ) ).unwrap();
+        fs::write( dir.join( "main.scm" ), "(load \"lib/helpers.scm\")\n(define result (twice 21))" ).unwrap();
+        fs::write( dir.join( "lib/helpers.scm" ), "(define (twice x)\n  (* 2 x))\n(if #t (load \"more.scm\") 0)" ).unwrap();
+        fs::write( dir.join( "lib/more.scm" ), "(define loaded-more #t)" ).unwrap();
+        fs::write( dir.join( "cycle.scm" ), "(load \"cycle.scm\")" ).unwrap();
+        fs::write( dir.join( "broken.scm" ), "(define x 1)\n(car x)" ).unwrap();
+
+        let environment = Environment::new();
+        let main_file   = dir.join( "main.scm" );
+        eval_all( &environment, format!( "(define file \"{}\") (load file)", main_file.display() ).as_str() );
+        assert_eq!( eval_all( &environment, "result" ), "42" );
+        assert_eq!( eval_all( &environment, "loaded-more" ), "#t" );
+
+        let cycle_file = fs::canonicalize( dir.join( "cycle.scm" ) ).unwrap();
+        let res = eval_all( &environment, format!( "(load \"{}\")", cycle_file.display() ).as_str() );
+        assert!( res.starts_with( "load: cycle detected;" ) );
+        assert!( res.contains( format!( "in file: `{}`", cycle_file.display() ).as_str() ) );
+
+        let broken_file = fs::canonicalize( dir.join( "broken.scm" ) ).unwrap();
+        let res = eval_all( &environment, format!( "(load \"{}\")", broken_file.display() ).as_str() );
+        assert!( res.starts_with( "car: contract violation;" ) );
+        assert!( res.ends_with( format!( "in file: `{}`", broken_file.display() ).as_str() ) );
+
+        assert!( eval_all( &environment, "(load \"no-such-file.scm\")" ).starts_with( "open-input-file: cannot open input file" ) );
+        assert!( eval_all( &environment, "(load 1)" ).starts_with( "load: contract violation;" ) );
+
+        fs::remove_dir_all( dir ).unwrap();
+    }
+
+    #[test]
+    fn test_mutation() {
+        let environment = Environment::new();
+
+        eval_all( &environment, "(define (make-counter) (define n 0) (lambda () (set! n (+ n 1)) n)) (define c1 (make-counter)) (define c2 (make-counter))" );
+        assert_eq!( eval_all( &environment, "(c1) (c1) (c1)" ), "3" );
+        assert_eq!( eval_all( &environment, "(c2)" ), "1" );
+
+        eval_all( &environment, "(define total 0) (define (add! x) (set! total (+ total x)))" );
+        assert_eq!( eval_all( &environment, "(add! 5) (add! 10) total" ), "15" );
+        assert_eq!( eval_all( &environment, "(set! undefined-var 1)" ), "set!: assignment disallowed;\n cannot set variable before its definition\n  variable: undefined-var" );
+        assert!( eval_all( &environment, "(set! 1 2)" ).starts_with( "set!: bad syntax;" ) );
+
+        eval_all( &environment, "(define p (list 1 2 3))" );
+        assert_eq!( eval_all( &environment, "(set-car! p 10) p" ), "'(10 2 3)" );
+        assert_eq!( eval_all( &environment, "(set-cdr! p '(20)) p" ), "'(10 20)" );
+        assert_eq!( eval_all( &environment, "(set-cdr! p 5) p" ), "'(10 . 5)" );
+        assert_eq!( eval_all( &environment, "(cdr p)" ), "5" );
+        assert_eq!( eval_all( &environment, "(set-car! '() 1)" ), "set-car!: contract violation;\n  expected: pair?\n  given: '()" );
+
+        // The pair itself changes, so every variable and list that shares it sees the change
+        eval_all( &environment, "(define q (list 1 2 3)) (define alias q) (define outer (list q))" );
+        assert_eq!( eval_all( &environment, "(set-car! (cdr q) 'x) alias" ), "'(1 x 3)" );
+        assert_eq!( eval_all( &environment, "(set-cdr! (cddr alias) '(4)) (car outer)" ), "'(1 x 3 4)" );
+        assert_eq!( eval_all( &environment, "(for-each set-car! (list q alias) '(a b)) q" ), "'(b x 3 4)" );
+        assert_eq!( eval_all( &environment, "(let ((mutate set-cdr!)) (mutate q '()) alias)" ), "'(b)" );
+    }
+
+    #[test]
+    fn test_let_forms() {
+        let environment = Environment::new();
+
+        eval_all( &environment, "(define x 10)" );
+        assert_eq!( eval_all( &environment, "(let ((x 1) (y x)) (+ x y))" ), "11" );
+        assert_eq!( eval_all( &environment, "(let () 5)" ), "5" );
+        assert_eq!( eval_all( &environment, "(let* ((x 1) (y (+ x 1)) (x (* y 10))) (list x y))" ), "'(20 2)" );
+        assert_eq!( eval_all( &environment, "x" ), "10" );
+
+        eval_all( &environment, "(define (parity n) (letrec ((ev? (lambda (n) (if (= n 0) #t (od? (- n 1))))) (od? (lambda (n) (if (= n 0) #f (ev? (- n 1)))))) (ev? n)))" );
+        assert_eq!( eval_all( &environment, "(parity 10)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(parity 7)" ), "#f" );
+        assert_eq!( eval_all( &environment, "(letrec* ((a 1) (b (+ a 1))) b)" ), "2" );
+
+        // Named let iterates in constant stack
+        assert_eq!( eval_all( &environment, "(let loop ((i 0) (acc 0)) (if (= i 100000) acc (loop (+ i 1) (+ acc 2))))" ), "200000" );
+        assert_eq!( eval_all( &environment, "(let fact ((n 5)) (if (= n 0) 1 (* n (fact (- n 1)))))" ), "120" );
+        assert_eq!( eval_all( &environment, "(let f ((f 1)) f)" ), "1" );
+
+        // Entering a named let doesn't leave a reference cycle behind
+        eval_all( &environment, "(define (once n) (let loop ((i 0)) (if (< i 1) (loop (+ i 1)) n)))" );
+        eval_all( &environment, "(define (run i) (if (> i 0) (begin (once i) (run (- i 1)))))" );
+        assert_eq!( eval_all( &environment, "(run 50000) (once 'done)" ), "'done" );
+        assert!( Environment::tracked_count() < 10 );
+
+        assert!( eval_all( &environment, "(let ((x 1)))" ).starts_with( "let: bad syntax;" ) );
+        assert!( eval_all( &environment, "(let ((x 1 2)) x)" ).starts_with( "let: bad syntax;" ) );
+        assert!( eval_all( &environment, "(let* (x) x)" ).starts_with( "let*: bad syntax;" ) );
+        assert!( eval_all( &environment, "(letrec ((a 1) (a 2)) a)" ).starts_with( "letrec: bad syntax;" ) );
+        assert!( eval_all( &environment, "(let loop ((i 0)) (loop))" ).starts_with( "loop: arity mismatch;" ) );
+    }
+
+    #[test]
+    fn test_quote_begin() {
+        let environment = Environment::new();
+
+        assert_eq!( eval_all( &environment, "(quote a)" ), "'a" );
+        assert_eq!( eval_all( &environment, "(quote (1 2 (3 b)))" ), "'(1 2 (3 b))" );
+        assert_eq!( eval_all( &environment, "(quote ())" ), "'()" );
+        assert_eq!( eval_all( &environment, "(car (quote (x y)))" ), "'x" );
+        assert_eq!( eval_all( &environment, "(quote (quote a))" ), "''a" );
+        assert_eq!( eval_all( &environment, "'(quote a)" ), "''a" );
+        assert_eq!( eval_all( &environment, "(list 'quote 'a)" ), "''a" );
+        assert_eq!( eval_all( &environment, "'(a 'b)" ), "'(a 'b)" );
+        assert!( eval_all( &environment, "(quote)" ).starts_with( "quote: bad syntax;" ) );
+
+        assert_eq!( eval_all( &environment, "(begin (define z 5) (+ z 1))" ), "6" );
+        assert_eq!( eval_all( &environment, "z" ), "5" );
+        assert_eq!( eval_all( &environment, "(begin)" ), "begin: bad syntax;\n  expected: at least one expression" );
+        assert_eq!( eval_all( &environment, "(do ((i 0 (+ i 1))) ((= i 3)))" ), "" );
+
+        // The last expression of begin is in tail position
+        eval_all( &environment, "(define (count n) (begin (if (= n 0) 'done (count (- n 1)))))" );
+        assert_eq!( eval_all( &environment, "(count 100000)" ), "'done" );
+    }
+
+    #[test]
+    fn test_strings() {
+        let environment = Environment::new();
+        let mut parser  = Parser::new();
+
+        parser.load( r#""a\nb\t\"q\"\\ \x41;\x3bb;" "cont \
+            inued""# );
+        assert_eq!( parser.next().unwrap(), Ok( Data::String( "a\nb\t\"q\"\\ Aλ".into() ) ) );
+        assert_eq!( parser.next().unwrap(), Ok( Data::String( "cont inued".into() ) ) );
+
+        parser.load( r#""\q""# );
+        assert_eq!( parser.next().unwrap(), Err( SchemeError::read_syntax( "unknown escape sequence \\q in string" ) ) );
+        parser.load( r#""\x41""# );
+        assert!( parser.next().unwrap().is_err() );
+
+        assert_eq!( eval_all( &environment, "(string? \"a\")" ), "#t" );
+        assert_eq!( eval_all( &environment, "(string? 'a)" ), "#f" );
+        assert_eq!( eval_all( &environment, r#""a\"b\n""# ), r#""a\"b\n""# );
+        assert_eq!( eval_all( &environment, "'(\"a\" b)" ), "'(\"a\" b)" );
+
+        parser.load( r#"'("a\n" b 'c) "x\ty""# );
+        let list    = environment.eval( &parser.next().unwrap().unwrap() ).unwrap();
+        let string  = environment.eval( &parser.next().unwrap().unwrap() ).unwrap();
+        assert_eq!( list.printed( PrintMode::Write ).to_string(), r#"("a\n" b (quote c))"# );
+        assert_eq!( list.printed( PrintMode::Display ).to_string(), "(a\n b (quote c))" );
+        assert_eq!( string.printed( PrintMode::Write ).to_string(), r#""x\ty""# );
+        assert_eq!( string.printed( PrintMode::Display ).to_string(), "x\ty" );
+
+        assert!( is_input_complete( r#"(display "\"(")"# ) );
+        assert!( !is_input_complete( r#"(display "\")"# ) );
+    }
+
+    #[test]
+    fn test_string_library() {
+        let environment = Environment::new();
+
+        assert_eq!( eval_all( &environment, "(string-length \"héllo\")" ), "5" );
+        assert_eq!( eval_all( &environment, "(string-ref \"abc\" 1)" ), "#\\b" );
+        assert_eq!( eval_all( &environment, "(substring \"hello world\" 6)" ), "\"world\"" );
+        assert_eq!( eval_all( &environment, "(substring \"hello\" 1 3)" ), "\"el\"" );
+        assert_eq!( eval_all( &environment, "(string-append \"a\" \"b\" \"c\")" ), "\"abc\"" );
+        assert_eq!( eval_all( &environment, "(string=? \"a\" \"a\" \"a\")" ), "#t" );
+        assert_eq!( eval_all( &environment, "(string<? \"a\" \"b\" \"c\")" ), "#t" );
+        assert_eq!( eval_all( &environment, "(string<? \"b\" \"a\")" ), "#f" );
+        assert_eq!( eval_all( &environment, "(string-upcase \"Hello\")" ), "\"HELLO\"" );
+        assert_eq!( eval_all( &environment, "(string->list \"a b\")" ), "'(#\\a #\\space #\\b)" );
+        assert_eq!( eval_all( &environment, "(list->string (string->list \"abc\"))" ), "\"abc\"" );
+        assert_eq!( eval_all( &environment, "(string->number \"42\")" ), "42" );
+        assert_eq!( eval_all( &environment, "(string->number \"-3.5\")" ), "-3.5" );
+        assert_eq!( eval_all( &environment, "(string->number \"ff\" 16)" ), "255" );
+        assert_eq!( eval_all( &environment, "(string->number \"inf\")" ), "#f" );
+        assert_eq!( eval_all( &environment, "(number->string -5 2)" ), "\"-101\"" );
+        assert_eq!( eval_all( &environment, "(string->symbol \"foo\")" ), "'foo" );
+        assert_eq!( eval_all( &environment, "(symbol->string 'bar)" ), "\"bar\"" );
+        assert_eq!( eval_all( &environment, "(string->symbol \"hello world\")" ), "'|hello world|" );
+        assert_eq!( eval_all( &environment, "(list (string->symbol \"42\") (string->symbol \"\") (string->symbol \"#t\"))" ), "'(|42| || |#t|)" );
+        assert_eq!( eval_all( &environment, "(symbol->string (string->symbol \"hello world\"))" ), "\"hello world\"" );
+
+        // What write prints between bars reads back as the same symbol
+        assert_eq!( eval_all( &environment, "'|hello world|" ), "'|hello world|" );
+        assert_eq!( eval_all( &environment, "(symbol->string '|hello world|)" ), "\"hello world\"" );
+        assert_eq!( eval_all( &environment, "(define |my var| 5) (+ |my var| 1)" ), "6" );
+        assert_eq!( eval_all( &environment, "'|abc|" ), "'abc" );
+        assert_eq!( eval_all( &environment, "'(|42| || |(a)|)" ), "'(|42| || |(a)|)" );
+        assert_eq!( eval_all( &environment, "(string->symbol \"a|b\")" ), "'|a|\\||b|" );
+        assert_eq!( eval_all( &environment, "(symbol->string '|a|\\||b|)" ), "\"a|b\"" );
+        assert_eq!( eval_all( &environment, "(symbol->string '|\\|)" ), "\"\\\\\"" );
+
+        assert_eq!( eval_all( &environment, "(string-split \"  a b   c \")" ), "'(\"a\" \"b\" \"c\")" );
+        assert_eq!( eval_all( &environment, "(string-split \"a,b,,c\" \",\")" ), "'(\"a\" \"b\" \"\" \"c\")" );
+        assert_eq!( eval_all( &environment, "(string-split \",a,\" \",\")" ), "'(\"a\")" );
+        assert_eq!( eval_all( &environment, "(string-join '(\"a\" \"b\" \"c\") \", \")" ), "\"a, b, c\"" );
+        assert_eq!( eval_all( &environment, "(string-index \"hello\" \"ll\")" ), "2" );
+        assert_eq!( eval_all( &environment, "(string-index \"hello\" \"z\")" ), "#f" );
+
+        assert_eq!( eval_all( &environment, "(string-ref \"abc\" 3)" ), "string-ref: index is out of range\n  index: 3\n  valid range: [0, 2]\n  string: \"abc\"" );
+        assert_eq!( eval_all( &environment, "(string-length 'a)" ), SchemeError::contract_violation( "string-length", "string?", "'a" ).to_string() );
+        assert_eq!( eval_all( &environment, "(list->string '(1 2))" ), SchemeError::contract_violation( "list->string", "(listof char?)", "'(1 2)" ).to_string() );
+        assert_eq!( eval_all( &environment, "(symbol->string \"bar\")" ), SchemeError::contract_violation( "symbol->string", "symbol?", "\"bar\"" ).to_string() );
+    }
+
+    #[test]
+    fn test_chars() {
+        let environment = Environment::new();
+        let mut parser  = Parser::new();
+
+        parser.load( "#\\a #\\space #\\NewLine #\\x41 #\\u3bb #\\λ #\\( #\\x" );
+        for expected in [ 'a', ' ', '\n', 'A', 'λ', 'λ', '(', 'x' ] {
+            assert_eq!( parser.next().unwrap(), Ok( Data::Char( expected ) ) );
         }
 
-        input = "(knapsack 50 3 w p)".to_string();
-        parser.load( input.as_str() );
-        assert_eq!( environment.eval( &parser.next().unwrap() ), Data::from_string( DataType::Real, "6".to_string() ) );
+        parser.load( "#\\foo" );
+        assert_eq!( parser.next().unwrap(), Err( SchemeError::read_syntax( "bad character constant `#\\foo`" ) ) );
+
+        assert_eq!( eval_all( &environment, "(list #\\a #\\space #\\) #\\nul)" ), "'(#\\a #\\space #\\) #\\nul)" );
+        assert_eq!( eval_all( &environment, "(char? #\\a)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(char? \"a\")" ), "#f" );
+        assert_eq!( eval_all( &environment, "(char->integer #\\A)" ), "65" );
+        assert_eq!( eval_all( &environment, "(integer->char 955)" ), "#\\λ" );
+        assert_eq!( eval_all( &environment, "(char-upcase #\\a)" ), "#\\A" );
+        assert_eq!( eval_all( &environment, "(char-downcase #\\A)" ), "#\\a" );
+        assert_eq!( eval_all( &environment, "(char-alphabetic? #\\a)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(char-alphabetic? #\\1)" ), "#f" );
+        assert_eq!( eval_all( &environment, "(char-whitespace? #\\tab)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(char=? #\\a #\\a #\\a)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(char<? #\\a #\\b #\\b)" ), "#f" );
+        assert_eq!( eval_all( &environment, "(char<=? #\\a #\\b #\\b)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(string-index \"hello\" #\\o)" ), "4" );
+
+        assert_eq!( eval_all( &environment, "(char<? #\\a 1)" ), SchemeError::contract_violation( "char<?", "char?", "1" ).to_string() );
+        assert_eq!( eval_all( &environment, "(integer->char 55296)" ), SchemeError::contract_violation( "integer->char", "valid-unicode-scalar-value?", "55296" ).to_string() );
+
+        assert!( is_input_complete( "(list #\\( #\\\")" ) );
+        assert!( !is_input_complete( "(list #\\) #\\a" ) );
     }
+
+    #[test]
+    fn test_bignums() {
+        let environment = Environment::new();
+
+        assert_eq!( eval_all( &environment, "(expt 2 100)" ), "1267650600228229401496703205376" );
+        eval_all( &environment, "(define (fact n) (if (= n 0) 1 (* n (fact (- n 1)))))" );
+        assert_eq!( eval_all( &environment, "(fact 30)" ), "265252859812191058636308480000000" );
+        eval_all( &environment, "(define (fib n) (let loop ((a 0) (b 1) (i 0)) (if (= i n) a (loop b (+ a b) (+ i 1)))))" );
+        assert_eq!( eval_all( &environment, "(fib 100)" ), "354224848179261915075" );
+
+        assert_eq!( eval_all( &environment, "(+ 9223372036854775807 1)" ), "9223372036854775808" );
+        assert_eq!( eval_all( &environment, "(- -9223372036854775808 1)" ), "-9223372036854775809" );
+        assert_eq!( eval_all( &environment, "(- (expt 2 64) (expt 2 64))" ), "0" );
+        assert_eq!( eval_all( &environment, "(quotient (expt 10 30) 7)" ), "142857142857142857142857142857" );
+        assert_eq!( eval_all( &environment, "(remainder (- (expt 10 30)) 7)" ), "-1" );
+        assert_eq!( eval_all( &environment, "(quotient -9223372036854775808 -1)" ), "9223372036854775808" );
+        assert_eq!( eval_all( &environment, "(< (expt 2 64) (expt 2 65) (expt 2 66))" ), "#t" );
+        assert_eq!( eval_all( &environment, "(= (expt 2 64) 18446744073709551616)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(max 1 (expt 2 70) 3)" ), "1180591620717411303424" );
+        assert_eq!( eval_all( &environment, "(number->string (expt 2 70) 16)" ), "\"400000000000000000\"" );
+        assert_eq!( eval_all( &environment, "(number->string 3.5 2)" ), "\"11.1\"" );
+        assert_eq!( eval_all( &environment, "(number->string 3.5 10)" ), "\"3.5\"" );
+        assert_eq!( eval_all( &environment, "(list (number->string -0.5 16) (number->string 8.0 8) (number->string -0.0 2))" ), "'(\"-0.8\" \"10.0\" \"-0.0\")" );
+        assert_eq!( eval_all( &environment, "(number->string 0.1 2)" ), "\"0.0001100110011001100110011001100110011001100110011001101\"" );
+        assert_eq!( eval_all( &environment, "(number->string (/ 1.0 0.0) 2)" ), "\"+inf.0\"" );
+        assert_eq!( eval_all( &environment, "(number->string 1/3 2)" ), "\"1/11\"" );
+        assert_eq!( eval_all( &environment, "(string->number \"123456789012345678901234567890\")" ), "123456789012345678901234567890" );
+
+        assert_eq!( eval_all( &environment, "(/ 1 0)" ), "/: division by zero" );
+        assert_eq!( eval_all( &environment, "(remainder 7 0)" ), "remainder: undefined for 0" );
+
+        // Exact powers never silently become inexact
+        assert_eq!( eval_all( &environment, "(expt 2 (expt 10 10))" ), "expt: out of memory" );
+        assert_eq!( eval_all( &environment, "(list (expt 1 (expt 10 10)) (expt -1 (expt 10 10)) (expt -1 (+ (expt 10 10) 1)) (expt 0 (expt 10 10)))" ), "'(1 1 -1 0)" );
+
+        // Big numbers are compared with inexact ones exactly
+        assert_eq!( eval_all( &environment, "(< (expt 10 400) (exp 1000))" ), "#t" );
+        assert_eq!( eval_all( &environment, "(> (- (expt 10 400)) (- (exp 1000)))" ), "#t" );
+        assert_eq!( eval_all( &environment, "(= (+ (expt 2 64) 1) 18446744073709551616.0)" ), "#f" );
+        assert_eq!( eval_all( &environment, "(= (expt 2 64) 18446744073709551616.0)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(< (expt 10 400) (/ 0.0 0.0))" ), "#f" );
+    }
+
+    #[test]
+    fn test_rationals() {
+        let environment = Environment::new();
+        let mut parser  = Parser::new();
+
+        parser.load( "2/4 -6/4 4/2 1/0" );
+        assert_eq!( parser.next().unwrap().unwrap().to_string(), "1/2" );
+        assert_eq!( parser.next().unwrap().unwrap().to_string(), "-3/2" );
+        assert_eq!( parser.next().unwrap(), Ok( Data::Number( Number::Int( 2 ) ) ) );
+        assert_eq!( parser.next().unwrap(), Err( SchemeError::read_syntax( "division by zero in `1/0`" ) ) );
+
+        assert_eq!( eval_all( &environment, "(/ 1 3)" ), "1/3" );
+        assert_eq!( eval_all( &environment, "(/ 6 3)" ), "2" );
+        assert_eq!( eval_all( &environment, "(/ 2)" ), "1/2" );
+        assert_eq!( eval_all( &environment, "(+ 1/3 1/6)" ), "1/2" );
+        assert_eq!( eval_all( &environment, "(* 2/3 3/2)" ), "1" );
+        assert_eq!( eval_all( &environment, "(- 1/2)" ), "-1/2" );
+        assert_eq!( eval_all( &environment, "(/ 1.0 4)" ), "0.25" );
+        assert_eq!( eval_all( &environment, "'(1/2 3)" ), "'(1/2 3)" );
+        assert_eq!( eval_all( &environment, "(< 1/3 0.34 1/2)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(= 1/2 2/4 0.5)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(max 1/2 1/3)" ), "1/2" );
+        assert_eq!( eval_all( &environment, "(expt 2/3 3)" ), "8/27" );
+        assert_eq!( eval_all( &environment, "(expt 2 -2)" ), "1/4" );
+        assert_eq!( eval_all( &environment, "(number? 1/2)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(integer? 1/2)" ), "#f" );
+        assert_eq!( eval_all( &environment, "(list (real? 1) (real? (expt 2 70)) (real? 1/2) (real? 0.5) (real? \"1\"))" ), "'(#t #t #t #t #f)" );
+        assert!( eval_all( &environment, "(real?)" ).starts_with( "real?: arity mismatch;" ) );
+        assert_eq!( eval_all( &environment, "(string->number \"3/9\")" ), "1/3" );
+
+        assert_eq!( eval_all( &environment, "(numerator 6/4)" ), "3" );
+        assert_eq!( eval_all( &environment, "(denominator 6/4)" ), "2" );
+        assert_eq!( eval_all( &environment, "(denominator 5)" ), "1" );
+        assert_eq!( eval_all( &environment, "(exact->inexact 1/3)" ), "0.3333333333333333" );
+        assert_eq!( eval_all( &environment, "(inexact->exact 0.25)" ), "1/4" );
+        assert_eq!( eval_all( &environment, "(inexact->exact 0.1)" ), "3602879701896397/36028797018963968" );
+
+        assert_eq!( eval_all( &environment, "(expt 0 -1)" ), "/: division by zero" );
+        assert_eq!( eval_all( &environment, "(quotient 1/2 2)" ), SchemeError::contract_violation( "quotient", "integer?", "1/2" ).to_string() );
+    }
+
+    #[test]
+    fn test_numeric_procedures() {
+        let environment = Environment::new();
+
+        assert_eq!( eval_all( &environment, "(* 1.0 2)" ), "2.0" );
+        assert_eq!( eval_all( &environment, "(/ 1 0.0)" ), "+inf.0" );
+        assert_eq!( eval_all( &environment, "(* 1.5 (expt 2 70))" ), "1.770887431076117e+21" );
+        assert_eq!( eval_all( &environment, "0.00000001" ), "1e-08" );
+
+        assert_eq!( eval_all( &environment, "(min 3 1 2)" ), "1" );
+        assert_eq!( eval_all( &environment, "(min 1 2.0)" ), "1.0" );
+        assert_eq!( eval_all( &environment, "(max 1 (/ 0.0 0.0))" ), "+nan.0" );
+        assert_eq!( eval_all( &environment, "(min (/ 0.0 0.0) 1)" ), "+nan.0" );
+        assert_eq!( eval_all( &environment, "(abs -1/2)" ), "1/2" );
+        assert_eq!( eval_all( &environment, "(modulo -7 2)" ), "1" );
+        assert_eq!( eval_all( &environment, "(modulo 7 -2)" ), "-1" );
+        assert_eq!( eval_all( &environment, "(remainder -7 2)" ), "-1" );
+        assert_eq!( eval_all( &environment, "(gcd -12 18 8)" ), "2" );
+        assert_eq!( eval_all( &environment, "(gcd)" ), "0" );
+        assert_eq!( eval_all( &environment, "(lcm 4 6)" ), "12" );
+        assert_eq!( eval_all( &environment, "(lcm 4 6.0)" ), "12.0" );
+        assert_eq!( eval_all( &environment, "(gcd -9223372036854775808)" ), "9223372036854775808" );
+        assert_eq!( eval_all( &environment, "(gcd -9223372036854775808 0)" ), "9223372036854775808" );
+        assert_eq!( eval_all( &environment, "(gcd -9223372036854775808 6)" ), "2" );
+        assert_eq!( eval_all( &environment, "(lcm -9223372036854775808)" ), "9223372036854775808" );
+        assert_eq!( eval_all( &environment, "(lcm -9223372036854775808 3)" ), "27670116110564327424" );
+
+        assert_eq!( eval_all( &environment, "(floor -5/2)" ), "-3" );
+        assert_eq!( eval_all( &environment, "(ceiling 5/2)" ), "3" );
+        assert_eq!( eval_all( &environment, "(round 5/2)" ), "2" );
+        assert_eq!( eval_all( &environment, "(round 7/2)" ), "4" );
+        assert_eq!( eval_all( &environment, "(round 2.5)" ), "2.0" );
+        assert_eq!( eval_all( &environment, "(truncate -5/2)" ), "-2" );
+        assert_eq!( eval_all( &environment, "(floor 2.7)" ), "2.0" );
+
+        assert_eq!( eval_all( &environment, "(sqrt 16)" ), "4" );
+        assert_eq!( eval_all( &environment, "(sqrt 1/4)" ), "1/2" );
+        assert_eq!( eval_all( &environment, "(sqrt 16.0)" ), "4.0" );
+        assert_eq!( eval_all( &environment, "(sqrt 2)" ), "1.4142135623730951" );
+        assert_eq!( eval_all( &environment, "(sqrt (expt 10 40))" ), "100000000000000000000" );
+        assert_eq!( eval_all( &environment, "(exact-integer-sqrt 17)" ), "4\n1" );
+        assert_eq!( eval_all( &environment, "(call-with-values (lambda () (exact-integer-sqrt 17)) list)" ), "'(4 1)" );
+        assert_eq!( eval_all( &environment, "(call-with-values (lambda () (values 1 2 3)) +)" ), "6" );
+        assert_eq!( eval_all( &environment, "(call-with-values (lambda () (values)) list)" ), "'()" );
+        assert_eq!( eval_all( &environment, "(call-with-values (lambda () 5) list)" ), "'(5)" );
+
+        assert_eq!( eval_all( &environment, "(exp 0)" ), "1" );
+        assert_eq!( eval_all( &environment, "(exp 1)" ), "2.718281828459045" );
+        assert_eq!( eval_all( &environment, "(log 1)" ), "0" );
+        assert_eq!( eval_all( &environment, "(log 100 10)" ), "2.0" );
+        assert_eq!( eval_all( &environment, "(sin 0)" ), "0" );
+        assert_eq!( eval_all( &environment, "(cos 0)" ), "1" );
+        assert_eq!( eval_all( &environment, "(atan 1 1)" ), "0.7853981633974483" );
+
+        assert_eq!( eval_all( &environment, "(zero? 0.0)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(positive? 1/2)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(negative? 0)" ), "#f" );
+        assert_eq!( eval_all( &environment, "(odd? 3)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(even? (expt 2 100))" ), "#t" );
+        assert_eq!( eval_all( &environment, "(even? 4.0)" ), "#t" );
+
+        assert_eq!( eval_all( &environment, "(modulo 7 0)" ), "modulo: undefined for 0" );
+        assert_eq!( eval_all( &environment, "(log 0)" ), "log: undefined for 0" );
+        assert_eq!( eval_all( &environment, "(atan 0 0)" ), "atan: division by zero" );
+        assert_eq!( eval_all( &environment, "(odd? 1.5)" ), SchemeError::contract_violation( "odd?", "integer?", "1.5" ).to_string() );
+        assert_eq!( eval_all( &environment, "(sqrt -4)" ), SchemeError::contract_violation( "sqrt", "(>=/c 0)", "-4" ).to_string() );
+    }
+
+    #[test]
+    fn test_values() {
+        let environment = Environment::new();
+
+        assert_eq!( Symbol::new( "abc" ), Symbol::new( "abc" ) );
+        assert_ne!( Symbol::new( "abc" ), Symbol::new( "abd" ) );
+        assert_eq!( eval_all( &environment, "(string->symbol \"abc\")" ), "'abc" );
+        assert_eq!( eval_all( &environment, "(+ 1 2.5)" ), "3.5" );
+        assert_eq!( eval_all( &environment, "'(1 #t (if #f #f) \"s\")" ), "'(1 #t (if #f #f) \"s\")" );
+        assert_eq!( eval_all( &environment, "(list (if #f #f))" ), "'(#<void>)" );
+
+        // A lambda is named after the variable it is defined as
+        assert_eq!( eval_all( &environment, "(define square (lambda (x) (* x x)))" ), "#<procedure:square>" );
+        assert!( eval_all( &environment, "(square)" ).starts_with( "square: arity mismatch;" ) );
+        assert!( eval_all( &environment, "((lambda (x) x))" ).starts_with( "#<procedure>: arity mismatch;" ) );
+
+        eval_all( &environment, "(define xs (list 1 2 3))" );
+        assert_eq!( eval_all( &environment, "(map square xs)" ), "'(1 4 9)" );
+        assert_eq!( eval_all( &environment, "(apply + xs)" ), "6" );
+    }
+
+    #[test]
+    fn test_shared_lists() {
+        let environment = Environment::new();
+        let numbers     : Vec< String > = ( 0..10000 ).map( |i| i.to_string() ).collect();
+
+        eval_all( &environment, format!( "(define big '({}))", numbers.join( " " ) ).as_str() );
+        eval_all( &environment, "(define (sum l acc) (if (null? l) acc (sum (cdr l) (+ acc (car l)))))" );
+
+        // Taking the cdr shares the rest of the list, so walking it is linear
+        assert_eq!( eval_all( &environment, "(let loop ((i 0) (res 0)) (if (= i 10) res (loop (+ i 1) (+ res (sum big 0)))))" ), "499950000" );
+        assert_eq!( eval_all( &environment, "(define rest (cdr (cdr big))) (car rest)" ), "2" );
+        assert_eq!( eval_all( &environment, "(car big)" ), "0" );
+        assert_eq!( eval_all( &environment, "(cdr (cdr (cons 1 (cons 2 3))))" ), "3" );
+        assert_eq!( eval_all( &environment, "(apply + (cdr (list 1 2 3)))" ), "5" );
+        assert_eq!( eval_all( &environment, "(cons 0 (cdr (list 1 2 3)))" ), "'(0 2 3)" );
+    }
+
+    #[test]
+    fn test_pairs() {
+        let environment = Environment::new();
+
+        assert_eq!( eval_all( &environment, "(cons 1 2)" ), "'(1 . 2)" );
+        assert_eq!( eval_all( &environment, "(cons 1 (cons 2 3))" ), "'(1 2 . 3)" );
+        assert_eq!( eval_all( &environment, "'(1 . (2 3))" ), "'(1 2 3)" );
+        assert_eq!( eval_all( &environment, "'((1 . 2) (3 . 4) ())" ), "'((1 . 2) (3 . 4) ())" );
+        assert_eq!( eval_all( &environment, "(cdr '(a . b))" ), "'b" );
+        assert_eq!( eval_all( &environment, "'(1 .5)" ), "'(1 0.5)" );
+        assert_eq!( eval_all( &environment, "'(a . b c)" ), "read-syntax: illegal use of `.`" );
+        assert_eq!( eval_all( &environment, "'( . a)" ), "read-syntax: illegal use of `.`" );
+        assert_eq!( eval_all( &environment, "'(a .)" ), "read-syntax: illegal use of `.`" );
+        assert_eq!( eval_all( &environment, "(+ 1 . 2)" ), "#%app: bad syntax;\n  given: (+ 1 . 2)" );
+
+        // Pairs are shared, so changing one is seen through every list that holds it
+        eval_all( &environment, "(define p (list 1 2 3)) (define q p) (define r (cdr p))" );
+        assert_eq!( eval_all( &environment, "(set-car! r 20) q" ), "'(1 20 3)" );
+        assert_eq!( eval_all( &environment, "(set-cdr! r 30) p" ), "'(1 20 . 30)" );
+        assert_eq!( eval_all( &environment, "(list? p)" ), "#f" );
+
+        eval_all( &environment, "(define c (list 1 2)) (set-cdr! (cdr c) c)" );
+        assert_eq!( eval_all( &environment, "(list? c)" ), "#f" );
+        assert_eq!( eval_all( &environment, "(car (cdr (cdr (cdr c))))" ), "2" );
+
+        // Circular lists are printed with datum labels, so printing them ends
+        assert_eq!( eval_all( &environment, "c" ), "#0='(1 2 . #0#)" );
+        assert_eq!( eval_all( &environment, "(length c)" ), "length: contract violation;\n  expected: list?\n  given: #0='(1 2 . #0#)" );
+        assert_eq!( eval_all( &environment, "(define n (list 1 2)) (set-car! (cdr n) n) n" ), "#0='(1 #0#)" );
+        assert_eq!( eval_all( &environment, "(let ((s (list 1))) (list s s))" ), "'((1) (1))" );
+
+        // Long lists are built and freed without recursion
+        eval_all( &environment, "(define big (let loop ((i 0) (acc '())) (if (= i 100000) acc (loop (+ i 1) (cons i acc)))))" );
+        assert_eq!( eval_all( &environment, "(car big)" ), "99999" );
+        assert_eq!( eval_all( &environment, "(set! big 0) big" ), "0" );
+    }
+
+    #[test]
+    fn test_macros() {
+        let environment = Environment::new();
+
+        eval_all( &environment, "(define-syntax while (syntax-rules () ((_ c body ...) (let lp () (if c (begin body ... (lp)) #f)))))" );
+        assert_eq!( eval_all( &environment, "(define i 0) (while (< i 5) (set! i (+ i 1))) i" ), "5" );
+
+        // Variables introduced by a template neither capture nor shadow the caller's
+        eval_all( &environment, "(define-syntax swap! (syntax-rules () ((_ a b) (let ((tmp a)) (set! a b) (set! b tmp)))))" );
+        assert_eq!( eval_all( &environment, "(define tmp 1) (define other 2) (swap! tmp other) (list tmp other)" ), "'(2 1)" );
+        eval_all( &environment, "(define-syntax my-or (syntax-rules () ((_) #f) ((_ e) e) ((_ e r ...) (let ((t e)) (if t t (my-or r ...))))))" );
+        assert_eq!( eval_all( &environment, "(let ((t 5)) (my-or #f t))" ), "5" );
+        assert_eq!( eval_all( &environment, "(my-or #f #f 7)" ), "7" );
+        eval_all( &environment, "(define-syntax repeat (syntax-rules () ((_ n body) (let lp ((k n)) (if (> k 0) (begin body (lp (- k 1))) 'done)))))" );
+        assert_eq!( eval_all( &environment, "(define k 100) (repeat 3 (set! k (+ k 1))) k" ), "103" );
+
+        // Literals, nested and trailing ellipses, dotted tails and custom ellipses
+        eval_all( &environment, "(define-syntax for (syntax-rules (in) ((_ x in lst body ...) (map (lambda (x) body ...) lst))))" );
+        assert_eq!( eval_all( &environment, "(for x in '(1 2 3) (* x x))" ), "'(1 4 9)" );
+        assert_eq!( eval_all( &environment, "(for x on '(1 2 3) x)" ), "for: bad syntax;\n  given: (for x on (quote (1 2 3)) x)" );
+        assert_eq!( eval_all( &environment, "(define-syntax rotate (syntax-rules () ((_ (a b ...) ...) '((b ... a) ...)))) (rotate (1 2 3) (4 5))" ), "'((2 3 1) (5 4))" );
+        assert_eq!( eval_all( &environment, "(define-syntax flat (syntax-rules () ((_ (a ...) ...) '(a ... ...)))) (flat (1 2) (3) (4 5 6))" ), "'(1 2 3 4 5 6)" );
+        assert_eq!( eval_all( &environment, "(define-syntax last (syntax-rules () ((_ a ... b) 'b))) (last 1 2 3)" ), "3" );
+        assert_eq!( eval_all( &environment, "(define-syntax rest (syntax-rules () ((_ a . r) 'r))) (rest 1 2 3)" ), "'(2 3)" );
+        assert_eq!( eval_all( &environment, "(define-syntax my-if (syntax-rules ::: () ((_ c t e :::) (cond (c t) (else e :::))))) (my-if #f 1 2 3)" ), "3" );
+
+        // Macros that define macros escape their inner ellipses with (... ...)
+        eval_all( &environment, "(define-syntax def-seq (syntax-rules () ((_ name) (define-syntax name (syntax-rules () ((_ e (... ...)) (begin e (... ...))))))))" );
+        assert_eq!( eval_all( &environment, "(def-seq seq) (seq 1 2 3)" ), "3" );
+
+        // Free identifiers in a template refer to the variables where the macro was defined
+        eval_all( &environment, "(define-syntax my-list (syntax-rules () ((_ a ...) (list a ...))))" );
+        assert_eq!( eval_all( &environment, "(let ((list +)) (my-list 1 2))" ), "'(1 2)" );
+        assert_eq!( eval_all( &environment, "(let ((x 1)) (let-syntax ((getx (syntax-rules () ((_) x)))) (let ((x 2)) (getx))))" ), "1" );
+        eval_all( &environment, "(define (helper) 'global) (define-syntax call-helper (syntax-rules () ((_) (helper))))" );
+        assert_eq!( eval_all( &environment, "(let ((helper (lambda () 'local))) (call-helper))" ), "'global" );
+        eval_all( &environment, "(define counter 0) (define-syntax bump! (syntax-rules () ((_) (set! counter (+ counter 1)))))" );
+        assert_eq!( eval_all( &environment, "(let ((counter 10)) (bump!) counter)" ), "10" );
+        assert_eq!( eval_all( &environment, "counter" ), "1" );
+        assert_eq!( eval_all( &environment, "(letrec-syntax ((ev? (syntax-rules () ((_) #t) ((_ x . r) (od? . r)))) (od? (syntax-rules () ((_) #f) ((_ x . r) (ev? . r))))) (ev? 1 2 3))" ), "#f" );
+
+        // Mistakes in the rules are reported when the macro is defined
+        assert_eq!( eval_all( &environment, "(define-syntax bad (syntax-rules () ((_ a a) a)))" ), "syntax-rules: bad syntax;\n  expected: each pattern variable only once\n  given: a" );
+        assert_eq!( eval_all( &environment, "(define-syntax bad (syntax-rules () ((_ a ... b ...) 1)))" ), "syntax-rules: bad syntax;\n  expected: at most one ellipsis, after a pattern\n  given: (a ... b ...)" );
+        assert_eq!( eval_all( &environment, "(define-syntax bad (syntax-rules () ((_ a ...) a)))" ), "syntax-rules: bad syntax;\n  expected: an ellipsis after the pattern variable\n  given: a" );
+        assert_eq!( eval_all( &environment, "(define-syntax bad (syntax-rules () ((_ a) (a ...))))" ), "syntax-rules: bad syntax;\n  expected: a pattern variable before the ellipsis\n  given: a" );
+        assert_eq!( eval_all( &environment, "bad" ), "bad: undefined;\n cannot reference an identifier before its definition" );
+
+        assert_eq!( eval_all( &environment, "(let-syntax ((ten-times (syntax-rules () ((_ x) (* x 10))))) (ten-times 4))" ), "40" );
+        assert_eq!( eval_all( &environment, "(ten-times 4)" ), "ten-times: undefined;\n cannot reference an identifier before its definition" );
+        assert_eq!( eval_all( &environment, "swap!" ), "swap!: bad syntax;" );
+    }
+
+    #[test]
+    fn test_quasiquote() {
+        let environment = Environment::new();
+
+        eval_all( &environment, "(define xs '(b c))" );
+        assert_eq!( eval_all( &environment, "`(1 ,(+ 1 1) ,@(list 3 4))" ), "'(1 2 3 4)" );
+        assert_eq!( eval_all( &environment, "`(a ,xs ,@xs)" ), "'(a (b c) b c)" );
+        assert_eq!( eval_all( &environment, "`(a . ,xs)" ), "'(a b c)" );
+        assert_eq!( eval_all( &environment, "`(1 ,@'() 2)" ), "'(1 2)" );
+        assert_eq!( eval_all( &environment, "`(,@'(1 2) . 3)" ), "'(1 2 . 3)" );
+        assert_eq!( eval_all( &environment, "`(1 '(,(+ 1 1)))" ), "'(1 '(2))" );
+        assert_eq!( eval_all( &environment, "`x `,(+ 2 3)" ), "5" );
+
+        // Only the unquotes of the outermost quasiquote are evaluated
+        assert_eq!( eval_all( &environment, "`(1 `(2 ,(3 ,(+ 1 2))))" ), "'(1 `(2 ,(3 3)))" );
+        assert_eq!( eval_all( &environment, "'`(a ,b ,@c)" ), "'`(a ,b ,@c)" );
+
+        // Templates in macros keep their symbols as data
+        eval_all( &environment, "(define-syntax tagged (syntax-rules () ((_ x) (let ((y 2)) `(tag x ,y ,x)))))" );
+        assert_eq!( eval_all( &environment, "(tagged 5)" ), "'(tag 5 2 5)" );
+        assert_eq!( eval_all( &environment, "(symbol->string (car (tagged 5)))" ), "\"tag\"" );
+
+        assert_eq!( eval_all( &environment, "`(1 ,@5)" ), "unquote-splicing: contract violation;\n  expected: list?\n  given: 5" );
+        assert_eq!( eval_all( &environment, ",x" ), "unquote: not in quasiquote" );
+        assert_eq!( eval_all( &environment, "`,@xs" ), "unquote-splicing: invalid context within quasiquote" );
+        assert_eq!( eval_all( &environment, "`" ), "read-syntax: expected an element for quasi-quoting \"`\" (found end-of-file)" );
+        assert_eq!( eval_all( &environment, "(list ,@" ), "read-syntax: expected an element for unquoting \",@\" (found end-of-file)" );
+
+        assert!( !is_input_complete( "`(a ,@" ) );
+        assert!( !is_input_complete( "`(a ,@b) ," ) );
+        assert!( is_input_complete( "`(a ,@b)" ) );
+    }
+
+    #[test]
+    fn test_continuations() {
+        let environment = Environment::new();
+
+        // Escaping from the middle of a loop
+        eval_all( &environment, "(define (find-negative lst) (call/cc (lambda (return) (map (lambda (x) (if (< x 0) (return x) x)) lst) #f)))" );
+        assert_eq!( eval_all( &environment, "(find-negative '(1 -2 3 -4))" ), "-2" );
+        assert_eq!( eval_all( &environment, "(find-negative '(1 2))" ), "#f" );
+        assert_eq!( eval_all( &environment, "(+ 1 (call-with-current-continuation (lambda (k) (* 10 (k 1)))))" ), "2" );
+
+        // Re-entering a continuation after its call/cc has returned
+        eval_all( &environment, "(define saved #f)" );
+        assert_eq!( eval_all( &environment, "(+ 1 (call/cc (lambda (k) (set! saved k) 1)))" ), "2" );
+        assert_eq!( eval_all( &environment, "(saved 10)" ), "11" );
+        assert_eq!( eval_all( &environment, "(define n 0) (let ((x (call/cc (lambda (k) (set! saved k) 0)))) (set! n (+ n 1)) (if (< x 3) (saved (+ x 1)) (list x n)))" ), "'(3 4)" );
+
+        // A generator that suspends itself between elements
+        eval_all( &environment, "(define (make-gen lst) (define return #f) (define resume #f)
+            (define (walk l) (if (null? l) (return 'done) (begin (call/cc (lambda (k) (set! resume k) (return (car l)))) (walk (cdr l)))))
+            (lambda () (call/cc (lambda (r) (set! return r) (if resume (resume #f) (walk lst))))))" );
+        eval_all( &environment, "(define gen (make-gen '(a b)))" );
+        assert_eq!( eval_all( &environment, "(list (gen) (gen) (gen))" ), "'(a b done)" );
+
+        // dynamic-wind runs its thunks whenever control goes in or out
+        eval_all( &environment, "(define trace '()) (define (note x) (set! trace (cons x trace)))" );
+        assert_eq!( eval_all( &environment, "(dynamic-wind (lambda () (note 'in)) (lambda () (note 'body) 5) (lambda () (note 'out)))" ), "5" );
+        assert_eq!( eval_all( &environment, "(call/cc (lambda (k) (dynamic-wind (lambda () (note 'in)) (lambda () (k 'escaped) (note 'never)) (lambda () (note 'out)))))" ), "'escaped" );
+        assert_eq!( eval_all( &environment, "trace" ), "'(out in out body in)" );
+        eval_all( &environment, "(set! trace '()) (define again #f) (define entered 0)" );
+        eval_all( &environment, "(dynamic-wind (lambda () (note 'in)) (lambda () (call/cc (lambda (k) (set! again k))) (note 'body)) (lambda () (note 'out)))" );
+        eval_all( &environment, "(set! entered (+ entered 1)) (if (< entered 2) (again #f))" );
+        assert_eq!( eval_all( &environment, "trace" ), "'(out body in out body in)" );
+
+        // Non-tail recursion doesn't use the Rust stack
+        eval_all( &environment, "(define (count n) (if (= n 0) 0 (+ 1 (count (- n 1)))))" );
+        assert_eq!( eval_all( &environment, "(count 100000)" ), "100000" );
+
+        assert_eq!( eval_all( &environment, "call/cc" ), "#<procedure:call/cc>" );
+        assert_eq!( eval_all( &environment, "(call/cc (lambda (k) k))" ), "#<continuation>" );
+        assert_eq!( eval_all( &environment, "(call/cc 1)" ), SchemeError::contract_violation( "call-with-current-continuation", "(any/c . -> . any)", "1" ).to_string() );
+        assert!( eval_all( &environment, "(dynamic-wind 1 2)" ).starts_with( "dynamic-wind: arity mismatch;" ) );
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let environment = Environment::new();
+
+        assert_eq!( eval_all( &environment, "(guard (e (#t (list (error-object-message e) (error-object-irritants e)))) (error \"bad thing:\" 1 'x))" ), "'(\"bad thing:\" (1 x))" );
+        assert_eq!( eval_all( &environment, "(guard (e ((number? e) (* e 2)) ((car e) => (lambda (x) (* x 10)))) (raise (list 4)))" ), "40" );
+        assert_eq!( eval_all( &environment, "(guard (e ((string? e) e)) (guard (e ((number? e) e)) (raise \"inner\")))" ), "\"inner\"" );
+        assert_eq!( eval_all( &environment, "(with-exception-handler (lambda (e) 10) (lambda () (+ 1 (raise-continuable 'oops))))" ), "11" );
+        assert_eq!( eval_all( &environment, "(cond ((car '(2)) => (lambda (x) (* x 3))) (else 0))" ), "6" );
+
+        // Built-in errors are raised as error objects
+        assert_eq!( eval_all( &environment, "(guard (e ((error-object? e) (error-object-message e))) (car 1))" ), "\"car: contract violation;\\n  expected: pair?\\n  given: 1\"" );
+        assert_eq!( eval_all( &environment, "(guard (e ((error-object? e) 'arity)) ((lambda (x) x)))" ), "'arity" );
+
+        // The handler of guard runs after leaving the body
+        eval_all( &environment, "(define trace '()) (define (note x) (set! trace (cons x trace)))" );
+        eval_all( &environment, "(guard (e (#t (note 'handled))) (dynamic-wind (lambda () (note 'in)) (lambda () (raise 'x)) (lambda () (note 'out))))" );
+        assert_eq!( eval_all( &environment, "trace" ), "'(handled out in)" );
+
+        // The handlers of with-exception-handler run where the exception was raised
+        assert_eq!( eval_all( &environment, "(with-exception-handler (lambda (e) (* e 2)) (lambda () (with-exception-handler (lambda (e) (+ 1 (raise-continuable e))) (lambda () (raise-continuable 5)))))" ), "11" );
+
+        // A built-in procedure called right away from an argument still raises to the handlers
+        assert_eq!( eval_all( &environment, "(guard (e ((error-object? e) 'caught)) (+ 1 (car 1)))" ), "'caught" );
+
+        // Values that aren't caught are reported like any other error
+        assert_eq!( eval_all( &environment, "(error \"bad thing:\" 1 \"s\")" ), "bad thing: 1 \"s\"" );
+        assert_eq!( eval_all( &environment, "(error 'my-proc \"failed\")" ), "my-proc: failed" );
+        assert_eq!( eval_all( &environment, "(error 'foo \"bad thing ~a\" 1)" ), "foo: bad thing 1" );
+        assert_eq!( eval_all( &environment, "(error 'foo \"~a and ~s, ~v~~\" \"x\" \"y\" 'z)" ), "foo: x and \"y\", 'z~" );
+        assert_eq!( eval_all( &environment, "(guard (e (#t (error-object-message e))) (error 'foo \"got ~a\" '(1 2)))" ), "\"foo: got (1 2)\"" );
+        assert_eq!( eval_all( &environment, "(error \"not formatted ~a\" 1)" ), "not formatted ~a 1" );
+        assert_eq!( eval_all( &environment, "(error 'foo \"~a\")" ), "error: format string requires 1 arguments, given 0" );
+        assert_eq!( eval_all( &environment, "(error 'foo \"x\" 1)" ), "error: format string requires 0 arguments, given 1" );
+        assert_eq!( eval_all( &environment, "(raise 'boom)" ), "uncaught exception: 'boom" );
+        assert_eq!( eval_all( &environment, "(guard (e ((string? e) e)) (car '()))" ), "car: contract violation;\n  expected: pair?\n  given: '()" );
+        assert_eq!( eval_all( &environment, "(with-exception-handler (lambda (e) 0) (lambda () (raise 'oops)))" ), "raise: exception handler returned from a non-continuable exception" );
+        assert_eq!( eval_all( &environment, "(error-object-message 1)" ), SchemeError::contract_violation( "error-object-message", "error-object?", "1" ).to_string() );
+    }
+
+    #[test]
+    fn test_conditional_forms() {
+        let environment = Environment::new();
+
+        assert_eq!( eval_all( &environment, "(and)" ), "#t" );
+        assert_eq!( eval_all( &environment, "(or)" ), "#f" );
+        assert_eq!( eval_all( &environment, "(and 1 2 'c)" ), "'c" );
+        assert_eq!( eval_all( &environment, "(or #f '(a) 3)" ), "'(a)" );
+        assert_eq!( eval_all( &environment, "(and (pair? '()) (car '()))" ), "#f" );
+        assert_eq!( eval_all( &environment, "(or (null? '()) (car '()))" ), "#t" );
+
+        // Expressions are evaluated from left to right, and only until the result is known
+        eval_all( &environment, "(define trace '()) (define (note x) (set! trace (cons x trace)) x)" );
+        assert_eq!( eval_all( &environment, "(and (note 1) (note #f) (note 3))" ), "#f" );
+        assert_eq!( eval_all( &environment, "(or (note #f) (note 2) (note 4))" ), "2" );
+        assert_eq!( eval_all( &environment, "(when (note 'test) (note 'a) (note 'b))" ), "'b" );
+        assert_eq!( eval_all( &environment, "(unless (note 'test) (note 'never))" ), "" );
+        assert_eq!( eval_all( &environment, "trace" ), "'(test b a test 2 #f #f 1)" );
+        assert_eq!( eval_all( &environment, "(unless #f 1 2)" ), "2" );
+
+        assert_eq!( eval_all( &environment, "(case (* 2 3) ((2 3 5 7) 'prime) ((1 4 6 8 9) 'composite))" ), "'composite" );
+        assert_eq!( eval_all( &environment, "(case (car '(c d)) ((a e i o u) 'vowel) ((w y) 'semivowel) (else => (lambda (x) x)))" ), "'c" );
+        assert_eq!( eval_all( &environment, "(case 5 ((5) => (lambda (x) (* x x))))" ), "25" );
+        assert_eq!( eval_all( &environment, "(case #\\a ((#\\a) 'char) (else 'other))" ), "'char" );
+        assert_eq!( eval_all( &environment, "(case 1.0 ((1) 'exact) (else 'inexact))" ), "'inexact" );
+        assert_eq!( eval_all( &environment, "(list (case 10 ((1) 'one)))" ), "'(#<void>)" );
+
+        assert_eq!( eval_all( &environment, "(do ((vec '() (cons i vec)) (i 0 (+ i 1))) ((= i 5) vec))" ), "'(4 3 2 1 0)" );
+        assert_eq!( eval_all( &environment, "(let ((x '(1 3 5 7 9))) (do ((x x (cdr x)) (sum 0 (+ sum (car x)))) ((null? x) sum)))" ), "25" );
+        assert_eq!( eval_all( &environment, "(do ((i 0 (+ i 1)) (acc '())) ((= i 3) acc) (set! acc (cons i acc)))" ), "'(2 1 0)" );
+        assert_eq!( eval_all( &environment, "(do ((i 0 (+ i 1))) ((= i 100000) 'done))" ), "'done" );
+
+        // The last expression of and, or, when and unless is in tail position
+        eval_all( &environment, "(define (count n) (or (= n 0) (and #t (when #t (count (- n 1))))))" );
+        assert_eq!( eval_all( &environment, "(count 100000)" ), "#t" );
+
+        assert!( eval_all( &environment, "(when #t)" ).starts_with( "when: bad syntax;" ) );
+        assert!( eval_all( &environment, "(case 1 (1 2))" ).starts_with( "case: bad syntax;" ) );
+        assert!( eval_all( &environment, "(do ((i 0)))" ).starts_with( "do: bad syntax;" ) );
+        assert!( eval_all( &environment, "(do ((1 0)) (#t))" ).starts_with( "do: bad syntax;" ) );
+        assert!( eval_all( &environment, "(do ((i 0) (i 1)) (#t))" ).starts_with( "do: bad syntax;" ) );
+
+        // Entering a do loop doesn't leave a reference cycle behind
+        eval_all( &environment, "(define (twice) (do ((i 0 (+ i 1))) ((= i 2) i)))" );
+        eval_all( &environment, "(define (run i) (if (> i 0) (begin (twice) (run (- i 1)))))" );
+        assert_eq!( eval_all( &environment, "(run 50000) (twice)" ), "2" );
+        assert!( Environment::tracked_count() < 10 );
+    }
+
+    #[test]
+    fn test_variadic_lambdas() {
+        let environment = Environment::new();
+
+        assert_eq!( eval_all( &environment, "((lambda args args) 1 2 3)" ), "'(1 2 3)" );
+        assert_eq!( eval_all( &environment, "((lambda args args))" ), "'()" );
+        assert_eq!( eval_all( &environment, "((lambda (a b . rest) (list a b rest)) 1 2 3 4)" ), "'(1 2 (3 4))" );
+        assert_eq!( eval_all( &environment, "((lambda (a b . rest) rest) 1 2)" ), "'()" );
+
+        eval_all( &environment, "(define (my-list . xs) xs) (define (sum x . xs) (if (null? xs) x (+ x (apply sum xs))))" );
+        assert_eq!( eval_all( &environment, "(my-list 1 'a \"b\")" ), "'(1 a \"b\")" );
+        assert_eq!( eval_all( &environment, "(sum 1 2 3 4)" ), "10" );
+
+        eval_all( &environment, "(define area (case-lambda ((r) (* 3 r r)) ((w h) (* w h)) ((w h . more) (cons (* w h) more))))" );
+        assert_eq!( eval_all( &environment, "(area 2)" ), "12" );
+        assert_eq!( eval_all( &environment, "(area 2 5)" ), "10" );
+        assert_eq!( eval_all( &environment, "(area 2 5 'cm)" ), "'(10 cm)" );
+        assert_eq!( eval_all( &environment, "area" ), "#<procedure:area>" );
+
+        assert_eq!( eval_all( &environment, "(sum)" ), "sum: arity mismatch;\n the expected number of arguments does not match the given number\n  expected: at least 1\n  given: 0" );
+        assert_eq!( eval_all( &environment, "(area)" ), "area: arity mismatch;\n the expected number of arguments does not match the given number\n  expected: 1 or at least 2\n  given: 0" );
+        assert_eq!( eval_all( &environment, "((case-lambda ((a b c) a) ((a) a) ((a b . c) a) ((a) a) ((a . b) a)) 1 2)" ), "1" );
+        assert_eq!( eval_all( &environment, "((case-lambda ((a b c) a) ((a b . c) a) ((a) a) ((a) a)))" ), "#<procedure>: arity mismatch;\n the expected number of arguments does not match the given number\n  expected: 1 or at least 2\n  given: 0" );
+        assert!( eval_all( &environment, "((case-lambda ((x) x)) 1 2)" ).starts_with( "#<procedure>: arity mismatch;" ) );
+        assert!( eval_all( &environment, "(lambda (a . 1) a)" ).starts_with( "lambda: bad syntax;" ) );
+        assert!( eval_all( &environment, "(case-lambda x)" ).starts_with( "case-lambda: bad syntax;" ) );
+        assert!( eval_all( &environment, "(lambda (a b a) a)" ).starts_with( "lambda: bad syntax;" ) );
+        assert!( eval_all( &environment, "(lambda (a b . a) a)" ).starts_with( "lambda: bad syntax;" ) );
+        assert!( eval_all( &environment, "(define (dup a a) a)" ).starts_with( "define: bad syntax;" ) );
+        assert!( eval_all( &environment, "(case-lambda ((a) a) ((b b) b))" ).starts_with( "case-lambda: bad syntax;" ) );
+    }
+
+    #[test]
+    fn test_higher_order_procedures() {
+        let environment = Environment::new();
+
+        eval_all( &environment, "(define xs '(1 2 3 4)) (define (square x) (* x x))" );
+        assert_eq!( eval_all( &environment, "(map square xs)" ), "'(1 4 9 16)" );
+        assert_eq!( eval_all( &environment, "(map + xs '(10 20 30 40))" ), "'(11 22 33 44)" );
+        assert_eq!( eval_all( &environment, "(map (lambda (f) (f 9)) (list sqrt square))" ), "'(3 81)" );
+        assert_eq!( eval_all( &environment, "((lambda (m) (m car '((a b) (c d)))) map)" ), "'(a c)" );
+        assert_eq!( eval_all( &environment, "(let ((acc '())) (for-each (lambda (x y) (set! acc (cons (- x y) acc))) xs '(4 3 2 1)) acc)" ), "'(3 1 -1 -3)" );
+        assert_eq!( eval_all( &environment, "(filter odd? xs)" ), "'(1 3)" );
+        assert_eq!( eval_all( &environment, "(reduce + 0 xs)" ), "10" );
+        assert_eq!( eval_all( &environment, "(reduce + 0 '())" ), "0" );
+        assert_eq!( eval_all( &environment, "(reduce cons '() xs)" ), "'(4 3 2 . 1)" );
+        assert_eq!( eval_all( &environment, "(fold-left cons '() xs)" ), "'((((() . 1) . 2) . 3) . 4)" );
+        assert_eq!( eval_all( &environment, "(fold-right cons '() xs)" ), "'(1 2 3 4)" );
+        assert_eq!( eval_all( &environment, "(fold-left (lambda (acc x y) (+ acc (* x y))) 0 xs xs)" ), "30" );
+        assert_eq!( eval_all( &environment, "(apply + 1 2 xs)" ), "13" );
+        assert_eq!( eval_all( &environment, "(apply map list '((1 2) (3 4)))" ), "'((1 3) (2 4))" );
+
+        assert_eq!( eval_all( &environment, "(member 2.0 xs)" ), "#f" );
+        assert_eq!( eval_all( &environment, "(member 2.0 xs =)" ), "'(2 3 4)" );
+        assert_eq!( eval_all( &environment, "(member '(b) '(a (b) c))" ), "'((b) c)" );
+        assert_eq!( eval_all( &environment, "(assoc 3 '((1 . a) (3 . b)))" ), "'(3 . b)" );
+        assert_eq!( eval_all( &environment, "(assoc \"B\" '((\"a\" . 1) (\"b\" . 2)) (lambda (x y) (string=? (string-downcase x) y)))" ), "'(\"b\" . 2)" );
+        assert_eq!( eval_all( &environment, "(assoc 5 '((1 . a)) =)" ), "#f" );
+
+        // The procedures are applied by the evaluation loop, so long lists don't grow the Rust stack
+        eval_all( &environment, "(define (range n) (let loop ((i n) (acc '())) (if (= i 0) acc (loop (- i 1) (cons i acc)))))" );
+        assert_eq!( eval_all( &environment, "(fold-left + 0 (map square (filter even? (range 100000))))" ), "166671666700000" );
+
+        assert!( eval_all( &environment, "(map car)" ).starts_with( "map: arity mismatch;" ) );
+        assert_eq!( eval_all( &environment, "(map + '(1) '(1 2))" ), "map: contract violation;\n  expected: all lists must have same size\n  given: 1 and 2" );
+        assert_eq!( eval_all( &environment, "(filter 1 xs)" ), "filter: contract violation;\n  expected: a procedure that can be applied to arguments\n  given: 1" );
+        assert_eq!( eval_all( &environment, "(apply + 1 2)" ), "apply: contract violation;\n  expected: list?\n  given: 2" );
+        assert_eq!( eval_all( &environment, "(assoc 1 '(1 2))" ), "assoc: contract violation;\n  expected: (listof pair?)\n  given: '(1 2)" );
+
+        // A circular list is not a list, and the error shows it with datum labels
+        eval_all( &environment, "(define c (list 1 2)) (set-cdr! (cdr c) c)" );
+        assert_eq!( eval_all( &environment, "(map square c)" ), "map: contract violation;\n  expected: list?\n  given: #0='(1 2 . #0#)" );
+        assert_eq!( eval_all( &environment, "(map + xs c)" ), "map: contract violation;\n  expected: list?\n  given: #0='(1 2 . #0#)" );
+        assert_eq!( eval_all( &environment, "(for-each display c)" ), "for-each: contract violation;\n  expected: list?\n  given: #0='(1 2 . #0#)" );
+        assert_eq!( eval_all( &environment, "(fold-left + 0 c)" ), "fold-left: contract violation;\n  expected: list?\n  given: #0='(1 2 . #0#)" );
+        assert_eq!( eval_all( &environment, "(apply + 1 c)" ), "apply: contract violation;\n  expected: list?\n  given: #0='(1 2 . #0#)" );
+
+        // Circular elements are compared without going around forever
+        eval_all( &environment, "(define d (list 1 2 1 2)) (set-cdr! (cdddr d) d)" );
+        assert_eq!( eval_all( &environment, "(member c (list 3 c))" ), "'(#0=(1 2 . #0#))" );
+        assert_eq!( eval_all( &environment, "(member d (list 3 c))" ), "'(#0=(1 2 . #0#))" );
+        assert_eq!( eval_all( &environment, "(member c (list '(1 2 1 2)))" ), "#f" );
+        assert_eq!( eval_all( &environment, "(assoc c (list (cons 1 'one) (cons d 'found)))" ), "'(#0=(1 2 1 2 . #0#) . found)" );
+    }
+
+    #[test]
+    fn test_list_library() {
+        let environment = Environment::new();
+
+        eval_all( &environment, "(define xs '(1 2 3 4))" );
+        assert_eq!( eval_all( &environment, "(length xs)" ), "4" );
+        assert_eq!( eval_all( &environment, "(length '())" ), "0" );
+        assert_eq!( eval_all( &environment, "(append)" ), "'()" );
+        assert_eq!( eval_all( &environment, "(append '(1) '() '(2 3) 4)" ), "'(1 2 3 . 4)" );
+        assert_eq!( eval_all( &environment, "(let ((ys (list 1))) (set-car! (cdr (append '(0) ys)) 5) ys)" ), "'(5)" );
+        assert_eq!( eval_all( &environment, "(reverse xs)" ), "'(4 3 2 1)" );
+        assert_eq!( eval_all( &environment, "(list-ref xs 2)" ), "3" );
+        assert_eq!( eval_all( &environment, "(list-tail xs 2)" ), "'(3 4)" );
+        assert_eq!( eval_all( &environment, "(list-tail '(1 . 2) 1)" ), "2" );
+        assert_eq!( eval_all( &environment, "(last-pair '(1 2 . 3))" ), "'(2 . 3)" );
+        assert_eq!( eval_all( &environment, "(let* ((ys (list 1 2)) (copy (list-copy ys))) (set-car! copy 0) (list copy ys))" ), "'((0 2) (1 2))" );
+
+        assert_eq!( eval_all( &environment, "(cadr xs)" ), "2" );
+        assert_eq!( eval_all( &environment, "(cddr xs)" ), "'(3 4)" );
+        assert_eq!( eval_all( &environment, "(caddr xs)" ), "3" );
+        assert_eq!( eval_all( &environment, "(caar '((a b) c))" ), "'a" );
+        assert_eq!( eval_all( &environment, "(cdadr '(1 (2 3)))" ), "'(3)" );
+
+        assert_eq!( eval_all( &environment, "(memq 'c '(a b c d))" ), "'(c d)" );
+        assert_eq!( eval_all( &environment, "(memv 1.0 '(1 1.0 2))" ), "'(1.0 2)" );
+        assert_eq!( eval_all( &environment, "(memv '(a) '((a)))" ), "#f" );
+        assert_eq!( eval_all( &environment, "(member '(a) '((a)))" ), "'((a))" );
+        assert_eq!( eval_all( &environment, "(assq 'b '((a 1) (b 2)))" ), "'(b 2)" );
+        assert_eq!( eval_all( &environment, "(assv 2 '((1 . one) (2 . two)))" ), "'(2 . two)" );
+        assert_eq!( eval_all( &environment, "(assv 3 '((1 . one)))" ), "#f" );
+
+        assert_eq!( eval_all( &environment, "(iota 5)" ), "'(0 1 2 3 4)" );
+        assert_eq!( eval_all( &environment, "(iota 3 1)" ), "'(1 2 3)" );
+        assert_eq!( eval_all( &environment, "(iota 3 0 1/2)" ), "'(0 1/2 1)" );
+        assert_eq!( eval_all( &environment, "(iota 0)" ), "'()" );
+        assert_eq!( eval_all( &environment, "(delete 1 '(1 2 1 3))" ), "'(2 3)" );
+        assert_eq!( eval_all( &environment, "(remove 1 '(1 2 1 3))" ), "'(2 1 3)" );
+
+        assert_eq!( eval_all( &environment, "(length '(1 . 2))" ), "length: contract violation;\n  expected: list?\n  given: '(1 . 2)" );
+        assert_eq!( eval_all( &environment, "(append 1 '(2))" ), "append: contract violation;\n  expected: list?\n  given: 1" );
+        assert_eq!( eval_all( &environment, "(list-ref xs 4)" ), "list-ref: index too large for list\n  index: 4\n  in: '(1 2 3 4)" );
+        assert_eq!( eval_all( &environment, "(list-ref '(1 . 2) 1)" ), "list-ref: index reaches a non-pair\n  index: 1\n  in: '(1 . 2)" );
+        assert_eq!( eval_all( &environment, "(list-tail xs -1)" ), "list-tail: contract violation;\n  expected: exact-nonnegative-integer?\n  given: -1" );
+        assert_eq!( eval_all( &environment, "(last-pair '())" ), "last-pair: contract violation;\n  expected: pair?\n  given: '()" );
+        assert_eq!( eval_all( &environment, "(cadr '(1))" ), "cadr: contract violation;\n  expected: (cons/c any/c pair?)\n  given: '(1)" );
+        assert_eq!( eval_all( &environment, "(caddr '(1 2))" ), "caddr: contract violation;\n  expected: (cons/c any/c (cons/c any/c pair?))\n  given: '(1 2)" );
+        assert_eq!( eval_all( &environment, "(caar '(1))" ), "caar: contract violation;\n  expected: (cons/c pair? any/c)\n  given: '(1)" );
+        assert_eq!( eval_all( &environment, "(assq 'a '(a))" ), "assq: contract violation;\n  expected: (listof pair?)\n  given: '(a)" );
+
+        eval_all( &environment, "(define c (list 1 2 3)) (set-cdr! (cddr c) c)" );
+        assert_eq!( eval_all( &environment, "(length c)" ), "length: contract violation;\n  expected: list?\n  given: #0='(1 2 3 . #0#)" );
+        assert_eq!( eval_all( &environment, "(reverse c)" ), "reverse: contract violation;\n  expected: list?\n  given: #0='(1 2 3 . #0#)" );
+        assert_eq!( eval_all( &environment, "(list-copy c)" ), "list-copy: contract violation;\n  expected: list?\n  given: #0='(1 2 3 . #0#)" );
+        assert_eq!( eval_all( &environment, "(last-pair c)" ), "last-pair: contract violation;\n  expected: (and/c pair? (not/c circular?))\n  given: #0='(1 2 3 . #0#)" );
+        assert_eq!( eval_all( &environment, "(let ((d (list 1))) (set-cdr! d d) (last-pair d))" ), "last-pair: contract violation;\n  expected: (and/c pair? (not/c circular?))\n  given: #0='(1 . #0#)" );
+        assert_eq!( eval_all( &environment, "(list-tail c 4)" ), "#0='(2 3 1 . #0#)" );
+        assert_eq!( eval_all( &environment, "(list-ref c 7)" ), "2" );
+        assert_eq!( eval_all( &environment, "(delete c (list 1 c 2 c))" ), "'(1 2)" );
+        assert_eq!( eval_all( &environment, "(remove c (list 1 c 2 c))" ), "'(1 2 #0=(1 2 3 . #0#))" );
+        assert_eq!( eval_all( &environment, "(delete '(1 2 3) (list c))" ), "'(#0=(1 2 3 . #0#))" );
+    }
+
 }
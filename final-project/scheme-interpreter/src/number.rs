@@ -0,0 +1,440 @@
+use std::cmp::Ordering;
+use std::fmt;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{ Num, One, Pow, Signed, ToPrimitive, Zero };
+use crate::interpreter::Data;
+use crate::error::SchemeError;
+
+
+/// The ways of rounding a number to an integer
+pub enum Rounding {
+    Floor,
+    Ceiling,
+    Truncate,
+    Round,      // To the nearest integer, and to the even one on ties
+}
+
+
+/// A number held by a `Data`. Exact integers are
+/// kept in an `i64` while they fit and are promoted to a `BigInt` on overflow.
+/// Exact non-integers are kept as a `BigRational` in lowest terms. The big
+/// values are boxed, so that every value the evaluator moves around stays small
+#[derive( Clone, PartialEq, Debug )]
+pub enum Number {
+    Int( i64 ),
+    Big( Box< BigInt > ),
+    Rational( Box< BigRational > ),
+    Real( f64 ),
+}
+
+
+impl Number {
+
+    pub fn from_data( data: &Data ) -> Option< Number > {
+        match data {
+            Data::Number( num ) => Some( num.clone() ),
+            _                   => None,
+        }
+    }
+
+    /// Parses an exact integer or an `n/d` fraction written in the given radix.
+    /// Fractions are brought to lowest terms, and a zero denominator gives `None`
+    pub fn from_str_radix( string: &str, radix: u32 ) -> Option< Number > {
+        if let Ok( val ) = i64::from_str_radix( string, radix ) {
+            return Some( Number::Int( val ) );
+        }
+
+        match string.split_once( '/' ) {
+            Some( ( numer, denom ) ) => {
+                let numer = BigInt::from_str_radix( numer, radix ).ok()?;
+                let denom = BigInt::from_str_radix( denom, radix ).ok().filter( |denom| denom.is_positive() )?;
+                Some( Number::from_rational( BigRational::new( numer, denom ) ) )
+            },
+            None => BigInt::from_str_radix( string, radix ).ok().map( Number::from_big ),
+        }
+    }
+
+    pub fn into_data( self ) -> Data {
+        Data::Number( self )
+    }
+
+    /// Writes the number in the given radix. An inexact number is written with all
+    /// the digits of its binary value, which always end in radix 2, 8 and 16
+    pub fn to_str_radix( &self, radix: u32 ) -> String {
+        let val = match self {
+            Number::Real( val ) if radix == 10 || !val.is_finite()  => return format_real( *val ),
+            Number::Real( val )                                     => BigRational::from_float( *val ).unwrap(),
+            _                                                       => self.to_rational().unwrap(),
+        };
+
+        if self.is_exact() {
+            return match val.is_integer() {
+                true    => val.numer().to_str_radix( radix ),
+                false   => format!( "{}/{}", val.numer().to_str_radix( radix ), val.denom().to_str_radix( radix ) ),
+            };
+        }
+
+        let sign        = if matches!( self, Number::Real( val ) if val.is_sign_negative() ) { "-" } else { "" };
+        let mut res     = format!( "{}{}.", sign, val.abs().trunc().numer().to_str_radix( radix ) );
+        let mut frac    = val.abs().fract();
+        let radix_val   = BigRational::from_integer( BigInt::from( radix ) );
+        loop {
+            frac       *= &radix_val;
+            let digit   = frac.trunc().numer().to_u32().unwrap();
+            res.push( std::char::from_digit( digit, radix ).unwrap() );
+            frac        = frac.fract();
+            if frac.is_zero() {
+                return res;
+            }
+        }
+    }
+
+    /// Demotes a `BigInt` back to an `i64` when it fits
+    fn from_big( val: BigInt ) -> Number {
+        match val.to_i64() {
+            Some( val ) => Number::Int( val ),
+            None        => Number::Big( Box::new( val ) ),
+        }
+    }
+
+    /// Demotes a fraction with a denominator of 1 to an integer
+    pub fn from_rational( val: BigRational ) -> Number {
+        if val.is_integer() {
+            Number::from_big( val.to_integer() )
+        }
+        else {
+            Number::Rational( Box::new( val ) )
+        }
+    }
+
+    fn to_rational( &self ) -> Option< BigRational > {
+        match self {
+            Number::Int( val )      => Some( BigRational::from_integer( BigInt::from( *val ) ) ),
+            Number::Big( val )      => Some( BigRational::from_integer( ( **val ).clone() ) ),
+            Number::Rational( val ) => Some( ( **val ).clone() ),
+            Number::Real( _ )       => None,
+        }
+    }
+
+    pub fn to_f64( &self ) -> f64 {
+        match self {
+            Number::Int( val )      => *val as f64,
+            Number::Big( val )      => val.to_f64().unwrap_or( f64::NAN ),
+            Number::Rational( val ) => val.to_f64().unwrap_or( f64::NAN ),
+            Number::Real( val )     => *val,
+        }
+    }
+
+    /// `exact->inexact`
+    pub fn to_inexact( &self ) -> Number {
+        Number::Real( self.to_f64() )
+    }
+
+    /// `inexact->exact`, which gives `None` for infinities and NaN
+    pub fn to_exact( &self ) -> Option< Number > {
+        match self {
+            Number::Real( val ) => BigRational::from_float( *val ).map( Number::from_rational ),
+            _                   => Some( self.clone() ),
+        }
+    }
+
+    pub fn is_exact( &self ) -> bool {
+        !matches!( self, Number::Real( _ ) )
+    }
+
+    pub fn is_zero( &self ) -> bool {
+        match self {
+            Number::Int( val )      => *val == 0,
+            Number::Big( val )      => val.is_zero(),
+            Number::Rational( val ) => val.is_zero(),
+            Number::Real( val )     => *val == 0.0,
+        }
+    }
+
+    pub fn is_integer( &self ) -> bool {
+        match self {
+            Number::Int( _ ) | Number::Big( _ ) => true,
+            Number::Rational( _ )               => false,
+            Number::Real( val )                 => val.fract() == 0.0,
+        }
+    }
+
+    pub fn is_negative( &self ) -> bool {
+        self.compare( &Number::Int( 0 ) ) == Some( Ordering::Less )
+    }
+
+    pub fn is_positive( &self ) -> bool {
+        self.compare( &Number::Int( 0 ) ) == Some( Ordering::Greater )
+    }
+
+    /// Only meaningful for integers
+    pub fn is_even( &self ) -> bool {
+        match self {
+            Number::Int( val )      => val % 2 == 0,
+            Number::Big( val )      => val.is_even(),
+            Number::Rational( _ )   => false,
+            Number::Real( val )     => val % 2.0 == 0.0,
+        }
+    }
+
+    pub fn numerator( &self ) -> Number {
+        match self {
+            Number::Rational( val ) => Number::from_big( val.numer().clone() ),
+            Number::Real( val )     => Number::Real( self.to_exact().map_or( *val, |exact| exact.numerator().to_f64() ) ),
+            _                       => self.clone(),
+        }
+    }
+
+    pub fn denominator( &self ) -> Number {
+        match self {
+            Number::Rational( val ) => Number::from_big( val.denom().clone() ),
+            Number::Real( val )     => Number::Real( self.to_exact().map_or( *val, |exact| exact.denominator().to_f64() ) ),
+            _                       => Number::Int( 1 ),
+        }
+    }
+
+    /// Applies an operation exactly when both numbers are exact, trying the
+    /// `i64` version first, and in floating point otherwise
+    fn combine( &self, other: &Number, int_op: fn( i64, i64 ) -> Option< i64 >, exact_op: fn( BigRational, BigRational ) -> BigRational, real_op: fn( f64, f64 ) -> f64 ) -> Number {
+        if let ( Number::Int( lhs ), Number::Int( rhs ) ) = ( self, other ) {
+            if let Some( res ) = int_op( *lhs, *rhs ) {
+                return Number::Int( res );
+            }
+        }
+
+        match ( self.to_rational(), other.to_rational() ) {
+            ( Some( lhs ), Some( rhs ) )    => Number::from_rational( exact_op( lhs, rhs ) ),
+            _                               => Number::Real( real_op( self.to_f64(), other.to_f64() ) ),
+        }
+    }
+
+    pub fn add( &self, other: &Number ) -> Number {
+        self.combine( other, i64::checked_add, |lhs, rhs| lhs + rhs, |lhs, rhs| lhs + rhs )
+    }
+
+    pub fn sub( &self, other: &Number ) -> Number {
+        self.combine( other, i64::checked_sub, |lhs, rhs| lhs - rhs, |lhs, rhs| lhs - rhs )
+    }
+
+    pub fn mul( &self, other: &Number ) -> Number {
+        self.combine( other, i64::checked_mul, |lhs, rhs| lhs * rhs, |lhs, rhs| lhs * rhs )
+    }
+
+    /// Dividing exact numbers gives an exact result. Returns `None` when dividing by an exact zero
+    pub fn div( &self, other: &Number ) -> Option< Number > {
+        if other.is_exact() && other.is_zero() {
+            return None;
+        }
+
+        let int_div = |lhs: i64, rhs: i64| lhs.checked_rem( rhs ).filter( |rem| *rem == 0 ).and( lhs.checked_div( rhs ) );
+        Some( self.combine( other, int_div, |lhs, rhs| lhs / rhs, |lhs, rhs| lhs / rhs ) )
+    }
+
+    /// Integer division truncating towards zero. Returns `None` when dividing by zero
+    pub fn quotient( &self, other: &Number ) -> Option< Number > {
+        if other.is_zero() {
+            return None;
+        }
+
+        Some( self.combine( other, i64::checked_div, |lhs, rhs| ( lhs / rhs ).trunc(), |lhs, rhs| ( lhs / rhs ).trunc() ) )
+    }
+
+    /// The remainder of `quotient`, which has the sign of `self`. Returns `None` when dividing by zero
+    pub fn remainder( &self, other: &Number ) -> Option< Number > {
+        if other.is_zero() {
+            return None;
+        }
+
+        Some( self.combine( other, i64::checked_rem, |lhs, rhs| lhs % rhs, |lhs, rhs| lhs % rhs ) )
+    }
+
+    /// The remainder of flooring division, which has the sign of `other`. Returns `None` when dividing by zero
+    pub fn modulo( &self, other: &Number ) -> Option< Number > {
+        let rem = self.remainder( other )?;
+
+        if !rem.is_zero() && rem.is_negative() != other.is_negative() {
+            Some( rem.add( other ) )
+        }
+        else {
+            Some( rem )
+        }
+    }
+
+    pub fn abs( &self ) -> Number {
+        if self.is_negative() { Number::Int( 0 ).sub( self ) } else { self.clone() }
+    }
+
+    pub fn round( &self, rounding: Rounding ) -> Number {
+        match self {
+            Number::Int( _ ) | Number::Big( _ ) => self.clone(),
+            Number::Rational( val ) => {
+                let res = match rounding {
+                    Rounding::Floor     => val.floor(),
+                    Rounding::Ceiling   => val.ceil(),
+                    Rounding::Truncate  => val.trunc(),
+                    Rounding::Round     => {
+                        let floor   = val.floor();
+                        let half    = BigRational::new( BigInt::one(), BigInt::from( 2 ) );
+                        match ( &**val - &floor ).cmp( &half ) {
+                            Ordering::Less                                      => floor,
+                            Ordering::Equal if floor.to_integer().is_even()    => floor,
+                            _                                                   => floor + BigRational::one(),
+                        }
+                    },
+                };
+                Number::from_rational( res )
+            },
+            Number::Real( val ) => {
+                let res = match rounding {
+                    Rounding::Floor     => val.floor(),
+                    Rounding::Ceiling   => val.ceil(),
+                    Rounding::Truncate  => val.trunc(),
+                    Rounding::Round     => val.round_ties_even(),
+                };
+                Number::Real( res )
+            },
+        }
+    }
+
+    /// Stays exact when both the numerator and the denominator are perfect squares.
+    /// Returns `None` for negative numbers, since complex numbers aren't supported
+    pub fn sqrt( &self ) -> Option< Number > {
+        if self.is_negative() {
+            return None;
+        }
+
+        if let Some( val ) = self.to_rational() {
+            let numer = val.numer().sqrt();
+            let denom = val.denom().sqrt();
+            if &numer * &numer == *val.numer() && &denom * &denom == *val.denom() {
+                return Some( Number::from_rational( BigRational::new( numer, denom ) ) );
+            }
+        }
+
+        Some( Number::Real( self.to_f64().sqrt() ) )
+    }
+
+    /// The largest integer whose square is at most `self`, and the rest.
+    /// Only meaningful for non-negative exact integers
+    pub fn exact_integer_sqrt( &self ) -> ( Number, Number ) {
+        let val     = self.to_rational().unwrap().to_integer();
+        let root    = val.sqrt();
+        let rest    = &val - &root * &root;
+        ( Number::from_big( root ), Number::from_big( rest ) )
+    }
+
+    /// Only meaningful for integers. The result is inexact if either number is
+    pub fn gcd( &self, other: &Number ) -> Number {
+        self.combine( other, gcd_i64, |lhs, rhs| BigRational::from_integer( lhs.to_integer().gcd( &rhs.to_integer() ) ), gcd_f64 )
+    }
+
+    /// Only meaningful for integers. The result is inexact if either number is
+    pub fn lcm( &self, other: &Number ) -> Number {
+        let gcd = self.gcd( other );
+        if gcd.is_zero() {
+            return gcd;
+        }
+
+        self.quotient( &gcd ).unwrap().mul( other ).abs()
+    }
+
+    /// Raising an exact number to an exact integer power stays exact. Fails when raising
+    /// an exact zero to a negative power, or when the exact result is too large to hold
+    pub fn expt( &self, other: &Number ) -> Result< Number, SchemeError > {
+        let ( base, exponent ) = match ( self.to_rational(), other ) {
+            ( Some( base ), Number::Int( val ) )    => ( base, BigInt::from( *val ) ),
+            ( Some( base ), Number::Big( val ) )    => ( base, ( **val ).clone() ),
+            _                                       => return Ok( Number::Real( self.to_f64().powf( other.to_f64() ) ) ),
+        };
+
+        if base.is_zero() && exponent.is_negative() {
+            return Err( SchemeError::DivisionByZero { procedure: "/".to_string() } );
+        }
+
+        let res = match exponent.to_i32() {
+            Some( exponent ) => {
+                let res = base.pow( exponent.unsigned_abs() );
+                if exponent < 0 { res.recip() } else { res }
+            },
+            // Beyond an i32 only the powers of 0, 1 and -1 are small enough to hold
+            None if base.is_zero() || base.abs().is_one() => {
+                if exponent.is_even() { base.abs() } else { base }
+            },
+            None => return Err( SchemeError::OutOfMemory { procedure: "expt".to_string() } ),
+        };
+
+        Ok( Number::from_rational( res ) )
+    }
+
+    /// Compares numbers exactly, like Racket does even when one of them is inexact.
+    /// Only two inexact numbers are compared in floating point
+    pub fn compare( &self, other: &Number ) -> Option< Ordering > {
+        match ( self, other ) {
+            ( Number::Int( lhs ), Number::Int( rhs ) )      => Some( lhs.cmp( rhs ) ),
+            ( Number::Real( lhs ), Number::Real( rhs ) )    => lhs.partial_cmp( rhs ),
+            ( Number::Real( _ ), _ )                        => other.compare( self ).map( Ordering::reverse ),
+            // Integers below 2^53 convert to f64 exactly
+            ( Number::Int( lhs ), Number::Real( rhs ) ) if lhs.unsigned_abs() < 1 << 53 => ( *lhs as f64 ).partial_cmp( rhs ),
+            // Every exact number lies between the infinities, and NaN is unordered
+            ( _, Number::Real( rhs ) ) if !rhs.is_finite()  => 0.0.partial_cmp( rhs ),
+            ( _, Number::Real( rhs ) )                      => Some( self.to_rational()?.cmp( &BigRational::from_float( *rhs )? ) ),
+            _                                               => Some( self.to_rational()?.cmp( &other.to_rational()? ) ),
+        }
+    }
+
+}
+
+
+/// Writes the number the way Racket prints it
+impl fmt::Display for Number {
+    fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+        match self {
+            Number::Int( val )      => write!( f, "{}", val ),
+            Number::Big( val )      => write!( f, "{}", val ),
+            Number::Rational( val ) => write!( f, "{}", val ),
+            Number::Real( val )     => write!( f, "{}", format_real( *val ) ),
+        }
+    }
+}
+
+
+/// Negating `i64::MIN` overflows inside `gcd`, so it is left to the exact version
+fn gcd_i64( lhs: i64, rhs: i64 ) -> Option< i64 > {
+    if lhs == i64::MIN || rhs == i64::MIN { None } else { Some( lhs.gcd( &rhs ) ) }
+}
+
+
+fn gcd_f64( lhs: f64, rhs: f64 ) -> f64 {
+    let ( mut lhs, mut rhs ) = ( lhs.abs(), rhs.abs() );
+    while rhs != 0.0 {
+        let rem = lhs % rhs;
+        lhs     = rhs;
+        rhs     = rem;
+    }
+    lhs
+}
+
+
+/// Writes a floating point number the way Racket does, e.g. `1.0`, `1e+21` or `+inf.0`
+pub fn format_real( val: f64 ) -> String {
+    if val.is_nan() {
+        return "+nan.0".to_string();
+    }
+
+    if val.is_infinite() {
+        return if val > 0.0 { "+inf.0" } else { "-inf.0" }.to_string();
+    }
+
+    let abs = val.abs();
+    if abs != 0.0 && !( 1e-7..1e21 ).contains( &abs ) {
+        let sci                     = format!( "{:e}", val );
+        let ( mantissa, exponent )  = sci.split_once( 'e' ).unwrap();
+        let ( sign, digits )        = if let Some( digits ) = exponent.strip_prefix( '-' ) { ( '-', digits ) } else { ( '+', exponent ) };
+        return format!( "{}e{}{:0>2}", mantissa, sign, digits );
+    }
+
+    let res = val.to_string();
+    if res.contains( '.' ) { res } else { res + ".0" }
+}
@@ -1,23 +1,38 @@
 use crate::interpreter;
+use crate::error::SchemeError;
 use logos::Logos;
 use interpreter::Data;
-use interpreter::DataType;
-use interpreter::NULL_SYM;
+use interpreter::CHAR_NAMES;
+use interpreter::new_list_data;
+use interpreter::new_improper_list_data;
+use crate::number::Number;
+use crate::symbol::Symbol;
 
 #[derive( Logos, Debug, PartialEq )]
 pub enum Token {
     #[regex( r"[\-\+]?[0-9]+", |lex| lex.slice().to_string() )]
     Int( String ),
 
+    #[regex( r"[\-\+]?[0-9]+/[0-9]+", |lex| lex.slice().to_string() )]
+    Rational( String ),
+
     #[regex( r"[\-\+]?([0-9]+\.[0-9]*|[0-9]*\.[0-9]+)", |lex| lex.slice().to_string() )]
     Real( String ),
 
     #[regex( r"(([a-zA-Z\*<=>!\?:\$%_&~\^\+\-\./]+[0-9]*)+|([0-9]+[a-zA-Z\*<=>!\?:\$%_&~\^\+\-\./]+)*|#'*[a-zA-Z]*)", |lex| lex.slice().to_string())]
     Identifier( String ),
 
-    #[regex( "\"[^\"]*\"", |lex| lex.slice().to_string() )]
+    #[regex( r#""([^"\\]|\\(.|\n))*""#, |lex| lex.slice().to_string() )]
     String( String ),
 
+    /// A symbol written between bars, like `|hello world|`. Bars can be chained
+    /// with `\|` between them for a name that has bars in it
+    #[regex( r"\|[^|]*\|(\\\|\|[^|]*\|)*", |lex| unbar_symbol( lex.slice() ) )]
+    BarIdentifier( String ),
+
+    #[token( "#\\", lex_char )]
+    Char( String ),
+
     #[token( "(" )]
     OpenBracket,
 
@@ -27,6 +42,18 @@ pub enum Token {
     #[token( "'" )]
     Quote,
 
+    #[token( "`" )]
+    Quasiquote,
+
+    #[token( "," )]
+    Unquote,
+
+    #[token( ",@" )]
+    UnquoteSplicing,
+
+    #[token( "." )]
+    Dot,
+
     #[regex( r"[ \t\r\n\f]+", logos::skip )]
     Skip,
 
@@ -35,6 +62,23 @@ pub enum Token {
 }
 
 
+/// Reads the rest of a char literal after `#\`: a run of letters and digits
+/// for names and code points, or any single char otherwise
+fn lex_char( lex: &mut logos::Lexer< Token > ) -> Option< String > {
+    let rest    = lex.remainder();
+    let first   = rest.chars().next()?;
+    let len     = if first.is_ascii_alphanumeric() {
+        rest.find( |c: char| !c.is_ascii_alphanumeric() ).unwrap_or( rest.len() )
+    }
+    else {
+        first.len_utf8()
+    };
+
+    lex.bump( len );
+    Some( lex.slice().to_string() )
+}
+
+
 pub struct Parser {
     tokens_arr  : Vec< Token >,
     index       : usize,
@@ -53,41 +97,54 @@ impl Parser {
 
         self.tokens_arr.clear();
         self.index = 0;
-        let mut data = lex.next();
-        while !data.is_none() {
-            self.tokens_arr.push( data.unwrap() );
-            data = lex.next();
+        for token in &mut lex {
+            self.tokens_arr.push( token );
         }
     }
 
 
-    fn parse_next( &mut self, quote_level: u16 ) -> Option< Data > {
-
-        if self.index >= self.tokens_arr.len() {
-            return None;
-        }
+    fn parse_next( &mut self ) -> Result< Data, SchemeError > {
 
         match &self.tokens_arr[ self.index ] {
-            Token::Int( data )          => Some( Data::from_string_quoted( DataType::Integer,   data.to_string(), quote_level ) ),
-            Token::Real( data )         => Some( Data::from_string_quoted( DataType::Real,      data.to_string(), quote_level ) ),
-            Token::Identifier( data )   =>
-                Some(
-                    Data::from_string_quoted(
-                        if quote_level == 0 { DataType::Variable } else { DataType::Symbol }
-                        , data.to_string()
-                        , quote_level
-                    )
-                ),
-            Token::Quote                => { self.index += 1; self.parse_next( quote_level + 1 ) }
-            Token::String( data )       => Some( Data::from_string_quoted( DataType::Symbol,    data.to_string(), quote_level ) ),
-            Token::OpenBracket          => {
+            Token::Int( data ) | Token::Rational( data ) => {
+                // Fractions are read in lowest terms, so 4/2 is the integer 2
+                Number::from_str_radix( data, 10 )
+                    .map( Number::into_data )
+                    .ok_or_else( || SchemeError::read_syntax( format!( "division by zero in `{}`", data ).as_str() ) )
+            },
+            Token::Real( data )         => Ok( Number::Real( data.parse().unwrap() ).into_data() ),
+            Token::Identifier( data )   => {
+                match data.as_str() {
+                    "#t" | "#true"  => Ok( Data::Bool( true ) ),
+                    "#f" | "#false" => Ok( Data::Bool( false ) ),
+                    _               => Ok( Data::Symbol( Symbol::new( data ) ) ),
+                }
+            },
+            Token::Quote | Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing => {
+                // 'x is read as (quote x), `x as (quasiquote x), ,x as (unquote x) and ,@x as (unquote-splicing x)
+                let ( form, prefix, what ) = match self.tokens_arr[ self.index ] {
+                    Token::Quote            => ( "quote"            , "'"   , "quoting" ),
+                    Token::Quasiquote       => ( "quasiquote"       , "`"   , "quasi-quoting" ),
+                    Token::Unquote          => ( "unquote"          , ","   , "unquoting" ),
+                    _                       => ( "unquote-splicing" , ",@"  , "unquoting" ),
+                };
 
-                let mut res_list = Data::new_list();
-                if quote_level > 0 {
-                    res_list.data_type          = DataType::Symbol;
-                    res_list.quote_level    = quote_level;
+                self.index += 1;
+                if self.index >= self.tokens_arr.len() {
+                    return Err( SchemeError::read_syntax( format!( "expected an element for {} \"{}\" (found end-of-file)", what, prefix ).as_str() ) );
                 }
 
+                let datum = self.parse_next()?;
+                Ok( new_list_data( vec![ Data::Symbol( Symbol::new( form ) ), datum ] ) )
+            },
+            Token::BarIdentifier( data ) => Ok( Data::Symbol( Symbol::new( data ) ) ),
+            Token::String( data )       => Ok( Data::String( unescape_string( data )?.into() ) ),
+            Token::Char( data )         => Ok( Data::Char( parse_char( data )? ) ),
+            Token::OpenBracket          => {
+
+                let mut res_list    = vec![];
+                let mut tail        = Data::Null;
+
                 self.index += 1;
 
                 while self.index < self.tokens_arr.len() {
@@ -95,46 +152,44 @@ impl Parser {
                         break;
                     }
 
-                    let res = self.parse_next( quote_level );
-                    if res.is_none() {
-                        return None;
+                    // `(a b . c)` ends with c instead of '(). The dot must follow at least
+                    // one datum and be followed by exactly one more before the `)`
+                    if let Token::Dot = self.tokens_arr[ self.index ] {
+                        self.index += 1;
+                        if res_list.is_empty() || self.index >= self.tokens_arr.len() || self.tokens_arr[ self.index ] == Token::CloseBracket {
+                            return Err( SchemeError::read_syntax( "illegal use of `.`" ) );
+                        }
+
+                        tail        = self.parse_next()?;
+                        self.index += 1;
+                        if self.index < self.tokens_arr.len() && self.tokens_arr[ self.index ] != Token::CloseBracket {
+                            return Err( SchemeError::read_syntax( "illegal use of `.`" ) );
+                        }
+                        break;
                     }
-                    res_list.list.push( res.unwrap() );
+
+                    let res = self.parse_next()?;
+                    res_list.push( res );
 
                     if self.index >= self.tokens_arr.len() {
-                        return None;
+                        break;
                     }
 
                     self.index += 1;
                 }
 
                 if self.index == self.tokens_arr.len() /*|| let Token::CloseBracket(_) = self.fTokens[ self.fIndex ]*/ {
-                    print_error( Error::ReadSyntax, "expected a `)` to close `(`" );
-                    return None;
-                }
-
-                if quote_level == 1 && res_list.list.is_empty() {
-                    return Some( NULL_SYM );
-                }
-
-                if let DataType::Symbol = res_list.data_type {
-                    if !res_list.list.is_empty() {
-                        res_list.list.push( NULL_SYM );
-                    }
+                    return Err( SchemeError::read_syntax( "expected a `)` to close `(`" ) );
                 }
 
-                Some( res_list )
+                Ok( new_improper_list_data( res_list, tail ) )
             },
 
-            Token::CloseBracket         => {
-                print_error( Error::ReadSyntax, "unexpected `)`" );
-                None
-            },
+            Token::CloseBracket         => Err( SchemeError::read_syntax( "unexpected `)`" ) ),
 
-            Token::Error                => {
-                print_error( Error::Unknown, "" );
-                None
-            },
+            Token::Dot                  => Err( SchemeError::read_syntax( "illegal use of `.`" ) ),
+
+            Token::Error                => Err( SchemeError::UnknownToken ),
 
             Token::Skip                 => {
                 unreachable!();
@@ -146,28 +201,166 @@ impl Parser {
 }
 
 
-impl Iterator for Parser {
+/// The name of a symbol written between bars, e.g. `|a|\||b|` is `a|b`
+fn unbar_symbol( literal: &str ) -> String {
+    let mut res     = String::new();
+    let mut rest    = literal;
 
-    type Item = Data;
-    
-    fn next( &mut self ) -> Option< Data > {
-        let res = self.parse_next( 0 );
-        self.index += 1;
-        res
+    loop {
+        let end = rest[ 1.. ].find( '|' ).unwrap() + 1;
+        res    += &rest[ 1..end ];
+        rest    = match rest[ end + 1.. ].strip_prefix( "\\|" ) {
+            Some( rest )    => rest,
+            None            => return res,
+        };
+        res.push( '|' );
     }
+}
 
+
+/// How a symbol that isn't a plain identifier is written so it reads back: between
+/// bars, and split into several barred parts where the name itself has a bar
+pub fn bar_symbol( name: &str ) -> String {
+    name.split( '|' ).map( |part| format!( "|{}|", part ) ).collect::< Vec< String > >().join( "\\|" )
 }
 
 
-enum Error {
-    Unknown,
-    ReadSyntax,
+/// Turns a string literal, quotes included, into the string it denotes
+fn unescape_string( literal: &str ) -> Result< String, SchemeError > {
+    let mut res     = String::new();
+    let mut chars   = literal[ 1..literal.len() - 1 ].chars();
+
+    while let Some( ch ) = chars.next() {
+        if ch != '\\' {
+            res.push( ch );
+            continue;
+        }
+
+        match chars.next() {
+            Some( 'n' )     => res.push( '\n' ),
+            Some( 't' )     => res.push( '\t' ),
+            Some( 'r' )     => res.push( '\r' ),
+            Some( 'a' )     => res.push( '\x07' ),
+            Some( 'b' )     => res.push( '\x08' ),
+            Some( '0' )     => res.push( '\0' ),
+            Some( '"' )     => res.push( '"' ),
+            Some( '\\' )    => res.push( '\\' ),
+            Some( '|' )     => res.push( '|' ),
+            Some( 'x' )     => {
+                // \x<hex digits>; names a character by its code point
+                let rest        = chars.as_str();
+                let hex         = rest.split( ';' ).next().unwrap();
+                let code        = u32::from_str_radix( hex, 16 ).ok().and_then( char::from_u32 );
+
+                match code {
+                    Some( code ) if hex.len() < rest.len() => {
+                        res.push( code );
+                        chars = rest[ hex.len() + 1.. ].chars();
+                    },
+                    _ => return Err( SchemeError::read_syntax( format!( "bad or incomplete hex escape `\\x{}` in string", hex ).as_str() ) ),
+                }
+            },
+            Some( '\n' )    => {
+                // A backslash before a line break continues the string on the next line
+                // without the break or the next line's leading whitespace
+                let rest    = chars.as_str().trim_start_matches( [ ' ', '\t' ] );
+                chars       = rest.chars();
+            },
+            Some( other )   => return Err( SchemeError::read_syntax( format!( "unknown escape sequence \\{} in string", other ).as_str() ) ),
+            None            => unreachable!(),
+        }
+    }
+
+    Ok( res )
+}
+
+
+/// Reads a char literal: `#\a`, a name like `#\space`, or a code point like `#\x41` or `#\u03bb`
+fn parse_char( literal: &str ) -> Result< char, SchemeError > {
+    let body = &literal[ 2.. ];
+
+    let mut chars = body.chars();
+    if let ( Some( ch ), None ) = ( chars.next(), chars.next() ) {
+        return Ok( ch );
+    }
+
+    if let Some( ( _, ch ) ) = CHAR_NAMES.iter().find( |( name, _ )| name.eq_ignore_ascii_case( body ) ) {
+        return Ok( *ch );
+    }
+
+    let code = body.strip_prefix( |c| c == 'x' || c == 'u' )
+        .and_then( |hex| u32::from_str_radix( hex, 16 ).ok() )
+        .and_then( char::from_u32 );
+
+    code.ok_or_else( || SchemeError::read_syntax( format!( "bad character constant `{}`", literal ).as_str() ) )
+}
+
+
+/// Checks whether the input holds only complete data, i.e. every `(` is closed,
+/// every string and barred symbol is terminated and no `'`, `` ` ``, `,` or `,@` is left waiting for its datum
+pub fn is_input_complete( input: &str ) -> bool {
+    let mut depth           = 0;
+    let mut in_string       = false;
+    let mut in_bars         = false;
+    let mut escaped         = false;
+    let mut pending_quote   = false;
+
+    let mut chars = input.chars();
+    while let Some( ch ) = chars.next() {
+        if in_string {
+            in_string   = escaped || ch != '"';
+            escaped     = !escaped && ch == '\\';
+            continue;
+        }
+
+        if in_bars {
+            in_bars = ch != '|';
+            continue;
+        }
+
+        let is_prefix = matches!( ch, '\'' | '`' | ',' );
+        if !ch.is_whitespace() && !is_prefix {
+            pending_quote = false;
+        }
+
+        match ch {
+            '"'     => in_string = true,
+            '|'     => in_bars = true,
+            '#' if chars.as_str().starts_with( '\\' ) => { chars.nth( 1 ); },   // The char after #\ is never a delimiter
+            ',' if chars.as_str().starts_with( '@' ) => { chars.next(); pending_quote = true; },
+            '('     => depth += 1,
+            ')'     => depth -= 1,
+            _ if is_prefix => pending_quote = true,
+            _       => {},
+        }
+    }
+
+    // An unmatched `)` can't be fixed by reading more, so let the parser report it
+    !in_string && !in_bars && !pending_quote && depth <= 0
+}
+
+
+/// Checks whether the name of a symbol reads back as that symbol, so it can be printed without bars
+pub fn is_plain_identifier( name: &str ) -> bool {
+    let mut lex = Token::lexer( name );
+    matches!( lex.next(), Some( Token::Identifier( _ ) ) )
+        && lex.span() == ( 0..name.len() )
+        && !matches!( name, "#t" | "#true" | "#f" | "#false" )
 }
 
 
-fn print_error( err: Error, text: &str ) {
-    match err {
-        Error::ReadSyntax   => println!( "read-syntax: {}", text ),
-        _                   => println!( "an unknown error occured while parsing..." ),
+impl Iterator for Parser {
+
+    type Item = Result< Data, SchemeError >;
+    
+    fn next( &mut self ) -> Option< Self::Item > {
+        if self.index >= self.tokens_arr.len() {
+            return None;
+        }
+
+        let res = self.parse_next();
+        self.index = if res.is_ok() { self.index + 1 } else { self.tokens_arr.len() };   // Skip the rest of the input after an error
+        Some( res )
     }
+
 }
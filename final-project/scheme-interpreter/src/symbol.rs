@@ -0,0 +1,127 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{ BuildHasherDefault, Hash, Hasher };
+use std::rc::{ Rc, Weak };
+use crate::interpreter::Environment;
+
+thread_local! {
+    /// All interned symbols created so far, by name
+    static SYMBOLS: RefCell< HashMap< Rc< str >, Symbol > > = RefCell::new( HashMap::new() );
+}
+
+
+/// A symbol. All interned symbols with the same name are one object,
+/// so they are compared and hashed by pointer instead of by their text
+#[derive( Clone )]
+pub struct Symbol( Rc< Name > );
+
+
+struct Name {
+    text    : Rc< str >,
+    /// For an identifier that a macro renamed, the identifier it was renamed from
+    /// and the environment the macro was defined in, where that one is looked up
+    alias   : Option< ( Symbol, Weak< Environment > ) >,
+}
+
+
+impl Symbol {
+
+    pub fn new( name: &str ) -> Symbol {
+        SYMBOLS.with( |symbols| {
+            let mut symbols = symbols.borrow_mut();
+            if let Some( interned ) = symbols.get( name ) {
+                return interned.clone();
+            }
+
+            let text: Rc< str >   = Rc::from( name );
+            let interned            = Symbol( Rc::new( Name { text: Rc::clone( &text ), alias: None } ) );
+            symbols.insert( text, interned.clone() );
+            interned
+        } )
+    }
+
+    pub fn as_str( &self ) -> &str {
+        &self.0.text
+    }
+
+    /// Creates a symbol with the given name that is different from every other
+    /// symbol, including the interned one
+    pub fn fresh( name: &str ) -> Symbol {
+        Symbol( Rc::new( Name { text: Rc::from( name ), alias: None } ) )
+    }
+
+    /// Creates a fresh symbol with the same name that refers to this one as seen from `env`.
+    /// Macros rename the identifiers they introduce to these, with the environment they were defined in
+    pub fn renamed( &self, env: &Weak< Environment > ) -> Symbol {
+        Symbol( Rc::new( Name { text: Rc::clone( &self.0.text ), alias: Some( ( self.clone(), Weak::clone( env ) ) ) } ) )
+    }
+
+    /// The identifier that a renamed one refers to and the environment to look it up in,
+    /// or `None` for symbols that weren't renamed
+    pub fn alias( &self ) -> Option< ( Symbol, Rc< Environment > ) > {
+        let ( original, env ) = self.0.alias.as_ref()?;
+        Some( ( original.clone(), env.upgrade()? ) )
+    }
+
+}
+
+
+impl PartialEq for Symbol {
+    fn eq( &self, other: &Symbol ) -> bool {
+        Rc::ptr_eq( &self.0, &other.0 )
+    }
+}
+
+
+impl Eq for Symbol {}
+
+
+impl Hash for Symbol {
+    fn hash< H: Hasher >( &self, state: &mut H ) {
+        ( Rc::as_ptr( &self.0 ) as usize ).hash( state );
+    }
+}
+
+
+/// Hashes symbols by the address of their name, which is already unique,
+/// so maps keyed by symbols don't need a general purpose hash function
+#[derive( Default )]
+pub struct SymbolHasher( u64 );
+
+
+impl Hasher for SymbolHasher {
+
+    fn finish( &self ) -> u64 {
+        self.0
+    }
+
+    fn write( &mut self, bytes: &[ u8 ] ) {
+        for byte in bytes {
+            self.0 = ( self.0 << 8 ) | u64::from( *byte );
+        }
+    }
+
+    fn write_usize( &mut self, val: usize ) {
+        // The low bits of an address are always zero because of alignment
+        self.0 = ( val as u64 ) >> 3;
+    }
+
+}
+
+
+pub type BuildSymbolHasher = BuildHasherDefault< SymbolHasher >;
+
+
+impl fmt::Display for Symbol {
+    fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+        write!( f, "{}", self.as_str() )
+    }
+}
+
+
+impl fmt::Debug for Symbol {
+    fn fmt( &self, f: &mut fmt::Formatter<'_> ) -> fmt::Result {
+        write!( f, "{}", self.as_str() )
+    }
+}